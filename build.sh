@@ -1,7 +1,33 @@
 set -e
 
+# Guest slots in the GUESTS flash region (see pico2-quick/memory.x)
+SLOT0=0x10100000
+SLOT1=0x10180000
+
+# The firmware only runs guests signed with this key; its public half is
+# pico2-quick/guest-signing.pub, embedded when the firmware is built
+KEY=keys/guest-signing.key
+[ -f $KEY ] || cargo run -p compiler -- keygen --key $KEY
+
 cargo build -p temperature-sensor --target wasm32-unknown-unknown --release
-wasm-tools component new target/wasm32-unknown-unknown/release/temperature_sensor.wasm -o pacman.wasm
-cargo run -p compiler -- unknown
+cargo run -p compiler -- compile target/wasm32-unknown-unknown/release/temperature_sensor.wasm --key $KEY -o target/guest.img
+
+# Display guest: the pacman app with the OLED driver plugged into its graphics import
+# (ball-screensaver.wasm works the same way)
+cargo build -p pacman -p pmod-oled-driver --target wasm32-unknown-unknown --release
+cargo run -p compiler -- compile target/wasm32-unknown-unknown/release/pacman.wasm \
+    --plug target/wasm32-unknown-unknown/release/pmod_oled_driver.wasm --key $KEY -o target/display.img
+
+# Guests are flashed on their own, so they can be updated without rebuilding the host.
+# Once the firmware runs, a guest can also be replaced over UART0 without a probe:
+#   stty -F /dev/ttyUSB0 115200 raw -echo
+#   cargo run -p compiler -- upload target/display.img /dev/ttyUSB0 --slot 1
+# Both guests also fit in one slot as a bundle, with their devices and limits from bundle.toml:
+#   cargo run -p compiler -- bundle bundle.toml --key $KEY -o target/bundle.img
+# Adding --compress to compile or bundle takes less flash, but the host then
+# inflates each guest into its heap instead of running it in place.
+probe-rs download --chip RP235x --binary-format bin --base-address $SLOT0 target/guest.img
+probe-rs download --chip RP235x --binary-format bin --base-address $SLOT1 target/display.img
+
 cd pico2-quick
 cargo run --release
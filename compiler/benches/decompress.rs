@@ -0,0 +1,82 @@
+//! How long inflating a compressed guest takes, on the machine it runs on.
+//! Precompiles each component given on the command line (or a generated one)
+//! for the Pico 2's engine settings, compresses it like `compile --compress`
+//! and times `guest_compress::decompress`.
+//!
+//! Linux only gives a lower bound: the RP2350 is a 150 MHz Cortex-M33
+//! reading the stream from XIP flash, so expect it to be a lot slower.
+
+use std::hint::black_box;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use engine_config::PICO2;
+use wasmtime::Engine;
+
+const ITERATIONS: u32 = 50;
+
+// Enough functions to be the size of a small real guest
+fn generated() -> Vec<u8> {
+    let mut functions = String::new();
+    for i in 0..400 {
+        functions.push_str(&format!(
+            "(func $f{i} (param i32) (result i32)
+                local.get 0 i32.const {i} i32.add i32.const 3 i32.mul
+                local.get 0 i32.const {i} i32.xor i32.sub)\n"
+        ));
+    }
+    wat::parse_str(format!(
+        r#"(component
+            (core module $m {functions} (func (export "run")))
+            (core instance $i (instantiate $m))
+            (func (export "run") (canon lift (core func $i "run")))
+        )"#
+    ))
+    .unwrap()
+}
+
+fn bench(engine: &Engine, name: &str, component: &[u8]) {
+    let serialized = engine.precompile_component(component).unwrap();
+
+    let start = Instant::now();
+    let compressed = guest_compress::compress(&serialized);
+    let compress_time = start.elapsed();
+
+    let mut total = Duration::ZERO;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        let inflated = guest_compress::decompress(black_box(&compressed), usize::MAX).unwrap();
+        total += start.elapsed();
+        assert_eq!(inflated.len(), serialized.len());
+    }
+    let per_run = total / ITERATIONS;
+
+    println!(
+        "{name}: {} -> {} bytes ({}%), compress {:.1?}, decompress {:.1?} ({:.0} MB/s)",
+        serialized.len(),
+        compressed.len(),
+        compressed.len() * 100 / serialized.len(),
+        compress_time,
+        per_run,
+        serialized.len() as f64 / per_run.as_secs_f64() / 1e6
+    );
+}
+
+fn main() {
+    let engine = PICO2
+        .config()
+        .and_then(|config| Engine::new(&config))
+        .unwrap();
+    // `cargo bench` passes `--bench`
+    let paths: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect();
+    if paths.is_empty() {
+        bench(&engine, "generated", &generated());
+    }
+    for path in &paths {
+        let component = wat::parse_file(Path::new(path)).unwrap();
+        bench(&engine, path, &component);
+    }
+}
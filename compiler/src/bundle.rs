@@ -0,0 +1,108 @@
+//! The TOML file `compiler bundle` reads, one `[[guest]]` table per guest:
+//!
+//! ```toml
+//! [[guest]]
+//! name = "pacman"
+//! component = "../target/wasm32-unknown-unknown/release/pacman.wasm"
+//! plugs = ["../target/wasm32-unknown-unknown/release/pmod_oled_driver.wasm"]
+//! gpio = ["DC", "RES", "VBATC", "VDDC"]
+//! spi = ["display"]
+//! # Optional: export = "my:app/run", memory = 131072, fuel = 100000, autostart = true
+//! ```
+//!
+//! Paths are relative to the file. The interfaces each guest needs from the
+//! host are not listed, they are read from the component.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use guest_bundle::{EntryManifest, Limits};
+use serde::Deserialize;
+
+use crate::Failure;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BundleManifest {
+    #[serde(rename = "guest")]
+    pub guests: Vec<GuestSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuestSpec {
+    pub name: String,
+    pub component: PathBuf,
+    #[serde(default)]
+    pub plugs: Vec<PathBuf>,
+    #[serde(default = "default_export")]
+    pub export: String,
+    // Bytes per linear memory, capped by the host's own limit; the bundle
+    // records it as a u32, so anything larger is refused when parsing
+    pub memory: Option<u32>,
+    // Fuel per slice between yields to the host
+    pub fuel: Option<u32>,
+    #[serde(default)]
+    pub gpio: Vec<String>,
+    #[serde(default)]
+    pub spi: Vec<String>,
+    #[serde(default = "default_autostart")]
+    pub autostart: bool,
+}
+
+fn default_export() -> String {
+    "my:app/run".to_string()
+}
+
+fn default_autostart() -> bool {
+    true
+}
+
+// The bundle writer takes `&[&str]`s
+pub struct Lists<'a> {
+    interfaces: Vec<&'a str>,
+    gpio: Vec<&'a str>,
+    spi: Vec<&'a str>,
+}
+
+impl GuestSpec {
+    /// `imports` are what the guest left for the host once composed.
+    pub fn lists<'a>(&'a self, imports: &'a [String]) -> Lists<'a> {
+        let strs = |names: &'a [String]| names.iter().map(String::as_str).collect();
+        Lists {
+            interfaces: strs(imports),
+            gpio: strs(&self.gpio),
+            spi: strs(&self.spi),
+        }
+    }
+
+    pub fn entry_manifest<'a>(&'a self, lists: &'a Lists<'a>) -> EntryManifest<'a> {
+        EntryManifest {
+            name: &self.name,
+            export: &self.export,
+            interfaces: &lists.interfaces,
+            limits: Limits {
+                memory_bytes: self.memory.unwrap_or(0),
+                fuel_slice: self.fuel.unwrap_or(0),
+            },
+            gpio: &lists.gpio,
+            spi: &lists.spi,
+            autostart: self.autostart,
+        }
+    }
+}
+
+pub fn load(path: &Path) -> Result<BundleManifest, Failure> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| Failure::Io(anyhow::anyhow!("{}: {}", path.display(), err)))?;
+    let mut manifest: BundleManifest = toml::from_str(&text)
+        .map_err(|err| Failure::Compile(anyhow::anyhow!("{}: {}", path.display(), err)))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for guest in &mut manifest.guests {
+        guest.component = dir.join(&guest.component);
+        for plug in &mut guest.plugs {
+            *plug = dir.join(&*plug);
+        }
+    }
+    Ok(manifest)
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+
+use anyhow::Context;
+use semver::Version;
+
+/// The manifest pico2-quick ships, used unless `--host` names another.
+pub const PICO2_MANIFEST: &str = include_str!("../../pico2-quick/host-capabilities.txt");
+
+/// What a host links for its guests: one interface name per line, such as
+/// `wasi:spi/spi` or `wasi:cli/environment@0.2.0`, with `#` comments.
+///
+/// Names are all it holds, so an import naming a provided interface passes
+/// even if its functions or their types differ from the host's WIT.
+pub struct Manifest {
+    interfaces: Vec<String>,
+}
+
+/// An import the host cannot satisfy.
+#[derive(Debug)]
+pub enum Unsatisfied {
+    Unknown(String),
+    Version { import: String, host: String },
+}
+
+impl Manifest {
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut interfaces = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some((_, version)) = line.split_once('@') {
+                Version::parse(version).with_context(|| format!("line {}: `{line}`", i + 1))?;
+            }
+            interfaces.push(line.to_string());
+        }
+        Ok(Self { interfaces })
+    }
+
+    /// Matches imports the way Wasmtime's linker resolves them: exact names,
+    /// or semver-compatible versions of the same interface (`@0.2.0` is
+    /// satisfied by `@0.2.3`, `@1.0.0` by `@1.4.0`).
+    pub fn check(&self, imports: &[String]) -> Vec<Unsatisfied> {
+        imports
+            .iter()
+            .filter_map(|import| self.check_one(import))
+            .collect()
+    }
+
+    fn check_one(&self, import: &str) -> Option<Unsatisfied> {
+        if self.interfaces.iter().any(|provided| provided == import) {
+            return None;
+        }
+        let (base, _) = split_version(import);
+        let key = compat_key(import);
+        let mut mismatch = None;
+        for provided in self
+            .interfaces
+            .iter()
+            .filter(|provided| split_version(provided).0 == base)
+        {
+            if key.is_some() && compat_key(provided) == key {
+                return None;
+            }
+            mismatch.get_or_insert(provided);
+        }
+        Some(match mismatch {
+            Some(host) => Unsatisfied::Version {
+                import: import.to_string(),
+                host: host.clone(),
+            },
+            None => Unsatisfied::Unknown(import.to_string()),
+        })
+    }
+}
+
+fn split_version(name: &str) -> (&str, Option<&str>) {
+    match name.split_once('@') {
+        Some((base, version)) => (base, Some(version)),
+        None => (name, None),
+    }
+}
+
+// Wasmtime's "alternate lookup key": the part of the version that must match
+fn compat_key(name: &str) -> Option<(String, u64, Option<u64>)> {
+    let (base, version) = split_version(name);
+    let version = Version::parse(version?).ok()?;
+    if !version.pre.is_empty() {
+        return None;
+    }
+    match (version.major, version.minor) {
+        (0, 0) => None,
+        (0, minor) => Some((base.to_string(), 0, Some(minor))),
+        (major, _) => Some((base.to_string(), major, None)),
+    }
+}
+
+/// The readable diff printed when a guest is refused.
+pub struct MissingImports<'a> {
+    pub unsatisfied: &'a [Unsatisfied],
+    pub manifest: &'a Manifest,
+}
+
+impl fmt::Display for MissingImports<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "guest imports what the host does not provide:")?;
+        for problem in self.unsatisfied {
+            match problem {
+                Unsatisfied::Unknown(import) => writeln!(f, "  - {import}  (unknown to the host)")?,
+                Unsatisfied::Version { import, host } => {
+                    writeln!(f, "  - {import}  (host has {host})")?
+                }
+            }
+        }
+        writeln!(f, "host provides:")?;
+        for interface in &self.manifest.interfaces {
+            writeln!(f, "  + {interface}")?;
+        }
+        write!(
+            f,
+            "plug a component exporting the missing interfaces with --plug, or rebuild the guest without them"
+        )
+    }
+}
@@ -0,0 +1,57 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use wit_component::ComponentEncoder;
+
+/// An adapter module for `--adapt`, e.g. the WASI preview1 adapter that lets
+/// a `wasm32-wasip1` module run against preview2 imports.
+#[derive(Clone, Debug)]
+pub struct Adapter {
+    /// The core import module the adapter replaces.
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// Parses `name=path`, or just `path` with the name taken from the file stem
+/// (`wasi_snapshot_preview1.reactor.wasm` adapts `wasi_snapshot_preview1`),
+/// the same way `wasm-tools component new --adapt` does.
+pub fn parse_adapter(arg: &str) -> Result<Adapter, String> {
+    if let Some((name, path)) = arg.split_once('=') {
+        return Ok(Adapter {
+            name: name.to_string(),
+            path: PathBuf::from(path),
+        });
+    }
+    let path = PathBuf::from(arg);
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('.').next())
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| format!("cannot derive an adapter name from `{arg}`, use name=path"))?;
+    Ok(Adapter {
+        name: name.to_string(),
+        path,
+    })
+}
+
+/// Wraps a core module into a component using the WIT world that
+/// wit-bindgen embedded in its `component-type` custom section.
+pub fn componentize(module: &[u8], adapters: &[Adapter]) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = ComponentEncoder::default()
+        .validate(true)
+        .module(module)
+        .context("reading the core module's component-type section")?;
+    for adapter in adapters {
+        let bytes = read_adapter(&adapter.path)?;
+        encoder = encoder
+            .adapter(&adapter.name, &bytes)
+            .with_context(|| format!("adapter `{}`", adapter.name))?;
+    }
+    // Fails on imports no WIT world describes, e.g. a module built without wit-bindgen
+    encoder.encode().context("componentizing core module")
+}
+
+fn read_adapter(path: &Path) -> anyhow::Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("reading adapter {}", path.display()))
+}
@@ -0,0 +1,48 @@
+use anyhow::Context;
+use wac_graph::types::Package;
+use wac_graph::{CompositionGraph, EncodeOptions};
+use wasmparser::{Parser, Payload};
+
+/// Plugs the exports of every plug into the matching imports of `socket`,
+/// as `wac plug` does, and returns the composed component. Plugs may import
+/// from each other's exports only through the socket, so order does not matter.
+pub fn compose(socket: Vec<u8>, plugs: Vec<Vec<u8>>) -> anyhow::Result<Vec<u8>> {
+    let mut graph = CompositionGraph::new();
+    let socket = Package::from_bytes("app:socket", None, socket, graph.types_mut())
+        .context("loading the app component")?;
+    let socket = graph.register_package(socket)?;
+
+    let mut packages = Vec::with_capacity(plugs.len());
+    for (i, plug) in plugs.into_iter().enumerate() {
+        let name = format!("plug:plug{i}");
+        let package = Package::from_bytes(&name, None, plug, graph.types_mut())
+            .with_context(|| format!("loading plug {i}"))?;
+        packages.push(graph.register_package(package)?);
+    }
+
+    wac_graph::plug(&mut graph, packages, socket)?;
+    graph
+        .encode(EncodeOptions::default())
+        .context("encoding the composed component")
+}
+
+/// Names of the top-level imports of a component, i.e. everything the host
+/// has to provide for it to instantiate.
+pub fn imports(component: &[u8]) -> anyhow::Result<Vec<String>> {
+    let mut names = Vec::new();
+    // Nested modules and components have imports of their own, only the outermost ones matter
+    let mut depth = 0;
+    for payload in Parser::new(0).parse_all(component) {
+        match payload? {
+            Payload::ModuleSection { .. } | Payload::ComponentSection { .. } => depth += 1,
+            Payload::End(_) => depth -= 1,
+            Payload::ComponentImportSection(reader) if depth == 0 => {
+                for import in reader {
+                    names.push(import?.name.0.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(names)
+}
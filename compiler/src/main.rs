@@ -1,59 +1,701 @@
-use std::path::Path;
-use std::{env, fs};
-use wasmtime::{Config, Engine};
-
-fn main() -> anyhow::Result<()> {
-    println!("Compiling guest for Pulley...");
-    let mode = env::args().nth(1).unwrap_or_else(|| "unknown".to_string());
-
-    let input_path = match mode.as_str() {
-        "p2" => Path::new("target/wasm32-wasip2/release/guest.wasm"),
-        "unknown" => Path::new("pacman.wasm"),
-        _ => anyhow::bail!("Invalid mode '{}'. Use: p2 | unknown", mode),
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::{fmt, fs};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use engine_config::{EngineSettings, PICO2};
+use guest_image::SigningKey;
+use upload::IoTransport;
+use wasmtime::Engine;
+
+use capabilities::{Manifest, MissingImports, PICO2_MANIFEST};
+use componentize::{componentize, parse_adapter, Adapter};
+use compose::compose;
+use report::{Budget, Report};
+
+mod bundle;
+mod capabilities;
+mod componentize;
+mod compose;
+mod report;
+
+/// Precompiles guest components for the Pico 2 host and uploads them to it.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Precompile a component into a flash image for one guest slot.
+    Compile(CompileArgs),
+    /// Precompile several guests into one image, with a manifest the host
+    /// reads to start them and hand out their devices.
+    Bundle(BundleArgs),
+    /// Send an image to the firmware's UART receiver, replacing the guest in `slot`.
+    ///
+    /// The serial device must already be in raw mode at the firmware's baud
+    /// rate, e.g. `stty -F /dev/ttyUSB0 115200 raw -echo`.
+    Upload {
+        image: PathBuf,
+        device: PathBuf,
+        #[arg(long, default_value_t = 0)]
+        slot: u8,
+    },
+    /// Generate the Ed25519 key pair guests are signed with. The firmware
+    /// embeds the public half, so rebuild it afterwards.
+    Keygen {
+        #[arg(long, default_value = "keys/guest-signing.key")]
+        key: PathBuf,
+        #[arg(long, default_value = "pico2-quick/guest-signing.pub")]
+        public: PathBuf,
+        /// Replace an existing key; images signed with it stop verifying.
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(clap::Args)]
+struct CompileArgs {
+    /// Component to precompile (binary or text format), or a core module
+    /// built with wit-bindgen, which is componentized first.
+    input: PathBuf,
+    #[arg(short, long, default_value = "target/guest.img")]
+    output: PathBuf,
+    /// Recorded in the image header so the host can log which build it runs.
+    #[arg(long = "image-version", default_value_t = 0)]
+    image_version: u32,
+    #[command(flatten)]
+    engine: EngineArgs,
+    /// Component (or core module) whose exports satisfy the app's imports,
+    /// e.g. a device driver; repeatable.
+    #[arg(long = "plug", value_name = "PATH")]
+    plugs: Vec<PathBuf>,
+    /// Adapter for a core module's imports, as `name=path` or `path`; repeatable.
+    #[arg(long = "adapt", value_name = "[NAME=]PATH", value_parser = parse_adapter)]
+    adapters: Vec<Adapter>,
+    #[command(flatten)]
+    checks: CheckArgs,
+    /// Ed25519 key to sign the image with, see `keygen`; the host refuses unsigned images.
+    #[arg(long, value_name = "PATH")]
+    key: Option<PathBuf>,
+    /// DEFLATE-compress the precompiled component. It takes less flash, but
+    /// the host inflates it into the heap instead of running it in place.
+    #[arg(long)]
+    compress: bool,
+    /// Print the engine settings and size breakdown.
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+#[derive(clap::Args)]
+struct BundleArgs {
+    /// TOML file with a `[[guest]]` table per guest: name, component, plugs,
+    /// export, memory, fuel, gpio, spi and autostart.
+    manifest: PathBuf,
+    #[arg(short, long, default_value = "target/bundle.img")]
+    output: PathBuf,
+    /// Recorded in the image header so the host can log which build it runs.
+    #[arg(long = "image-version", default_value_t = 0)]
+    image_version: u32,
+    #[command(flatten)]
+    engine: EngineArgs,
+    #[command(flatten)]
+    checks: CheckArgs,
+    /// Ed25519 key to sign the image with, see `keygen`; the host refuses unsigned images.
+    #[arg(long, value_name = "PATH")]
+    key: Option<PathBuf>,
+    /// DEFLATE-compress the precompiled component. It takes less flash, but
+    /// the host inflates it into the heap instead of running it in place.
+    #[arg(long)]
+    compress: bool,
+    /// Print the engine settings and size breakdown.
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+// Engine settings, defaulting to the Pico 2 host's
+#[derive(clap::Args)]
+struct EngineArgs {
+    /// Pulley flavour; the Pico 2 runs `pulley32`, `pulley64` runs on 64-bit Linux hosts.
+    #[arg(long, value_enum, default_value_t = Target::Pulley32)]
+    target: Target,
+    /// Wasm stack limit in bytes [default: the host's].
+    #[arg(long)]
+    max_wasm_stack: Option<usize>,
+    /// Leave out fuel checks; the host meters guests with fuel and will refuse the image.
+    #[arg(long)]
+    no_fuel: bool,
+    /// Compile for synchronous calls only; the host will refuse the image.
+    #[arg(long)]
+    no_async: bool,
+}
+
+// What a guest is checked against before it is written out
+#[derive(clap::Args)]
+struct CheckArgs {
+    /// Host capability manifest to check the guest's imports against
+    /// [default: pico2-quick/host-capabilities.txt, built in]. Only interface
+    /// names and versions are checked, not the functions in them or their
+    /// signatures; a guest built against other WIT still fails when the host
+    /// instantiates it.
+    #[arg(long, value_name = "PATH")]
+    host: Option<PathBuf>,
+    /// Do not check imports against a host manifest.
+    #[arg(long, conflicts_with = "host")]
+    skip_host_check: bool,
+    /// Largest image allowed, in bytes [default: one guest slot].
+    #[arg(long, value_name = "BYTES")]
+    flash_budget: Option<usize>,
+    /// Heap the guest may need to instantiate, in bytes [default: the
+    /// firmware heap split evenly across the guest slots].
+    #[arg(long, value_name = "BYTES")]
+    heap_budget: Option<usize>,
+    /// Bytes any one linear memory may start with [default: the firmware's per-memory limit].
+    #[arg(long, value_name = "BYTES")]
+    memory_budget: Option<usize>,
+}
+
+impl CheckArgs {
+    fn budget(&self) -> Budget {
+        Budget {
+            flash: self.flash_budget.unwrap_or(guest_image::SLOT_SIZE),
+            heap: self
+                .heap_budget
+                .unwrap_or(engine_config::HEAP_SIZE / guest_image::SLOT_COUNT),
+            memory: self
+                .memory_budget
+                .unwrap_or(engine_config::GUEST_MEMORY_BYTES),
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Target {
+    Pulley32,
+    Pulley64,
+}
+
+impl Target {
+    fn triple(self) -> &'static str {
+        match self {
+            Target::Pulley32 => "pulley32",
+            Target::Pulley64 => "pulley64",
+        }
+    }
+
+    fn pointer_size(self) -> usize {
+        match self {
+            Target::Pulley32 => 4,
+            Target::Pulley64 => 8,
+        }
+    }
+}
+
+/// Why a command failed; each kind has its own exit code so scripts can
+/// tell a broken guest from a missing file. Usage errors exit with 2.
+#[derive(Debug)]
+enum Failure {
+    Io(anyhow::Error),
+    Compile(anyhow::Error),
+    // One line per exceeded budget
+    OverBudget(Vec<String>),
+    Upload(anyhow::Error),
+    // Rendered `capabilities::MissingImports`
+    Unsatisfied(String),
+}
+
+impl Failure {
+    fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            Failure::Io(_) => 3,
+            Failure::Compile(_) => 4,
+            Failure::OverBudget(_) => 5,
+            Failure::Upload(_) => 6,
+            Failure::Unsatisfied(_) => 7,
+        })
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Io(err) | Failure::Compile(err) | Failure::Upload(err) => {
+                write!(f, "{err:#}")
+            }
+            Failure::OverBudget(over) => write!(f, "over budget: {}", over.join("; ")),
+            Failure::Unsatisfied(report) => f.write_str(report),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Compile(args) => compile(&args),
+        Command::Bundle(args) => bundle(&args),
+        Command::Upload {
+            image,
+            device,
+            slot,
+        } => upload_image(&image, &device, slot),
+        Command::Keygen { key, public, force } => keygen(&key, &public, force),
     };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            eprintln!("error: {failure}");
+            failure.exit_code()
+        }
+    }
+}
+
+fn compile(args: &CompileArgs) -> Result<(), Failure> {
+    let (settings, engine) = engine_for(&args.engine, args.verbose)?;
+    let mut guest = build_guest(
+        &engine,
+        &args.input,
+        &args.plugs,
+        &args.adapters,
+        &args.checks,
+        args.verbose,
+    )?;
+    if args.compress {
+        guest.compress();
+    }
 
-    // 1. Configure Engine to EXACTLY match the Pico 2 Host capabilities
-    let mut config = Config::new();
-    config.target("pulley32")?;
+    // 6. Wrap in a flash image for one of the firmware's guest slots, signed if there is a key
+    let fingerprint = settings.fingerprint();
+    let image = write_image(
+        args.image_version,
+        fingerprint,
+        guest.stored(),
+        args.key.as_deref(),
+    )?;
 
-    // --- Features: Must match what is enabled/disabled in Host Cargo.toml ---
-    config.wasm_component_model(true);
-    config.async_support(false);
+    // Checked before writing, so an image that cannot run never reaches the device
+    let report = Report {
+        image_bytes: image.len(),
+        ..guest.report(&args.engine, &settings)?
+    };
+    println!(
+        "Budget report:\n{}",
+        report::Display {
+            report: &report,
+            budget: &args.checks.budget(),
+        }
+    );
+    let over = report.over_budget(&args.checks.budget());
+    if !over.is_empty() {
+        return Err(Failure::OverBudget(over));
+    }
 
-    // Disable GC features (Proposal + Support)
-    config.wasm_gc(false);
-    config.wasm_function_references(false);
-    config.gc_support(false);
+    // 7. Output
+    write_output(&args.output, &image)?;
+    println!(
+        "Wrote {} bytes (version {}, {}, engine {:#010x}, {}) to {}",
+        image.len(),
+        args.image_version,
+        settings.target,
+        fingerprint,
+        signed(args.key.as_deref()),
+        args.output.display()
+    );
+    Ok(())
+}
 
-    // --- Runtime/Memory: Must match Host config initialization ---
-    config.signals_based_traps(false); // No OS signals on Pico
-    config.memory_init_cow(false); // Match CoW setting (Copy-on-Write)
-    config.memory_guard_size(0); // No virtual memory guard pages
-    config.memory_reservation(0); // No address space reservation
-    config.max_wasm_stack(32 * 1024); // Match stack size limit
+// Each guest goes through the same steps as `compile`, then they are
+// bundled into one image with the manifest the host reads
+fn bundle(args: &BundleArgs) -> Result<(), Failure> {
+    let manifest = bundle::load(&args.manifest)?;
+    let (settings, engine) = engine_for(&args.engine, args.verbose)?;
 
-    let engine = Engine::new(&config)?;
+    let mut guests = Vec::with_capacity(manifest.guests.len());
+    let mut over = Vec::new();
+    for spec in &manifest.guests {
+        println!("Guest {}:", spec.name);
+        let mut guest = build_guest(
+            &engine,
+            &spec.component,
+            &spec.plugs,
+            &[],
+            &args.checks,
+            args.verbose,
+        )?;
+        if args.compress {
+            guest.compress();
+        }
+        // A manifest limit is what the host will enforce, so it is what the guest has to fit
+        let budget = Budget {
+            memory: spec.memory.map_or(args.checks.budget().memory, |memory| {
+                (memory as usize).min(args.checks.budget().memory)
+            }),
+            ..args.checks.budget()
+        };
+        let report = guest.report(&args.engine, &settings)?;
+        println!(
+            "{}",
+            report::Display {
+                report: &report,
+                budget: &budget,
+            }
+        );
+        over.extend(
+            report
+                .over_budget(&budget)
+                .into_iter()
+                .map(|line| format!("{}: {}", spec.name, line)),
+        );
+        guests.push(guest);
+    }
 
-    println!("Reading component from: {:?}", input_path);
-    let wasm_bytes = fs::read(input_path)?;
+    let lists: Vec<_> = manifest
+        .guests
+        .iter()
+        .zip(&guests)
+        .map(|(spec, guest)| spec.lists(&guest.imports))
+        .collect();
+    let entries: Vec<_> = manifest
+        .guests
+        .iter()
+        .zip(&guests)
+        .zip(&lists)
+        .map(|((spec, guest), lists)| (spec.entry_manifest(lists), guest.stored()))
+        .collect();
+    let payload = guest_bundle::write_bundle(&entries)
+        .map_err(|err| Failure::Compile(anyhow::anyhow!("{}: {}", args.manifest.display(), err)))?;
 
-    //println!("Componentizing module...");
-    //let component_bytes = ComponentEncoder::default()
-    //    .validate(true)
-    //    .module(&wasm_bytes)?
-    //    .encode()?;
+    let fingerprint = settings.fingerprint();
+    let image = write_image(
+        args.image_version,
+        fingerprint,
+        &payload,
+        args.key.as_deref(),
+    )?;
+    let flash = args.checks.budget().flash;
+    println!(
+        "Bundle: {} bytes, {}% of {}",
+        image.len(),
+        image.len() * 100 / flash.max(1),
+        flash
+    );
+    if image.len() > flash {
+        over.push(format!(
+            "bundle is {} bytes, the flash budget is {}",
+            image.len(),
+            flash
+        ));
+    }
+    if !over.is_empty() {
+        return Err(Failure::OverBudget(over));
+    }
 
-    // 3. Precompile
-    let serialized = engine.precompile_component(&wasm_bytes)?;
+    write_output(&args.output, &image)?;
+    println!(
+        "Wrote {} guests in {} bytes (version {}, {}, engine {:#010x}, {}) to {}",
+        guests.len(),
+        image.len(),
+        args.image_version,
+        settings.target,
+        fingerprint,
+        signed(args.key.as_deref()),
+        args.output.display()
+    );
+    Ok(())
+}
+
+// 1. The engine settings the Pico 2 host runs with, unless overridden
+fn engine_for(args: &EngineArgs, verbose: bool) -> Result<(EngineSettings, Engine), Failure> {
+    let settings = EngineSettings {
+        target: args.target.triple(),
+        max_wasm_stack: args.max_wasm_stack.unwrap_or(PICO2.max_wasm_stack),
+        consume_fuel: !args.no_fuel,
+        async_support: !args.no_async,
+        ..PICO2
+    };
+    // The fingerprint lets the host refuse an image built against other settings
+    let fingerprint = settings.fingerprint();
+    if verbose {
+        println!("Engine settings: {settings:#?}");
+        println!("Engine fingerprint: {fingerprint:#010x}");
+        if fingerprint != engine_config::FINGERPRINT {
+            println!("Note: these differ from the Pico 2 host's, it will refuse this image");
+        }
+    }
+    let engine = settings
+        .config()
+        .and_then(|config| Engine::new(&config))
+        .map_err(Failure::Compile)?;
+    Ok((settings, engine))
+}
+
+// One guest, precompiled and checked against the host's capabilities
+struct Guest {
+    component: Vec<u8>,
+    serialized: Vec<u8>,
+    // What goes into the image in place of `serialized`, see `--compress`
+    compressed: Option<Vec<u8>>,
+    // Left for the host's linker after plugging
+    imports: Vec<String>,
+}
+
+impl Guest {
+    fn compress(&mut self) {
+        self.compressed = Some(guest_compress::compress(&self.serialized));
+    }
+
+    fn stored(&self) -> &[u8] {
+        self.compressed.as_deref().unwrap_or(&self.serialized)
+    }
+
+    // `image_bytes` is the stored size, until the caller knows the image's
+    fn report(&self, engine: &EngineArgs, settings: &EngineSettings) -> Result<Report, Failure> {
+        Ok(Report {
+            image_bytes: self.stored().len(),
+            serialized_bytes: self.serialized.len(),
+            compressed_bytes: self.compressed.as_ref().map(Vec::len),
+            modules: report::analyze(&self.component).map_err(Failure::Compile)?,
+            pointer_size: engine.target.pointer_size(),
+            async_stack_size: settings.async_stack_size,
+        })
+    }
+}
+
+fn build_guest(
+    engine: &Engine,
+    input: &Path,
+    plug_paths: &[PathBuf],
+    adapters: &[Adapter],
+    checks: &CheckArgs,
+    verbose: bool,
+) -> Result<Guest, Failure> {
+    // 2. Read the app and its plugs. Cargo's wasm32 output is a core module,
+    // wrapped here so no wasm-tools step is needed
+    let mut any_core = false;
+    let app = load_component(input, adapters, verbose, &mut any_core)?;
+    let mut plugs = Vec::with_capacity(plug_paths.len());
+    for plug in plug_paths {
+        plugs.push(load_component(plug, adapters, verbose, &mut any_core)?);
+    }
+    if !adapters.is_empty() && !any_core {
+        return Err(Failure::Compile(anyhow::anyhow!(
+            "--adapt only applies to core modules, and every input is a component"
+        )));
+    }
+
+    // 3. Plug the drivers' exports into the app's imports, leaving one component
+    let component = if plugs.is_empty() {
+        app
+    } else {
+        let composed = compose(app, plugs)
+            .map_err(|err| Failure::Compile(err.context("composing components")))?;
+        if verbose {
+            println!(
+                "Composed {} with {} plug(s) ({} bytes)",
+                input.display(),
+                plug_paths.len(),
+                composed.len()
+            );
+        }
+        composed
+    };
+    // 4. Whatever no plug satisfied has to come from the host's linker, so a
+    // guest it cannot instantiate is refused here rather than on the device
+    let imports = compose::imports(&component).map_err(Failure::Compile)?;
+    if !plug_paths.is_empty() || verbose {
+        println!("Imports left for the host: {}", list(&imports));
+    }
+    if !checks.skip_host_check {
+        let manifest = load_manifest(checks.host.as_deref())?;
+        let unsatisfied = manifest.check(&imports);
+        if !unsatisfied.is_empty() {
+            let missing = MissingImports {
+                unsatisfied: &unsatisfied,
+                manifest: &manifest,
+            };
+            return Err(Failure::Unsatisfied(missing.to_string()));
+        }
+    }
+
+    // 5. Precompile
+    let serialized = engine
+        .precompile_component(&component)
+        .map_err(|err| Failure::Compile(err.context("precompiling")))?;
+    Ok(Guest {
+        component,
+        serialized,
+        compressed: None,
+        imports,
+    })
+}
+
+// Signed when there is a key, the host only runs signed images
+fn write_image(
+    version: u32,
+    engine: u32,
+    payload: &[u8],
+    key: Option<&Path>,
+) -> Result<Vec<u8>, Failure> {
+    Ok(match key {
+        Some(path) => {
+            guest_image::write_signed_image(version, engine, payload, &load_signing_key(path)?)
+        }
+        None => guest_image::write_image(version, engine, payload),
+    })
+}
+
+fn signed(key: Option<&Path>) -> &'static str {
+    match key {
+        Some(_) => "signed",
+        None => "unsigned, the Pico 2 host will refuse it",
+    }
+}
+
+fn load_signing_key(path: &Path) -> Result<SigningKey, Failure> {
+    let bytes = fs::read(path)
+        .map_err(|err| Failure::Io(anyhow::anyhow!("{}: {}", path.display(), err)))?;
+    let seed = bytes.try_into().map_err(|bytes: Vec<u8>| {
+        Failure::Compile(anyhow::anyhow!(
+            "{}: a signing key is 32 bytes, this file has {}",
+            path.display(),
+            bytes.len()
+        ))
+    })?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+fn keygen(key_path: &Path, public_path: &Path, force: bool) -> Result<(), Failure> {
+    let mut seed = [0; 32];
+    getrandom::fill(&mut seed).map_err(|err| Failure::Io(anyhow::anyhow!("{err}")))?;
+    let key = SigningKey::from_bytes(&seed);
+
+    // The secret half is only readable by its owner, and not silently replaced
+    if let Some(parent) = key_path.parent() {
+        fs::create_dir_all(parent).map_err(|err| Failure::Io(err.into()))?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(key_path)
+        .and_then(|mut file| file.write_all(&seed))
+        .map_err(|err| {
+            let hint = match err.kind() {
+                ErrorKind::AlreadyExists => ", --force replaces it",
+                _ => "",
+            };
+            Failure::Io(anyhow::anyhow!("{}: {}{}", key_path.display(), err, hint))
+        })?;
+    write_output(public_path, key.verifying_key().as_bytes())?;
+
+    println!(
+        "Wrote signing key to {} and its public key to {}",
+        key_path.display(),
+        public_path.display()
+    );
+    Ok(())
+}
+
+fn write_output(path: &Path, image: &[u8]) -> Result<(), Failure> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| Failure::Io(err.into()))?;
+    }
+    fs::write(path, image)
+        .map_err(|err| Failure::Io(anyhow::anyhow!("{}: {}", path.display(), err)))
+}
+
+// Reads a component, converting it from text or componentizing it first if needed
+fn load_component(
+    path: &Path,
+    adapters: &[Adapter],
+    verbose: bool,
+    any_core: &mut bool,
+) -> Result<Vec<u8>, Failure> {
+    let bytes = fs::read(path)
+        .map_err(|err| Failure::Io(anyhow::anyhow!("{}: {}", path.display(), err)))?;
+    if verbose {
+        println!("Read {} bytes from {}", bytes.len(), path.display());
+    }
+    // Text format is accepted for hand-written test guests
+    let bytes = wat::parse_bytes(&bytes)
+        .map_err(|err| Failure::Compile(anyhow::anyhow!("{}: {}", path.display(), err)))?
+        .into_owned();
+    if !wasmparser::Parser::is_core_wasm(&bytes) {
+        return Ok(bytes);
+    }
+
+    *any_core = true;
+    let component = componentize(&bytes, adapters)
+        .map_err(|err| Failure::Compile(err.context(path.display().to_string())))?;
+    if verbose {
+        println!(
+            "Componentized core module {} ({} -> {} bytes)",
+            path.display(),
+            bytes.len(),
+            component.len()
+        );
+    }
+    Ok(component)
+}
+
+fn load_manifest(path: Option<&Path>) -> Result<Manifest, Failure> {
+    let Some(path) = path else {
+        return Manifest::parse(PICO2_MANIFEST).map_err(Failure::Compile);
+    };
+    let text = fs::read_to_string(path)
+        .map_err(|err| Failure::Io(anyhow::anyhow!("{}: {}", path.display(), err)))?;
+    Manifest::parse(&text).map_err(|err| Failure::Compile(err.context(path.display().to_string())))
+}
+
+fn list(names: &[String]) -> String {
+    if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(", ")
+    }
+}
 
-    // 4. Output
-    let output_path = Path::new("pico2-quick/src/guest.pulley");
-    fs::write(output_path, &serialized)?;
+fn upload_image(image_path: &Path, device: &Path, slot: u8) -> Result<(), Failure> {
+    let image = fs::read(image_path)
+        .map_err(|err| Failure::Io(anyhow::anyhow!("{}: {}", image_path.display(), err)))?;
+    // Catches a wrong file before the device erases the slot
+    let (header, _) = guest_image::read_image(&image)
+        .map_err(|err| Failure::Upload(anyhow::anyhow!("{}: {}", image_path.display(), err)))?;
+    if !header.signed {
+        println!(
+            "Note: {} is unsigned, the device will refuse it",
+            image_path.display()
+        );
+    }
+    let port = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(device)
+        .map_err(|err| Failure::Io(anyhow::anyhow!("{}: {}", device.display(), err)))?;
 
     println!(
-        "Success! Wrote {} bytes to {:?}",
-        serialized.len(),
-        output_path
+        "Uploading {} (version {}, {} bytes) to slot {} via {}...",
+        image_path.display(),
+        header.version,
+        image.len(),
+        slot,
+        device.display()
     );
+    let version = upload::upload(&mut IoTransport(port), slot, &image, |sent, total| {
+        print!("\r{sent}/{total} bytes");
+        let _ = std::io::stdout().flush();
+    })
+    .map_err(|err| Failure::Upload(err.into()))?;
+    println!("\nSuccess! Slot {} now runs version {}", slot, version);
     Ok(())
 }
@@ -0,0 +1,224 @@
+use std::fmt;
+
+use wasmparser::{Encoding, Parser, Payload};
+
+// Wasmtime's per-instance bookkeeping (vmctx, instance handle, type tables);
+// a rough figure, the real one depends on how many imports and exports there are
+const INSTANCE_OVERHEAD: usize = 2 * 1024;
+
+/// What one core module inside a component defines.
+#[derive(Debug, Default)]
+pub struct ModuleStats {
+    pub functions: u32,
+    // (initial, maximum) in bytes
+    pub memories: Vec<(u64, Option<u64>)>,
+    // (initial, maximum) in elements
+    pub tables: Vec<(u64, Option<u64>)>,
+    pub data_bytes: usize,
+}
+
+/// Size and memory breakdown of a component and its precompiled image.
+pub struct Report {
+    pub image_bytes: usize,
+    pub serialized_bytes: usize,
+    // Set when the image holds the component compressed, which the host inflates into its heap
+    pub compressed_bytes: Option<usize>,
+    pub modules: Vec<ModuleStats>,
+    // Bytes per table element on the target
+    pub pointer_size: usize,
+    // Allocated for every guest call that can yield, see `EngineSettings::async_stack_size`
+    pub async_stack_size: usize,
+}
+
+/// Limits a guest has to fit in on the device.
+pub struct Budget {
+    pub flash: usize,
+    pub heap: usize,
+    pub memory: usize,
+}
+
+/// Walks every core module in `component`. Imported memories and tables
+/// (the adapter shims wit-component adds) are not counted, only the
+/// module that defines them.
+pub fn analyze(component: &[u8]) -> anyhow::Result<Vec<ModuleStats>> {
+    let mut modules: Vec<ModuleStats> = Vec::new();
+    for payload in Parser::new(0).parse_all(component) {
+        // Core sections only occur inside a module, so they belong to the last one started
+        let current = modules.last_mut();
+        match (payload?, current) {
+            (
+                Payload::Version {
+                    encoding: Encoding::Module,
+                    ..
+                },
+                _,
+            ) => modules.push(ModuleStats::default()),
+            (Payload::FunctionSection(reader), Some(module)) => module.functions += reader.count(),
+            (Payload::MemorySection(reader), Some(module)) => {
+                for memory in reader {
+                    let memory = memory?;
+                    let page = 1u64 << memory.page_size_log2.unwrap_or(16);
+                    module
+                        .memories
+                        .push((memory.initial * page, memory.maximum.map(|max| max * page)));
+                }
+            }
+            (Payload::TableSection(reader), Some(module)) => {
+                for table in reader {
+                    let ty = table?.ty;
+                    module.tables.push((ty.initial, ty.maximum));
+                }
+            }
+            (Payload::DataSection(reader), Some(module)) => {
+                for data in reader {
+                    module.data_bytes += data?.data.len();
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(modules)
+}
+
+impl Report {
+    fn memories(&self) -> impl Iterator<Item = u64> + '_ {
+        self.modules
+            .iter()
+            .flat_map(|module| &module.memories)
+            .map(|(initial, _)| *initial)
+    }
+
+    pub fn initial_memory(&self) -> u64 {
+        self.memories().sum()
+    }
+
+    /// The firmware limits each linear memory on its own, not their sum.
+    pub fn largest_memory(&self) -> u64 {
+        self.memories().max().unwrap_or(0)
+    }
+
+    /// Heap needed to instantiate the guest and make its first call: initial
+    /// linear memory, tables, the async fiber stack and per-instance state.
+    /// Code and data segments stay in flash, the image is used in place,
+    /// unless it is compressed: then the whole component is inflated into the heap.
+    pub fn estimated_heap(&self) -> usize {
+        let tables: u64 = self
+            .modules
+            .iter()
+            .flat_map(|module| &module.tables)
+            .map(|(initial, _)| initial)
+            .sum();
+        self.initial_memory() as usize
+            + tables as usize * self.pointer_size
+            + self.async_stack_size
+            + self.modules.len() * INSTANCE_OVERHEAD
+            + self.inflated_code()
+    }
+
+    /// Heap the inflated component takes, zero when it runs from flash.
+    pub fn inflated_code(&self) -> usize {
+        self.compressed_bytes.map_or(0, |_| self.serialized_bytes)
+    }
+
+    /// Everything over `budget`, as lines for the error message.
+    pub fn over_budget(&self, budget: &Budget) -> Vec<String> {
+        let mut over = Vec::new();
+        if self.image_bytes > budget.flash {
+            over.push(format!(
+                "image is {} bytes, the flash budget is {}",
+                self.image_bytes, budget.flash
+            ));
+        }
+        if self.largest_memory() as usize > budget.memory {
+            over.push(format!(
+                "a linear memory starts at {} bytes, each may use {}",
+                self.largest_memory(),
+                budget.memory
+            ));
+        }
+        if self.estimated_heap() > budget.heap {
+            over.push(format!(
+                "instantiating needs about {} bytes of heap, the budget is {}",
+                self.estimated_heap(),
+                budget.heap
+            ));
+        }
+        over
+    }
+}
+
+fn kib(bytes: u64) -> String {
+    format!("{} KiB", bytes / 1024)
+}
+
+fn percent(used: usize, budget: usize) -> usize {
+    (used * 100).checked_div(budget).unwrap_or(0)
+}
+
+pub struct Display<'a> {
+    pub report: &'a Report,
+    pub budget: &'a Budget,
+}
+
+impl fmt::Display for Display<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Display { report, budget } = self;
+        write!(
+            f,
+            "  flash    {} bytes ({} precompiled",
+            report.image_bytes, report.serialized_bytes
+        )?;
+        if let Some(compressed) = report.compressed_bytes {
+            write!(
+                f,
+                ", compressed to {compressed}, {}%",
+                percent(compressed, report.serialized_bytes)
+            )?;
+        }
+        writeln!(
+            f,
+            "), {}% of {}",
+            percent(report.image_bytes, budget.flash),
+            budget.flash
+        )?;
+        for (i, module) in report.modules.iter().enumerate() {
+            write!(
+                f,
+                "  module {i}  {} functions, {} data bytes",
+                module.functions, module.data_bytes
+            )?;
+            for (initial, max) in &module.memories {
+                let max = max.map_or("unbounded".to_string(), kib);
+                write!(f, ", memory {} (max {max})", kib(*initial))?;
+            }
+            for (initial, max) in &module.tables {
+                let max = max.map_or("unbounded".to_string(), |max| max.to_string());
+                write!(f, ", table {initial} (max {max})")?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(
+            f,
+            "  memory   {} bytes initial, largest {} is {}% of the {} each may use",
+            report.initial_memory(),
+            report.largest_memory(),
+            percent(report.largest_memory() as usize, budget.memory),
+            budget.memory
+        )?;
+        write!(
+            f,
+            "  heap     ~{} bytes to instantiate (incl. {} fiber stack",
+            report.estimated_heap(),
+            report.async_stack_size
+        )?;
+        if report.inflated_code() > 0 {
+            write!(f, ", {} inflated code", report.inflated_code())?;
+        }
+        write!(
+            f,
+            "), {}% of {}",
+            percent(report.estimated_heap(), budget.heap),
+            budget.heap
+        )
+    }
+}
@@ -0,0 +1,651 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use engine_config::{EngineSettings, FINGERPRINT, PICO2};
+use guest_bundle::{Bundle, Limits};
+use guest_image::{read_image, verify_image, ImageError, VerifyingKey};
+use wasmtime::component::Component;
+use wasmtime::Engine;
+use wit_component::StringEncoding;
+use wit_parser::{ManglingAndAbi, Resolve};
+
+const GUEST: &str = r#"(component
+    (core module $m (func (export "run")))
+    (core instance $i (instantiate $m))
+    (func (export "run") (canon lift (core func $i "run")))
+)"#;
+
+fn compiler(args: &[&str], dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_compiler"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+fn guest_in(dir: &Path) {
+    fs::write(dir.join("guest.wat"), GUEST).unwrap();
+}
+
+// What cargo produces for a wit-bindgen guest of `world`: a core module
+// with the world embedded in a custom section
+fn core_module(wit: &str, world: &str) -> Vec<u8> {
+    let mut resolve = Resolve::default();
+    let package = resolve.push_str("guest.wit", wit).unwrap();
+    let world = resolve.select_world(&[package], Some(world)).unwrap();
+    let mut module = wit_component::dummy_module(&resolve, world, ManglingAndAbi::Standard32);
+    wit_component::embed_component_metadata(&mut module, &resolve, world, StringEncoding::UTF8)
+        .unwrap();
+    module
+}
+
+fn core_guest() -> Vec<u8> {
+    core_module(include_str!("../../wit/app.wit"), "app")
+}
+
+// An app drawing through a driver, like pacman and pmod-oled-driver
+const DISPLAY_WIT: &str = r#"
+package test:display;
+
+interface graphics {
+    draw: func(x: u32, y: u32);
+}
+
+interface bus {
+    write: func(bytes: list<u8>);
+}
+
+interface clock {
+    now-ms: func() -> u64;
+}
+
+world driver {
+    import bus;
+    export graphics;
+}
+
+world app {
+    import graphics;
+    import clock;
+    export run: func();
+}
+
+world unrelated {
+    export clock;
+}
+"#;
+
+fn pulley64_component(image: &[u8]) -> Component {
+    let settings = EngineSettings {
+        target: "pulley64",
+        ..PICO2
+    };
+    let (_, payload) = read_image(image).unwrap();
+    let engine = Engine::new(&settings.config().unwrap()).unwrap();
+    unsafe { Component::deserialize(&engine, payload) }.unwrap()
+}
+
+#[test]
+fn compiles_for_the_pico() {
+    let dir = tempfile::tempdir().unwrap();
+    guest_in(dir.path());
+
+    let out = compiler(
+        &[
+            "compile",
+            "guest.wat",
+            "-o",
+            "out/guest.img",
+            "--image-version",
+            "9",
+        ],
+        dir.path(),
+    );
+
+    assert!(out.status.success(), "{out:?}");
+    let image = fs::read(dir.path().join("out/guest.img")).unwrap();
+    let (header, _) = read_image(&image).unwrap();
+    assert_eq!(header.version, 9);
+    assert_eq!(header.engine, FINGERPRINT);
+}
+
+#[test]
+fn pulley64_output_runs_on_linux() {
+    let dir = tempfile::tempdir().unwrap();
+    guest_in(dir.path());
+
+    let out = compiler(
+        &[
+            "compile",
+            "guest.wat",
+            "-o",
+            "guest.img",
+            "--target",
+            "pulley64",
+        ],
+        dir.path(),
+    );
+    assert!(out.status.success(), "{out:?}");
+
+    let settings = EngineSettings {
+        target: "pulley64",
+        ..PICO2
+    };
+    let image = fs::read(dir.path().join("guest.img")).unwrap();
+    let (header, payload) = read_image(&image).unwrap();
+    assert_eq!(header.engine, settings.fingerprint());
+    assert!(header.check_engine(FINGERPRINT).is_err());
+
+    let engine = Engine::new(&settings.config().unwrap()).unwrap();
+    unsafe { Component::deserialize(&engine, payload) }.unwrap();
+}
+
+#[test]
+fn compressed_images_inflate_to_the_same_component() {
+    let dir = tempfile::tempdir().unwrap();
+    guest_in(dir.path());
+    let compile = |output: &str, compress: bool| {
+        let mut args = vec!["compile", "guest.wat", "-o", output, "--target", "pulley64"];
+        if compress {
+            args.push("--compress");
+        }
+        let out = compiler(&args, dir.path());
+        assert!(out.status.success(), "{out:?}");
+        (
+            String::from_utf8_lossy(&out.stdout).into_owned(),
+            fs::read(dir.path().join(output)).unwrap(),
+        )
+    };
+
+    let (_, plain) = compile("plain.img", false);
+    let (stdout, compressed) = compile("compressed.img", true);
+
+    assert!(stdout.contains(", compressed to "), "{stdout}");
+    assert!(stdout.contains(" inflated code)"), "{stdout}");
+    let (_, plain) = read_image(&plain).unwrap();
+    let (_, payload) = read_image(&compressed).unwrap();
+    assert!(!guest_compress::is_compressed(plain));
+    assert!(guest_compress::is_compressed(payload));
+    let inflated = guest_compress::decompress(payload, usize::MAX).unwrap();
+    assert_eq!(&inflated[..], plain);
+
+    let settings = EngineSettings {
+        target: "pulley64",
+        ..PICO2
+    };
+    let engine = Engine::new(&settings.config().unwrap()).unwrap();
+    unsafe { Component::deserialize(&engine, &inflated) }.unwrap();
+}
+
+#[test]
+fn overrides_change_the_fingerprint() {
+    let dir = tempfile::tempdir().unwrap();
+    guest_in(dir.path());
+
+    let out = compiler(
+        &[
+            "compile",
+            "guest.wat",
+            "--max-wasm-stack",
+            "24576",
+            "--no-fuel",
+            "--verbose",
+        ],
+        dir.path(),
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert!(String::from_utf8_lossy(&out.stdout).contains("will refuse this image"));
+
+    let expected = EngineSettings {
+        max_wasm_stack: 24576,
+        consume_fuel: false,
+        ..PICO2
+    };
+    // Default output path, relative to the working directory
+    let image = fs::read(dir.path().join("target/guest.img")).unwrap();
+    assert_eq!(read_image(&image).unwrap().0.engine, expected.fingerprint());
+}
+
+#[test]
+fn exit_codes_tell_failures_apart() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("broken.wat"), "(component (func $oops))").unwrap();
+
+    let missing = compiler(&["compile", "missing.wasm"], dir.path());
+    assert_eq!(missing.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("missing.wasm"));
+
+    let broken = compiler(&["compile", "broken.wat"], dir.path());
+    assert_eq!(broken.status.code(), Some(4));
+    assert!(!dir.path().join("target/guest.img").exists());
+
+    let usage = compiler(&["compile", "broken.wat", "--target", "x86"], dir.path());
+    assert_eq!(usage.status.code(), Some(2));
+}
+
+#[test]
+fn componentizes_core_modules() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("guest.wasm"), core_guest()).unwrap();
+
+    let out = compiler(
+        &[
+            "compile",
+            "guest.wasm",
+            "-o",
+            "guest.img",
+            "--target",
+            "pulley64",
+            "-v",
+        ],
+        dir.path(),
+    );
+    assert!(out.status.success(), "{out:?}");
+    assert!(String::from_utf8_lossy(&out.stdout).contains("Componentized core module"));
+
+    let component = pulley64_component(&fs::read(dir.path().join("guest.img")).unwrap());
+    assert!(component.get_export_index(None, "my:app/run").is_some());
+}
+
+#[test]
+fn core_module_without_wit_is_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    // Its import is not described by any WIT world, so there is nothing to lift it to
+    let plain = wat::parse_str(r#"(module (import "env" "tick" (func)))"#).unwrap();
+    fs::write(dir.path().join("plain.wasm"), plain).unwrap();
+
+    let out = compiler(&["compile", "plain.wasm"], dir.path());
+    assert_eq!(out.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("plain.wasm"));
+}
+
+#[test]
+fn adapters_only_apply_to_core_modules() {
+    let dir = tempfile::tempdir().unwrap();
+    guest_in(dir.path());
+
+    let out = compiler(
+        &[
+            "compile",
+            "guest.wat",
+            "--adapt",
+            "wasi_snapshot_preview1.wasm",
+        ],
+        dir.path(),
+    );
+    assert_eq!(out.status.code(), Some(4));
+}
+
+#[test]
+fn plugs_driver_into_app() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("app.wasm"), core_module(DISPLAY_WIT, "app")).unwrap();
+    fs::write(
+        dir.path().join("driver.wasm"),
+        core_module(DISPLAY_WIT, "driver"),
+    )
+    .unwrap();
+
+    fs::write(
+        dir.path().join("host.txt"),
+        "test:display/bus\ntest:display/clock\n",
+    )
+    .unwrap();
+
+    let out = compiler(
+        &[
+            "compile",
+            "app.wasm",
+            "--plug",
+            "driver.wasm",
+            "-o",
+            "display.img",
+            "--target",
+            "pulley64",
+            "--host",
+            "host.txt",
+        ],
+        dir.path(),
+    );
+    assert!(out.status.success(), "{out:?}");
+
+    // The driver's own imports surface next to the app's unplugged ones
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let line = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Imports left for the host: "))
+        .unwrap();
+    let mut imports: Vec<_> = line.split(", ").collect();
+    imports.sort();
+    assert_eq!(imports, ["test:display/bus", "test:display/clock"]);
+
+    let component = pulley64_component(&fs::read(dir.path().join("display.img")).unwrap());
+    assert!(component.get_export_index(None, "run").is_some());
+}
+
+#[test]
+fn plug_without_matching_exports_is_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.wasm"),
+        core_module(DISPLAY_WIT, "driver"),
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("plug.wasm"),
+        core_module(DISPLAY_WIT, "unrelated"),
+    )
+    .unwrap();
+
+    let out = compiler(&["compile", "app.wasm", "--plug", "plug.wasm"], dir.path());
+    assert_eq!(out.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("no matching imports"));
+}
+
+// Guests importing host interfaces by the names pico2-quick links them under
+fn host_guest(package: &str, interface: &str) -> Vec<u8> {
+    let wit = format!(
+        "package {package};
+        interface {interface} {{
+            ping: func();
+        }}
+        world guest {{
+            import {interface};
+            export run: func();
+        }}"
+    );
+    core_module(&wit, "guest")
+}
+
+#[test]
+fn default_manifest_accepts_host_interfaces() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("guest.wasm"),
+        host_guest("wasi:delay", "delay"),
+    )
+    .unwrap();
+
+    let out = compiler(&["compile", "guest.wasm"], dir.path());
+    assert!(out.status.success(), "{out:?}");
+}
+
+#[test]
+fn unknown_imports_are_refused_with_a_diff() {
+    let dir = tempfile::tempdir().unwrap();
+    let guest = host_guest("wasi:cli@0.2.0", "environment");
+    fs::write(dir.path().join("guest.wasm"), guest).unwrap();
+
+    let out = compiler(&["compile", "guest.wasm"], dir.path());
+    assert_eq!(out.status.code(), Some(7));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("- wasi:cli/environment@0.2.0  (unknown to the host)"));
+    assert!(stderr.contains("+ wasi:spi/spi"));
+    assert!(!dir.path().join("target/guest.img").exists());
+
+    let skipped = compiler(&["compile", "guest.wasm", "--skip-host-check"], dir.path());
+    assert!(skipped.status.success(), "{skipped:?}");
+}
+
+#[test]
+fn versions_match_like_the_linker() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("host.txt"),
+        "# A host on a newer patch release\nwasi:io/streams@0.2.3\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("compatible.wasm"),
+        host_guest("wasi:io@0.2.0", "streams"),
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("newer.wasm"),
+        host_guest("wasi:io@0.3.0", "streams"),
+    )
+    .unwrap();
+
+    let ok = compiler(
+        &["compile", "compatible.wasm", "--host", "host.txt"],
+        dir.path(),
+    );
+    assert!(ok.status.success(), "{ok:?}");
+
+    let newer = compiler(&["compile", "newer.wasm", "--host", "host.txt"], dir.path());
+    assert_eq!(newer.status.code(), Some(7));
+    assert!(String::from_utf8_lossy(&newer.stderr)
+        .contains("- wasi:io/streams@0.3.0  (host has wasi:io/streams@0.2.3)"));
+}
+
+// Two pages of memory, a table and a data segment, so every report line has something to show
+const SIZED_GUEST: &str = r#"(component
+    (core module $m
+        (memory 2 4)
+        (table 3 funcref)
+        (data (i32.const 0) "hello")
+        (func (export "run"))
+        (func $unused))
+    (core instance $i (instantiate $m))
+    (func (export "run") (canon lift (core func $i "run")))
+)"#;
+
+#[test]
+fn reports_sizes_and_memory() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("sized.wat"), SIZED_GUEST).unwrap();
+
+    let out = compiler(&["compile", "sized.wat"], dir.path());
+
+    assert!(out.status.success(), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("Budget report:"), "{stdout}");
+    assert!(
+        stdout.contains("module 0  2 functions, 5 data bytes, memory 128 KiB (max 256 KiB), table 3 (max unbounded)"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("memory   131072 bytes initial"), "{stdout}");
+}
+
+#[test]
+fn exceeded_budgets_fail_without_output() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("sized.wat"), SIZED_GUEST).unwrap();
+
+    let heap = compiler(
+        &["compile", "sized.wat", "--heap-budget", "1000"],
+        dir.path(),
+    );
+    assert_eq!(heap.status.code(), Some(5));
+    let stderr = String::from_utf8_lossy(&heap.stderr);
+    assert!(
+        stderr.contains("bytes of heap, the budget is 1000"),
+        "{stderr}"
+    );
+
+    let memory = compiler(
+        &["compile", "sized.wat", "--memory-budget", "65536"],
+        dir.path(),
+    );
+    assert_eq!(memory.status.code(), Some(5));
+    let stderr = String::from_utf8_lossy(&memory.stderr);
+    assert!(
+        stderr.contains("a linear memory starts at 131072 bytes, each may use 65536"),
+        "{stderr}"
+    );
+
+    let flash = compiler(
+        &["compile", "sized.wat", "--flash-budget", "100"],
+        dir.path(),
+    );
+    assert_eq!(flash.status.code(), Some(5));
+    assert!(!dir.path().join("target/guest.img").exists());
+}
+
+const BUNDLE: &str = r#"
+[[guest]]
+name = "sensor"
+component = "guests/sensor.wasm"
+spi = ["sensor"]
+
+[[guest]]
+name = "blinker"
+component = "guests/guest.wat"
+export = "run"
+memory = 65536
+fuel = 5000
+gpio = ["LED"]
+autostart = false
+"#;
+
+#[test]
+fn bundles_guests_with_their_manifest() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("guests")).unwrap();
+    fs::write(
+        dir.path().join("guests/sensor.wasm"),
+        host_guest("wasi:delay", "delay"),
+    )
+    .unwrap();
+    fs::write(dir.path().join("guests/guest.wat"), GUEST).unwrap();
+    fs::write(dir.path().join("bundle.toml"), BUNDLE).unwrap();
+
+    let out = compiler(
+        &["bundle", "bundle.toml", "--target", "pulley64"],
+        dir.path(),
+    );
+    assert!(out.status.success(), "{out:?}");
+
+    let image = fs::read(dir.path().join("target/bundle.img")).unwrap();
+    let (_, payload) = read_image(&image).unwrap();
+    let bundle = Bundle::parse(payload).unwrap();
+    let entries: Vec<_> = bundle.entries().collect();
+    assert_eq!(entries.len(), 2);
+
+    let sensor = &entries[0];
+    assert_eq!(sensor.name, "sensor");
+    assert_eq!(sensor.export, "my:app/run");
+    // Read from the component, not the manifest
+    assert_eq!(
+        sensor.interfaces.clone().collect::<Vec<_>>(),
+        ["wasi:delay/delay"]
+    );
+    assert_eq!(sensor.spi.clone().collect::<Vec<_>>(), ["sensor"]);
+    assert_eq!(sensor.limits, Limits::default());
+    assert!(sensor.autostart);
+
+    let blinker = &entries[1];
+    assert_eq!(blinker.export, "run");
+    assert_eq!(blinker.interfaces.clone().count(), 0);
+    assert_eq!(blinker.gpio.clone().collect::<Vec<_>>(), ["LED"]);
+    assert_eq!(
+        blinker.limits,
+        Limits {
+            memory_bytes: 65536,
+            fuel_slice: 5000,
+        }
+    );
+    assert!(!blinker.autostart);
+
+    let settings = EngineSettings {
+        target: "pulley64",
+        ..PICO2
+    };
+    let engine = Engine::new(&settings.config().unwrap()).unwrap();
+    for entry in entries {
+        unsafe { Component::deserialize(&engine, entry.component) }.unwrap();
+    }
+}
+
+#[test]
+fn bad_bundle_manifests_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    guest_in(dir.path());
+    let twice = "[[guest]]\nname = \"a\"\ncomponent = \"guest.wat\"\n".repeat(2);
+    fs::write(dir.path().join("twice.toml"), twice).unwrap();
+    fs::write(
+        dir.path().join("typo.toml"),
+        "[[guest]]\nname = \"a\"\ncomponent = \"guest.wat\"\nautostrat = true\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("huge.toml"),
+        "[[guest]]\nname = \"a\"\ncomponent = \"guest.wat\"\nmemory = 4294967296\n",
+    )
+    .unwrap();
+
+    let out = compiler(&["bundle", "twice.toml"], dir.path());
+    assert_eq!(out.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("name is already used"));
+
+    let out = compiler(&["bundle", "typo.toml"], dir.path());
+    assert_eq!(out.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("autostrat"));
+
+    let out = compiler(&["bundle", "huge.toml"], dir.path());
+    assert_eq!(out.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("memory = 4294967296"));
+
+    let out = compiler(&["bundle", "missing.toml"], dir.path());
+    assert_eq!(out.status.code(), Some(3));
+    assert!(!dir.path().join("target/bundle.img").exists());
+}
+
+#[test]
+fn signs_images_with_a_generated_key() {
+    let dir = tempfile::tempdir().unwrap();
+    guest_in(dir.path());
+    let keygen = &["keygen", "--key", "keys/test.key", "--public", "test.pub"];
+    let out = compiler(keygen, dir.path());
+    assert!(out.status.success(), "{out:?}");
+
+    let out = compiler(
+        &["compile", "guest.wat", "--key", "keys/test.key"],
+        dir.path(),
+    );
+    assert!(out.status.success(), "{out:?}");
+    let public: [u8; 32] = fs::read(dir.path().join("test.pub"))
+        .unwrap()
+        .try_into()
+        .unwrap();
+    let key = VerifyingKey::from_bytes(&public).unwrap();
+    let image = fs::read(dir.path().join("target/guest.img")).unwrap();
+    assert!(verify_image(&image, &key).is_ok());
+
+    // A second key pair signs images the first public key refuses
+    let other = &["keygen", "--key", "other.key", "--public", "other.pub"];
+    assert!(compiler(other, dir.path()).status.success());
+    let out = compiler(&["compile", "guest.wat", "--key", "other.key"], dir.path());
+    assert!(out.status.success(), "{out:?}");
+    let image = fs::read(dir.path().join("target/guest.img")).unwrap();
+    assert_eq!(verify_image(&image, &key), Err(ImageError::BadSignature));
+
+    let out = compiler(&["compile", "guest.wat"], dir.path());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("unsigned"));
+    let image = fs::read(dir.path().join("target/guest.img")).unwrap();
+    assert_eq!(verify_image(&image, &key), Err(ImageError::Unsigned));
+}
+
+#[test]
+fn keygen_keeps_existing_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let keygen = ["keygen", "--key", "test.key", "--public", "test.pub"];
+    assert!(compiler(&keygen, dir.path()).status.success());
+    let key = fs::read(dir.path().join("test.key")).unwrap();
+    assert_eq!(key.len(), 32);
+
+    let out = compiler(&keygen, dir.path());
+    assert_eq!(out.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--force"));
+    assert_eq!(fs::read(dir.path().join("test.key")).unwrap(), key);
+
+    let forced = [&keygen[..], &["--force"]].concat();
+    assert!(compiler(&forced, dir.path()).status.success());
+    assert_ne!(fs::read(dir.path().join("test.key")).unwrap(), key);
+
+    fs::write(dir.path().join("short.key"), [0; 16]).unwrap();
+    fs::write(dir.path().join("guest.wat"), GUEST).unwrap();
+    let out = compiler(&["compile", "guest.wat", "--key", "short.key"], dir.path());
+    assert_eq!(out.status.code(), Some(4));
+}
@@ -6,6 +6,7 @@ generate!({
     with: { "my:pmod-oled-driver/graphics": generate }
 });
 
+use crate::exports::my::app::run::Guest;
 use crate::my::pmod_oled_driver::graphics::{Display, DisplayError, PixelColor};
 
 struct DvdBounceApp;
@@ -9,6 +9,7 @@ generate!({
     }
 });
 
+use crate::exports::my::app::run::Guest;
 use crate::my::debug::logging::log;
 use crate::my::pmod_oled_driver::graphics::{Display, DisplayError, PixelColor};
 
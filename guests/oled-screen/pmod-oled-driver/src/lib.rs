@@ -10,7 +10,7 @@ generate!({
 use crate::exports::my::pmod_oled_driver::graphics::{
     DisplayError, Guest, GuestDisplay, PixelColor,
 };
-use crate::my::debug::logging::log;
+use crate::my::debug::logging::{Level as LogLevel, log_record};
 use crate::wasi::delay::delay::delay_ms as host_delay_ms;
 use crate::wasi::gpio::gpio::{Level, set_pin_state};
 use crate::wasi::spi::spi::{Config, Mode, SpiDevice, get_device_names, open_device};
@@ -37,15 +37,15 @@ pub struct Display {
 
 impl GuestDisplay for Display {
     fn new() -> Self {
-        log("[Driver] Display::new() invoked");
+        debug("Display::new() invoked");
 
-        log("[Driver] Calling get_device_names()...");
+        debug("Calling get_device_names()...");
         let names = get_device_names();
 
-        log("[Driver] Opening SPI device...");
+        debug("Opening SPI device...");
         let spi = open_device(&names[0]).expect("No SPI device found");
 
-        log("[Driver] Configuring SPI...");
+        debug("Configuring SPI...");
         // Pass Config by value
         spi.configure(Config {
             frequency: 8_000_000,
@@ -54,10 +54,10 @@ impl GuestDisplay for Display {
         })
         .unwrap();
 
-        log("[Driver] Allocating 512-byte framebuffer...");
+        debug("Allocating 512-byte framebuffer...");
         let buffer = RefCell::new(vec![0u8; 512]);
 
-        log("[Driver] Initialization complete!");
+        debug("Initialization complete!");
         Self {
             spi,
             buffer,
@@ -173,6 +173,10 @@ impl GuestDisplay for Display {
     }
 }
 
+fn debug(msg: &str) {
+    log_record(LogLevel::Debug, "pmod-oled-driver", msg, &[]);
+}
+
 impl Display {
     fn send_cmd(&self, c: u8) -> Result<(), DisplayError> {
         // DC is active high, so "Inactive" means Level::Low
@@ -5,10 +5,15 @@ wit_bindgen::generate!({
 });
 
 extern crate alloc;
-use exports::my::temp_sensor::sensor_app::Guest;
-use my::debug::logging::log;
+use exports::my::app::run::Guest;
+use my::debug::logging::{Field, Level, log, log_record};
+use my::diagnostics::heap;
+use wasi::delay::delay::delay_ms;
 use wasi::spi::spi::{Config, Mode, SpiDevice, open_device};
 
+// Time between readings; the host runs other guests while this one sleeps
+const SAMPLE_INTERVAL_MS: u32 = 2_000;
+
 struct Component;
 
 impl Guest for Component {
@@ -26,26 +31,62 @@ impl Guest for Component {
         // Read Chip ID
         let chip_id = read_register(&spi, 0xD0, 1)[0];
         if chip_id != 0x60 {
-            log(&alloc::format!(
-                "Warning: Expected Chip ID 0x60, got 0x{:X}",
-                chip_id
-            ));
+            log_record(
+                Level::Warn,
+                "bme280",
+                "Unexpected chip ID",
+                &[
+                    Field {
+                        key: "expected".into(),
+                        value: "0x60".into(),
+                    },
+                    Field {
+                        key: "got".into(),
+                        value: alloc::format!("0x{:X}", chip_id),
+                    },
+                ],
+            );
         }
 
-        log("Reading calibration data...");
+        log_record(Level::Debug, "bme280", "Reading calibration data...", &[]);
         let calib = read_calibration_data(&spi);
 
         write_register(&spi, 0xF2, 0x01); // ctrl_hum (Humidity x1)
         write_register(&spi, 0xF4, 0x27); // ctrl_meas (Temp x1, Pressure x1, Normal)
 
-        let raw_t = read_raw_temp(&spi);
-        let raw_h = read_raw_humidity(&spi);
-
-        let (temp_c, t_fine) = compensate_temperature(raw_t, &calib);
-        let humidity = compensate_humidity(raw_h, t_fine, &calib);
-
-        log(&alloc::format!("Temperature: {:.2} C", temp_c));
-        log(&alloc::format!("Humidity: {:.2} %RH", humidity));
+        loop {
+            let raw_t = read_raw_temp(&spi);
+            let raw_h = read_raw_humidity(&spi);
+
+            let (temp_c, t_fine) = compensate_temperature(raw_t, &calib);
+            let humidity = compensate_humidity(raw_h, t_fine, &calib);
+
+            log(&alloc::format!("Temperature: {:.2} C", temp_c));
+            log(&alloc::format!("Humidity: {:.2} %RH", humidity));
+
+            let stats = heap::stats();
+            log_record(
+                Level::Debug,
+                "heap",
+                "Host heap usage",
+                &[
+                    Field {
+                        key: "used".into(),
+                        value: alloc::format!("{}", stats.used),
+                    },
+                    Field {
+                        key: "peak".into(),
+                        value: alloc::format!("{}", stats.peak),
+                    },
+                    Field {
+                        key: "capacity".into(),
+                        value: alloc::format!("{}", stats.capacity),
+                    },
+                ],
+            );
+
+            delay_ms(SAMPLE_INTERVAL_MS);
+        }
     }
 }
 
@@ -81,7 +122,7 @@ fn read_register(spi: &SpiDevice, reg: u8, len: u64) -> alloc::vec::Vec<u8> {
 
 fn write_register(spi: &SpiDevice, reg: u8, value: u8) {
     // The host handles the CS pin automatically during this write!
-    let _ = spi.write(&[reg & !0x80, value]).unwrap();
+    spi.write(&[reg & !0x80, value]).unwrap();
 }
 
 // ... (keep the rest of the data extraction and math functions the exact same)
@@ -157,13 +198,7 @@ fn compensate_humidity(adc_h: i32, t_fine: f64, calib: &Bme280Calib) -> f32 {
                     * (1.0 + calib.dig_h3 as f64 / 67108864.0 * var_h)));
     var_h = var_h * (1.0 - calib.dig_h1 as f64 * var_h / 524288.0);
 
-    if var_h > 100.0 {
-        var_h = 100.0;
-    } else if var_h < 0.0 {
-        var_h = 0.0;
-    }
-
-    var_h as f32
+    var_h.clamp(0.0, 100.0) as f32
 }
 
 export!(Component);
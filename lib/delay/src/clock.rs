@@ -0,0 +1,143 @@
+//! Simulated time, for hosts that would rather not wait out a guest's delays.
+//!
+//! A [`VirtualClock`] only moves when everything it drives is asleep on it:
+//! [`VirtualClock::block_on`] polls until nothing is left to do, then jumps
+//! straight to the earliest deadline. Running guests take no time at all, so
+//! a run depends on what the guests do and not on how fast the machine is.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::mem;
+use std::pin::{Pin, pin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+/// Shared by every guest and the host loop; clones see the same time.
+#[derive(Clone, Default)]
+pub struct VirtualClock(Arc<Mutex<State>>);
+
+#[derive(Default)]
+struct State {
+    now_ms: u64,
+    next_sleep: u64,
+    // By deadline, then by sleep, so sleepers due together wake in the order they slept
+    sleepers: BTreeMap<(u64, u64), Waker>,
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Milliseconds since the clock was created.
+    pub fn now_ms(&self) -> u64 {
+        self.0.lock().unwrap().now_ms
+    }
+
+    /// Resolves once the clock has moved on by `ms`.
+    pub fn sleep(&self, ms: u64) -> Sleep {
+        let now_ms = self.now_ms();
+        self.sleep_until(now_ms.saturating_add(ms))
+    }
+
+    /// Resolves once the clock reads `deadline_ms`, at once if it is past.
+    pub fn sleep_until(&self, deadline_ms: u64) -> Sleep {
+        let mut state = self.0.lock().unwrap();
+        let id = state.next_sleep;
+        state.next_sleep += 1;
+        Sleep {
+            clock: self.clone(),
+            key: (deadline_ms, id),
+        }
+    }
+
+    /// Jumps to the earliest deadline anything sleeps until and wakes what
+    /// was due by then. Returns false, leaving the time as is, if nothing sleeps.
+    pub fn advance(&self) -> bool {
+        let mut state = self.0.lock().unwrap();
+        let Some(&(deadline, _)) = state.sleepers.keys().next() else {
+            return false;
+        };
+        state.now_ms = state.now_ms.max(deadline);
+        let later = state.sleepers.split_off(&(deadline, u64::MAX));
+        let due = mem::replace(&mut state.sleepers, later);
+        drop(state);
+        for waker in due.into_values() {
+            waker.wake();
+        }
+        true
+    }
+
+    /// Runs `future` to completion on this thread, advancing the clock each
+    /// time everything it drives is asleep. If something waits on anything
+    /// else, the thread parks until that wakes it.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        let signal = Arc::new(Signal {
+            woken: AtomicBool::new(false),
+            thread: thread::current(),
+        });
+        let waker = Waker::from(signal.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut future = pin!(future);
+        loop {
+            signal.woken.store(false, Ordering::SeqCst);
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            // Woken while polled, e.g. by a fuel yield: there is more to do now
+            if signal.woken.load(Ordering::SeqCst) || self.advance() {
+                continue;
+            }
+            while !signal.woken.swap(false, Ordering::SeqCst) {
+                thread::park();
+            }
+        }
+    }
+}
+
+/// See [`VirtualClock::sleep`].
+pub struct Sleep {
+    clock: VirtualClock,
+    // Deadline and sleep
+    key: (u64, u64),
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.clock.0.lock().unwrap();
+        if state.now_ms >= self.key.0 {
+            state.sleepers.remove(&self.key);
+            Poll::Ready(())
+        } else {
+            state.sleepers.insert(self.key, cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+// A guest dropped mid-sleep must not hold the clock back
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.clock.0.lock().unwrap().sleepers.remove(&self.key);
+    }
+}
+
+struct Signal {
+    woken: AtomicBool,
+    thread: Thread,
+}
+
+impl Wake for Signal {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+        self.thread.unpark();
+    }
+}
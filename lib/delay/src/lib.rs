@@ -1,16 +1,42 @@
 #![no_std]
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
 use core::marker::PhantomData;
 use wasmtime::component::{HasData, Linker};
 
+#[cfg(feature = "std")]
+mod clock;
+
+#[cfg(feature = "std")]
+pub use clock::{Sleep, VirtualClock};
+
 wasmtime::component::bindgen!({
     path: "../../wit/delay.wit",
     world: "wasi-delay-host",
+    // Sleeping guests yield to the executor, so other guests keep running
+    imports: { "wasi:delay/delay.delay-ms": async },
 });
 
+#[derive(Default)]
 pub struct DelayCtx {
-    // Empty for now, but provides the pattern for future extensions
+    // Sleeps on this instead of the embassy-time driver when set
+    #[cfg(feature = "std")]
+    clock: Option<VirtualClock>,
+}
+
+impl DelayCtx {
+    /// Delays wait on the embassy-time driver.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delays only advance `clock`, see [`VirtualClock`].
+    #[cfg(feature = "std")]
+    pub fn with_clock(clock: VirtualClock) -> Self {
+        Self { clock: Some(clock) }
+    }
 }
 
 pub trait DelayView {
@@ -21,16 +47,25 @@ pub struct DelayImpl<'a, T> {
     pub host: &'a mut T,
 }
 
-impl<'a, T: DelayView> wasi::delay::delay::Host for DelayImpl<'a, T> {
-    fn delay_ms(&mut self, ms: u32) {
-        embassy_time::block_for(embassy_time::Duration::from_millis(ms as u64));
+// The clock is whatever embassy-time driver the host links: the RP2350's
+// timer on the board, the std one in the simulator. Unless it is virtual.
+impl<'a, T: DelayView + Send> wasi::delay::delay::Host for DelayImpl<'a, T> {
+    async fn delay_ms(&mut self, ms: u32) {
+        #[cfg(feature = "std")]
+        if let Some(clock) = &self.host.delay_ctx().clock {
+            clock.sleep(ms as u64).await;
+            return;
+        }
+        embassy_time::Timer::after_millis(ms as u64).await;
     }
 }
 
 pub struct DelayBindingMarker<T>(PhantomData<T>);
-impl<T: DelayView + 'static> HasData for DelayBindingMarker<T> {
+impl<T: DelayView + Send + 'static> HasData for DelayBindingMarker<T> {
     type Data<'a> = DelayImpl<'a, T>;
 }
-pub fn add_to_linker<T: DelayView + 'static>(linker: &mut Linker<T>) -> wasmtime::Result<()> {
+pub fn add_to_linker<T: DelayView + Send + 'static>(
+    linker: &mut Linker<T>,
+) -> wasmtime::Result<()> {
     wasi::delay::delay::add_to_linker::<T, DelayBindingMarker<T>>(linker, |host| DelayImpl { host })
 }
@@ -0,0 +1,134 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Snapshot of the host heap, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HeapStats {
+    pub used: usize,
+    // Highest `used` seen since boot or the last `reset_peak`
+    pub peak: usize,
+    // Zero until `set_capacity` is called
+    pub capacity: usize,
+    // Live allocations
+    pub allocations: usize,
+    // Requests the inner allocator could not satisfy
+    pub failures: usize,
+}
+
+/// Anything that can report heap usage; lets [`DiagnosticsCtx`](crate::DiagnosticsCtx)
+/// hold a `TrackingHeap` without naming the inner allocator.
+pub trait HeapSource: Sync {
+    fn stats(&self) -> HeapStats;
+}
+
+/// Global allocator wrapper that counts what passes through it.
+///
+/// ```ignore
+/// #[global_allocator]
+/// static HEAP: TrackingHeap<Heap> = TrackingHeap::new(Heap::empty());
+/// unsafe { HEAP.inner().init(start, size) }
+/// HEAP.set_capacity(size);
+/// ```
+pub struct TrackingHeap<A> {
+    inner: A,
+    capacity: AtomicUsize,
+    used: AtomicUsize,
+    peak: AtomicUsize,
+    allocations: AtomicUsize,
+    failures: AtomicUsize,
+}
+
+impl<A> TrackingHeap<A> {
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            capacity: AtomicUsize::new(0),
+            used: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            allocations: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+    }
+
+    /// Starts a new high-water mark from the current usage, e.g. between guest runs.
+    pub fn reset_peak(&self) {
+        self.peak
+            .store(self.used.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    fn grew(&self, bytes: usize) {
+        let used = self.used.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.peak.fetch_max(used, Ordering::Relaxed);
+    }
+
+    fn shrank(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+impl<A> HeapSource for TrackingHeap<A>
+where
+    A: Sync,
+{
+    fn stats(&self) -> HeapStats {
+        HeapStats {
+            used: self.used.load(Ordering::Relaxed),
+            peak: self.peak.load(Ordering::Relaxed),
+            capacity: self.capacity.load(Ordering::Relaxed),
+            allocations: self.allocations.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+        }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingHeap<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.inner.alloc(layout) };
+        if ptr.is_null() {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.allocations.fetch_add(1, Ordering::Relaxed);
+            self.grew(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.inner.alloc_zeroed(layout) };
+        if ptr.is_null() {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.allocations.fetch_add(1, Ordering::Relaxed);
+            self.grew(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.inner.dealloc(ptr, layout) };
+        self.allocations.fetch_sub(1, Ordering::Relaxed);
+        self.shrank(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { self.inner.realloc(ptr, layout, new_size) };
+        if new_ptr.is_null() {
+            // The old block is still live, so only the failure is counted
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        } else if new_size > layout.size() {
+            self.grew(new_size - layout.size());
+        } else {
+            self.shrank(layout.size() - new_size);
+        }
+        new_ptr
+    }
+}
@@ -0,0 +1,60 @@
+#![no_std]
+extern crate alloc;
+
+use core::marker::PhantomData;
+use wasmtime::component::{HasData, Linker};
+
+mod heap;
+
+pub use heap::{HeapSource, HeapStats, TrackingHeap};
+
+wasmtime::component::bindgen!({
+    path: "../../wit/diagnostics.wit",
+    world: "diagnostics-host",
+});
+
+pub struct DiagnosticsCtx {
+    pub heap: &'static dyn HeapSource,
+}
+
+impl DiagnosticsCtx {
+    pub fn new(heap: &'static dyn HeapSource) -> Self {
+        Self { heap }
+    }
+}
+
+pub trait DiagnosticsView {
+    fn diagnostics_ctx(&mut self) -> &mut DiagnosticsCtx;
+}
+
+pub struct DiagnosticsImpl<'a, T> {
+    pub host: &'a mut T,
+}
+
+impl<'a, T: DiagnosticsView> my::diagnostics::heap::Host for DiagnosticsImpl<'a, T> {
+    fn stats(&mut self) -> my::diagnostics::heap::HeapStats {
+        let stats = self.host.diagnostics_ctx().heap.stats();
+        my::diagnostics::heap::HeapStats {
+            used: saturate(stats.used),
+            peak: saturate(stats.peak),
+            capacity: saturate(stats.capacity),
+            allocations: saturate(stats.allocations),
+            failures: saturate(stats.failures),
+        }
+    }
+}
+
+// Lossless on the 32-bit firmware, only clamps when the host runs on a 64-bit machine
+fn saturate(value: usize) -> u32 {
+    u32::try_from(value).unwrap_or(u32::MAX)
+}
+
+pub struct DiagnosticsBindingMarker<T>(PhantomData<T>);
+impl<T: DiagnosticsView + 'static> HasData for DiagnosticsBindingMarker<T> {
+    type Data<'a> = DiagnosticsImpl<'a, T>;
+}
+pub fn add_to_linker<T: DiagnosticsView + 'static>(linker: &mut Linker<T>) -> wasmtime::Result<()> {
+    my::diagnostics::heap::add_to_linker::<T, DiagnosticsBindingMarker<T>>(linker, |host| {
+        DiagnosticsImpl { host }
+    })
+}
@@ -0,0 +1,89 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+
+use diagnostics::{HeapSource, HeapStats, TrackingHeap};
+
+#[test]
+fn tracks_usage_and_peak() {
+    let heap = TrackingHeap::new(System);
+    heap.set_capacity(4096);
+    let small = Layout::from_size_align(100, 8).unwrap();
+    let large = Layout::from_size_align(1000, 8).unwrap();
+
+    unsafe {
+        let a = heap.alloc(small);
+        let b = heap.alloc_zeroed(large);
+        assert_eq!(
+            heap.stats(),
+            HeapStats {
+                used: 1100,
+                peak: 1100,
+                capacity: 4096,
+                allocations: 2,
+                failures: 0,
+            }
+        );
+
+        heap.dealloc(b, large);
+        let stats = heap.stats();
+        assert_eq!((stats.used, stats.peak, stats.allocations), (100, 1100, 1));
+
+        heap.dealloc(a, small);
+    }
+    assert_eq!(heap.stats().used, 0);
+}
+
+#[test]
+fn realloc_adjusts_usage_by_the_difference() {
+    let heap = TrackingHeap::new(System);
+    let layout = Layout::from_size_align(64, 8).unwrap();
+
+    unsafe {
+        let ptr = heap.alloc(layout);
+        let ptr = heap.realloc(ptr, layout, 256);
+        assert_eq!(heap.stats().used, 256);
+
+        let grown = Layout::from_size_align(256, 8).unwrap();
+        let ptr = heap.realloc(ptr, grown, 32);
+        let stats = heap.stats();
+        assert_eq!((stats.used, stats.peak, stats.allocations), (32, 256, 1));
+
+        heap.dealloc(ptr, Layout::from_size_align(32, 8).unwrap());
+    }
+}
+
+#[test]
+fn reset_peak_starts_from_current_usage() {
+    let heap = TrackingHeap::new(System);
+    let layout = Layout::from_size_align(512, 8).unwrap();
+
+    unsafe {
+        let a = heap.alloc(layout);
+        let b = heap.alloc(layout);
+        heap.dealloc(b, layout);
+        assert_eq!(heap.stats().peak, 1024);
+
+        heap.reset_peak();
+        assert_eq!(heap.stats().peak, 512);
+        heap.dealloc(a, layout);
+    }
+}
+
+struct Exhausted;
+
+unsafe impl GlobalAlloc for Exhausted {
+    unsafe fn alloc(&self, _layout: Layout) -> *mut u8 {
+        std::ptr::null_mut()
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+#[test]
+fn failed_allocations_are_counted() {
+    let heap = TrackingHeap::new(Exhausted);
+    let layout = Layout::from_size_align(16, 4).unwrap();
+
+    assert!(unsafe { heap.alloc(layout) }.is_null());
+    let stats = heap.stats();
+    assert_eq!((stats.used, stats.allocations, stats.failures), (0, 0, 1));
+}
@@ -0,0 +1,122 @@
+//! The Pulley engine configuration shared by the compiler, which precompiles
+//! guests with it, and the firmware, which runs them.
+//!
+//! Wasmtime only accepts precompiled code from an engine configured like its
+//! own, so both sides build their `Config` from [`PICO2`]. The compiler also
+//! stamps [`FINGERPRINT`] into every guest image, which lets the host refuse
+//! an image built against other settings before handing it to Wasmtime.
+//!
+//! It also holds the RAM budgets the firmware runs guests under, so the
+//! compiler can check a guest against them before it is ever flashed.
+#![no_std]
+
+use wasmtime::{Config, WasmFeatures};
+
+/// Every setting that has to agree between compiler and host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EngineSettings {
+    pub target: &'static str,
+    // Fuel checks are compiled into the guest code
+    pub consume_fuel: bool,
+    pub async_support: bool,
+    pub signals_based_traps: bool,
+    pub memory_init_cow: bool,
+    pub max_wasm_stack: usize,
+    // Fiber stack for async calls, must be larger than `max_wasm_stack`
+    pub async_stack_size: usize,
+    pub memory_guard_size: u64,
+    pub memory_reservation: u64,
+    pub memory_reservation_for_growth: u64,
+}
+
+/// The Pico 2: no MMU, no OS signals and about 500 KiB of RAM.
+pub const PICO2: EngineSettings = EngineSettings {
+    target: "pulley32",
+    consume_fuel: true,
+    async_support: true,
+    signals_based_traps: false,
+    memory_init_cow: false,
+    max_wasm_stack: 16 * 1024,
+    // The 2 MiB default does not fit in RAM
+    async_stack_size: 32 * 1024,
+    memory_guard_size: 0,
+    memory_reservation: 0,
+    memory_reservation_for_growth: 0,
+};
+
+/// Heap the firmware sets aside for Wasmtime, shared by every guest; the
+/// rest of the 512 KiB of RAM goes to the stack and statics.
+pub const HEAP_SIZE: usize = 470 * 1024;
+
+/// Linear memory a single guest may use, enforced by the firmware's
+/// `ResourceLimiter`. Matches the guests' `--max-memory` link flag.
+pub const GUEST_MEMORY_BYTES: usize = 128 * 1024;
+
+/// Fingerprint of [`PICO2`], as stored in guest image headers.
+pub const FINGERPRINT: u32 = PICO2.fingerprint();
+
+/// A `Config` built from [`PICO2`].
+pub fn config() -> wasmtime::Result<Config> {
+    PICO2.config()
+}
+
+impl EngineSettings {
+    pub fn config(&self) -> wasmtime::Result<Config> {
+        let mut config = Config::new();
+        config.target(self.target)?;
+        config.wasm_component_model(true);
+        // No GC on the device, so the proposals that need it are off on both sides
+        config.gc_support(false);
+        config.wasm_features(WasmFeatures::GC | WasmFeatures::FUNCTION_REFERENCES, false);
+        config.consume_fuel(self.consume_fuel);
+        config.async_support(self.async_support);
+        config.signals_based_traps(self.signals_based_traps);
+        config.memory_init_cow(self.memory_init_cow);
+        config.max_wasm_stack(self.max_wasm_stack);
+        config.async_stack_size(self.async_stack_size);
+        config.memory_guard_size(self.memory_guard_size);
+        config.memory_reservation(self.memory_reservation);
+        config.memory_reservation_for_growth(self.memory_reservation_for_growth);
+        Ok(config)
+    }
+
+    /// FNV-1a over every field. Sizes are hashed as 64-bit values so the
+    /// compiler (64-bit) and the firmware (32-bit) agree.
+    pub const fn fingerprint(&self) -> u32 {
+        let mut hash = Fnv::new().bytes(self.target.as_bytes()).bytes(&[0]);
+        let fields = [
+            self.consume_fuel as u64,
+            self.async_support as u64,
+            self.signals_based_traps as u64,
+            self.memory_init_cow as u64,
+            self.max_wasm_stack as u64,
+            self.async_stack_size as u64,
+            self.memory_guard_size,
+            self.memory_reservation,
+            self.memory_reservation_for_growth,
+        ];
+        let mut i = 0;
+        while i < fields.len() {
+            hash = hash.bytes(&fields[i].to_le_bytes());
+            i += 1;
+        }
+        hash.0
+    }
+}
+
+struct Fnv(u32);
+
+impl Fnv {
+    const fn new() -> Self {
+        Fnv(0x811C_9DC5)
+    }
+
+    const fn bytes(mut self, bytes: &[u8]) -> Self {
+        let mut i = 0;
+        while i < bytes.len() {
+            self.0 = (self.0 ^ bytes[i] as u32).wrapping_mul(0x0100_0193);
+            i += 1;
+        }
+        self
+    }
+}
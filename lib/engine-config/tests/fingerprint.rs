@@ -0,0 +1,98 @@
+use engine_config::{EngineSettings, FINGERPRINT, PICO2};
+use wasmtime::Engine;
+use wasmtime::component::Component;
+
+const GUEST: &str = r#"(component
+    (core module $m (func (export "run")))
+    (core instance $i (instantiate $m))
+    (func (export "run") (canon lift (core func $i "run")))
+)"#;
+
+#[test]
+fn pico2_config_builds_an_engine() {
+    let engine = Engine::new(&engine_config::config().unwrap()).unwrap();
+
+    // Compiling for the device works anywhere, only running needs a 32-bit host
+    engine.precompile_component(GUEST.as_bytes()).unwrap();
+}
+
+#[test]
+fn fingerprint_is_stable() {
+    assert_eq!(FINGERPRINT, PICO2.fingerprint());
+}
+
+#[test]
+fn every_setting_changes_the_fingerprint() {
+    let variants = [
+        EngineSettings {
+            target: "pulley64",
+            ..PICO2
+        },
+        EngineSettings {
+            consume_fuel: false,
+            ..PICO2
+        },
+        EngineSettings {
+            async_support: false,
+            ..PICO2
+        },
+        EngineSettings {
+            signals_based_traps: true,
+            ..PICO2
+        },
+        EngineSettings {
+            memory_init_cow: true,
+            ..PICO2
+        },
+        EngineSettings {
+            max_wasm_stack: 32 * 1024,
+            ..PICO2
+        },
+        EngineSettings {
+            async_stack_size: 64 * 1024,
+            ..PICO2
+        },
+        EngineSettings {
+            memory_guard_size: 4096,
+            ..PICO2
+        },
+        EngineSettings {
+            memory_reservation: 4096,
+            ..PICO2
+        },
+        EngineSettings {
+            memory_reservation_for_growth: 4096,
+            ..PICO2
+        },
+    ];
+    for variant in variants {
+        assert_ne!(variant.fingerprint(), FINGERPRINT, "{variant:?}");
+    }
+}
+
+// Pulley32 code only runs on 32-bit hosts, so these load it through the 64-bit interpreter
+const HOST: EngineSettings = EngineSettings {
+    target: "pulley64",
+    ..PICO2
+};
+
+#[test]
+fn precompiled_guest_loads_on_the_same_config() {
+    let engine = Engine::new(&HOST.config().unwrap()).unwrap();
+    let serialized = engine.precompile_component(GUEST.as_bytes()).unwrap();
+
+    unsafe { Component::deserialize(&engine, &serialized) }.unwrap();
+}
+
+#[test]
+fn precompiled_guest_is_refused_by_other_config() {
+    let engine = Engine::new(&HOST.config().unwrap()).unwrap();
+    let serialized = engine.precompile_component(GUEST.as_bytes()).unwrap();
+
+    let other = EngineSettings {
+        consume_fuel: false,
+        ..HOST
+    };
+    let other = Engine::new(&other.config().unwrap()).unwrap();
+    assert!(unsafe { Component::deserialize(&other, &serialized) }.is_err());
+}
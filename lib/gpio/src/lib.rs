@@ -1,11 +1,11 @@
 #![no_std]
 extern crate alloc;
 
+use alloc::boxed::Box;
 use alloc::collections::BTreeMap;
 use alloc::string::String;
 use core::marker::PhantomData;
 
-use embassy_rp::gpio::Output;
 use wasmtime::component::{HasData, Linker};
 
 wasmtime::component::bindgen!({
@@ -13,9 +13,42 @@ wasmtime::component::bindgen!({
     world: "wasi-gpio-host",
 });
 
+pub use wasi::gpio::gpio::Level;
+
+/// An output a guest drives by label: an `embassy_rp` pin on the board
+/// (with the `rp` feature) or a simulated one.
+pub trait OutputPin: Send {
+    fn set_level(&mut self, level: Level);
+}
+
+#[cfg(feature = "rp")]
+impl OutputPin for embassy_rp::gpio::Output<'static> {
+    fn set_level(&mut self, level: Level) {
+        let level = match level {
+            Level::High => embassy_rp::gpio::Level::High,
+            Level::Low => embassy_rp::gpio::Level::Low,
+        };
+        embassy_rp::gpio::Output::set_level(self, level);
+    }
+}
+
 pub struct GpioCtx {
     // Stores available initialized output pins mapped by a string label
-    pub pins: BTreeMap<String, Output<'static>>,
+    pub pins: BTreeMap<String, Box<dyn OutputPin>>,
+    // Level each labelled pin is returned to by `reset`, e.g. High for active-low power enables
+    pub safe_levels: BTreeMap<String, Level>,
+}
+
+impl GpioCtx {
+    /// Drives every pin that has a safe level back to it, for use after a
+    /// guest failed and before it is re-instantiated.
+    pub fn reset(&mut self) {
+        for (label, level) in &self.safe_levels {
+            if let Some(pin) = self.pins.get_mut(label) {
+                pin.set_level(*level);
+            }
+        }
+    }
 }
 
 pub trait GpioView {
@@ -27,12 +60,9 @@ pub struct GpioImpl<'a, T> {
 }
 
 impl<'a, T: GpioView> wasi::gpio::gpio::Host for GpioImpl<'a, T> {
-    fn set_pin_state(&mut self, label: String, level: wasi::gpio::gpio::Level) {
+    fn set_pin_state(&mut self, label: String, level: Level) {
         if let Some(pin) = self.host.gpio_ctx().pins.get_mut(&label) {
-            match level {
-                wasi::gpio::gpio::Level::High => pin.set_high(),
-                wasi::gpio::gpio::Level::Low => pin.set_low(),
-            }
+            pin.set_level(level);
         }
     }
 }
@@ -0,0 +1,417 @@
+//! Several precompiled guests in one flash image, each described by a
+//! manifest entry, shared by the compiler (which writes bundles) and the
+//! host (which reads them in place).
+//!
+//! A bundle is the payload of an ordinary `guest_image`, so it is checked,
+//! uploaded and stored in a slot like a single guest. It starts with a
+//! [`HEADER_LEN`]-byte little-endian header:
+//!
+//! | offset | size | field                           |
+//! |--------|------|---------------------------------|
+//! | 0      | 4    | magic, `b"GBDL"`                |
+//! | 4      | 2    | bundle format, [`FORMAT_VERSION`] |
+//! | 6      | 2    | number of entries               |
+//! | 8      | 4    | manifest length in bytes        |
+//! | 12     | 4    | reserved, zero                  |
+//!
+//! The manifest follows, one record per entry:
+//!
+//! | size | field                                          |
+//! |------|------------------------------------------------|
+//! | 4    | component offset from the start of the bundle |
+//! | 4    | component length                               |
+//! | 4    | linear memory limit in bytes, 0 for the host's |
+//! | 4    | fuel per slice, 0 for the host's               |
+//! | 1    | flags, bit 0 autostart                         |
+//! | str  | name                                           |
+//! | str  | entry export, the interface holding `run`      |
+//! | list | interfaces the guest imports from the host     |
+//! | list | GPIO pin labels the guest may drive            |
+//! | list | SPI devices the guest may select               |
+//!
+//! A `str` is a length byte followed by UTF-8, a `list` a count byte followed
+//! by that many `str`s. Components come after the manifest, each starting on
+//! a [`COMPONENT_ALIGN`] boundary.
+//!
+//! Hosts refuse an entry whose interfaces they do not link, before loading
+//! its component.
+#![no_std]
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::fmt;
+
+pub const MAGIC: [u8; 4] = *b"GBDL";
+pub const FORMAT_VERSION: u16 = 1;
+pub const HEADER_LEN: usize = 16;
+// Components are deserialized in place, and Wasmtime expects them 16-byte
+// aligned; the image header keeps the bundle itself aligned in its slot
+pub const COMPONENT_ALIGN: usize = 16;
+
+const AUTOSTART: u8 = 1 << 0;
+
+/// Overrides for the host's per-guest limits; zero keeps the host's own.
+/// The host may still cap them further.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub memory_bytes: u32,
+    pub fuel_slice: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BundleError {
+    BadMagic,
+    UnsupportedFormat(u16),
+    // The manifest or a component runs past the end of the bundle
+    Truncated,
+    BadString { entry: usize },
+    Misaligned { entry: usize },
+    DuplicateName { entry: usize },
+    // Manifest bytes left over after the last entry
+    TrailingManifest(usize),
+    // Only when writing: a string or list does not fit its length byte
+    TooLong { entry: usize },
+    TooManyEntries,
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleError::BadMagic => write!(f, "not a guest bundle (bad magic)"),
+            BundleError::UnsupportedFormat(version) => {
+                write!(f, "unsupported bundle format {version}")
+            }
+            BundleError::Truncated => write!(f, "bundle is truncated"),
+            BundleError::BadString { entry } => {
+                write!(f, "entry {entry}: manifest string is not UTF-8")
+            }
+            BundleError::Misaligned { entry } => {
+                write!(
+                    f,
+                    "entry {entry}: component is not {COMPONENT_ALIGN}-byte aligned"
+                )
+            }
+            BundleError::DuplicateName { entry } => {
+                write!(f, "entry {entry}: name is already used by another entry")
+            }
+            BundleError::TrailingManifest(len) => {
+                write!(f, "manifest has {len} bytes after its last entry")
+            }
+            BundleError::TooLong { entry } => {
+                write!(f, "entry {entry}: a string or list is longer than 255")
+            }
+            BundleError::TooManyEntries => write!(f, "more than {} entries", u16::MAX),
+        }
+    }
+}
+
+impl core::error::Error for BundleError {}
+
+/// One guest as read from a bundle; strings and the component point into it.
+#[derive(Clone, Debug)]
+pub struct Entry<'a> {
+    pub name: &'a str,
+    pub export: &'a str,
+    pub interfaces: Names<'a>,
+    pub limits: Limits,
+    pub gpio: Names<'a>,
+    pub spi: Names<'a>,
+    pub autostart: bool,
+    // `Engine::precompile_component` output
+    pub component: &'a [u8],
+}
+
+/// A `list` from the manifest, validated when the bundle was parsed.
+#[derive(Clone, Debug)]
+pub struct Names<'a> {
+    remaining: u8,
+    bytes: &'a [u8],
+}
+
+impl<'a> Names<'a> {
+    pub fn contains(&self, name: &str) -> bool {
+        self.clone().any(|candidate| candidate == name)
+    }
+}
+
+impl<'a> Iterator for Names<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let mut reader = Reader { bytes: self.bytes };
+        let name = reader.str().ok()?;
+        self.bytes = reader.bytes;
+        Some(name)
+    }
+}
+
+/// Whether `payload` (a guest image's) is a bundle rather than a single component.
+pub fn is_bundle(payload: &[u8]) -> bool {
+    payload.starts_with(&MAGIC)
+}
+
+/// A parsed bundle. Every entry was checked by [`Bundle::parse`], so
+/// iterating them cannot fail.
+#[derive(Clone, Copy, Debug)]
+pub struct Bundle<'a> {
+    count: u16,
+    manifest: &'a [u8],
+    bytes: &'a [u8],
+}
+
+impl<'a> Bundle<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, BundleError> {
+        let header = bytes.get(..HEADER_LEN).ok_or(BundleError::Truncated)?;
+        if header[0..4] != MAGIC {
+            return Err(BundleError::BadMagic);
+        }
+        let format = u16::from_le_bytes([header[4], header[5]]);
+        if format != FORMAT_VERSION {
+            return Err(BundleError::UnsupportedFormat(format));
+        }
+        let count = u16::from_le_bytes([header[6], header[7]]);
+        let manifest_len = read_u32(header, 8) as usize;
+        let manifest = bytes[HEADER_LEN..]
+            .get(..manifest_len)
+            .ok_or(BundleError::Truncated)?;
+
+        let bundle = Self {
+            count,
+            manifest,
+            bytes,
+        };
+        let mut reader = Reader { bytes: manifest };
+        for index in 0..count as usize {
+            let entry = reader.entry(bytes, index)?;
+            if bundle
+                .entries()
+                .take(index)
+                .any(|other| other.name == entry.name)
+            {
+                return Err(BundleError::DuplicateName { entry: index });
+            }
+        }
+        if !reader.bytes.is_empty() {
+            return Err(BundleError::TrailingManifest(reader.bytes.len()));
+        }
+        Ok(bundle)
+    }
+
+    pub fn len(&self) -> usize {
+        self.count as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn entries(&self) -> Entries<'a> {
+        Entries {
+            index: 0,
+            count: self.count as usize,
+            reader: Reader {
+                bytes: self.manifest,
+            },
+            bytes: self.bytes,
+        }
+    }
+
+    pub fn find(&self, name: &str) -> Option<Entry<'a>> {
+        self.entries().find(|entry| entry.name == name)
+    }
+}
+
+pub struct Entries<'a> {
+    index: usize,
+    count: usize,
+    reader: Reader<'a>,
+    bytes: &'a [u8],
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Entry<'a>;
+
+    fn next(&mut self) -> Option<Entry<'a>> {
+        if self.index == self.count {
+            return None;
+        }
+        let entry = self.reader.entry(self.bytes, self.index).ok()?;
+        self.index += 1;
+        Some(entry)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], BundleError> {
+        if self.bytes.len() < len {
+            return Err(BundleError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, BundleError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, BundleError> {
+        Ok(read_u32(self.take(4)?, 0))
+    }
+
+    // `BadString` is reported with the entry index by the caller
+    fn str(&mut self) -> Result<&'a str, BundleError> {
+        let len = self.u8()? as usize;
+        core::str::from_utf8(self.take(len)?).map_err(|_| BundleError::BadString { entry: 0 })
+    }
+
+    fn names(&mut self) -> Result<Names<'a>, BundleError> {
+        let count = self.u8()?;
+        let start = self.bytes;
+        for _ in 0..count {
+            self.str()?;
+        }
+        Ok(Names {
+            remaining: count,
+            bytes: &start[..start.len() - self.bytes.len()],
+        })
+    }
+
+    fn entry(&mut self, bundle: &'a [u8], index: usize) -> Result<Entry<'a>, BundleError> {
+        let at_entry = |err| match err {
+            BundleError::BadString { .. } => BundleError::BadString { entry: index },
+            other => other,
+        };
+        let offset = self.u32()? as usize;
+        let length = self.u32()? as usize;
+        let limits = Limits {
+            memory_bytes: self.u32()?,
+            fuel_slice: self.u32()?,
+        };
+        let flags = self.u8()?;
+        let name = self.str().map_err(at_entry)?;
+        let export = self.str().map_err(at_entry)?;
+        let interfaces = self.names().map_err(at_entry)?;
+        let gpio = self.names().map_err(at_entry)?;
+        let spi = self.names().map_err(at_entry)?;
+
+        if !offset.is_multiple_of(COMPONENT_ALIGN) {
+            return Err(BundleError::Misaligned { entry: index });
+        }
+        let component = offset
+            .checked_add(length)
+            .and_then(|end| bundle.get(offset..end))
+            .ok_or(BundleError::Truncated)?;
+        Ok(Entry {
+            name,
+            export,
+            interfaces,
+            limits,
+            gpio,
+            spi,
+            autostart: flags & AUTOSTART != 0,
+            component,
+        })
+    }
+}
+
+/// What the compiler knows about one guest before it is bundled.
+#[derive(Clone, Copy, Debug)]
+pub struct EntryManifest<'a> {
+    pub name: &'a str,
+    pub export: &'a str,
+    pub interfaces: &'a [&'a str],
+    pub limits: Limits,
+    pub gpio: &'a [&'a str],
+    pub spi: &'a [&'a str],
+    pub autostart: bool,
+}
+
+/// Builds a bundle from `(manifest, precompiled component)` pairs, in order.
+pub fn write_bundle(entries: &[(EntryManifest<'_>, &[u8])]) -> Result<Vec<u8>, BundleError> {
+    let count = u16::try_from(entries.len()).map_err(|_| BundleError::TooManyEntries)?;
+    for (index, (manifest, _)) in entries.iter().enumerate() {
+        if entries[..index]
+            .iter()
+            .any(|(other, _)| other.name == manifest.name)
+        {
+            return Err(BundleError::DuplicateName { entry: index });
+        }
+    }
+
+    // Offsets depend on the manifest's length, so it is sized first
+    let record_len = |manifest: &EntryManifest| {
+        let list = |names: &[&str]| 1 + names.iter().map(|name| 1 + name.len()).sum::<usize>();
+        17 + 1
+            + manifest.name.len()
+            + 1
+            + manifest.export.len()
+            + list(manifest.interfaces)
+            + list(manifest.gpio)
+            + list(manifest.spi)
+    };
+    let manifest_len: usize = entries
+        .iter()
+        .map(|(manifest, _)| record_len(manifest))
+        .sum();
+
+    let mut manifest = Vec::with_capacity(manifest_len);
+    let mut offset = align(HEADER_LEN + manifest_len);
+    for (index, (entry, component)) in entries.iter().enumerate() {
+        let too_long = BundleError::TooLong { entry: index };
+        manifest.extend_from_slice(&(offset as u32).to_le_bytes());
+        manifest.extend_from_slice(&(component.len() as u32).to_le_bytes());
+        manifest.extend_from_slice(&entry.limits.memory_bytes.to_le_bytes());
+        manifest.extend_from_slice(&entry.limits.fuel_slice.to_le_bytes());
+        manifest.push(if entry.autostart { AUTOSTART } else { 0 });
+        push_str(&mut manifest, entry.name).ok_or(too_long)?;
+        push_str(&mut manifest, entry.export).ok_or(too_long)?;
+        for names in [entry.interfaces, entry.gpio, entry.spi] {
+            manifest.push(u8::try_from(names.len()).map_err(|_| too_long)?);
+            for name in names {
+                push_str(&mut manifest, name).ok_or(too_long)?;
+            }
+        }
+        offset = align(offset + component.len());
+    }
+
+    let mut bundle = Vec::with_capacity(offset);
+    bundle.extend_from_slice(&MAGIC);
+    bundle.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bundle.extend_from_slice(&count.to_le_bytes());
+    bundle.extend_from_slice(&(manifest_len as u32).to_le_bytes());
+    bundle.extend_from_slice(&[0; 4]);
+    bundle.extend_from_slice(&manifest);
+    for (_, component) in entries {
+        bundle.resize(align(bundle.len()), 0);
+        bundle.extend_from_slice(component);
+    }
+    Ok(bundle)
+}
+
+fn push_str(out: &mut Vec<u8>, s: &str) -> Option<()> {
+    out.push(u8::try_from(s.len()).ok()?);
+    out.extend_from_slice(s.as_bytes());
+    Some(())
+}
+
+fn align(offset: usize) -> usize {
+    offset.next_multiple_of(COMPONENT_ALIGN)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
@@ -0,0 +1,166 @@
+use guest_bundle::{
+    Bundle, BundleError, COMPONENT_ALIGN, EntryManifest, HEADER_LEN, Limits, is_bundle,
+    write_bundle,
+};
+
+const SENSOR: EntryManifest = EntryManifest {
+    name: "temperature-sensor",
+    export: "my:app/run",
+    interfaces: &["wasi:spi/spi", "my:debug/logging"],
+    limits: Limits {
+        memory_bytes: 64 * 1024,
+        fuel_slice: 0,
+    },
+    gpio: &[],
+    spi: &["sensor"],
+    autostart: true,
+};
+
+const DISPLAY: EntryManifest = EntryManifest {
+    name: "pacman",
+    export: "my:app/run",
+    interfaces: &["wasi:spi/spi", "wasi:gpio/gpio", "wasi:delay/delay"],
+    limits: Limits {
+        memory_bytes: 0,
+        fuel_slice: 50_000,
+    },
+    gpio: &["DC", "RES", "VBATC", "VDDC"],
+    spi: &["display"],
+    autostart: false,
+};
+
+fn sample() -> Vec<u8> {
+    let sensor: Vec<u8> = (0..=255).cycle().take(1001).collect();
+    write_bundle(&[(SENSOR, &sensor), (DISPLAY, b"display component")]).unwrap()
+}
+
+#[test]
+fn round_trips_every_field() {
+    let bytes = sample();
+    assert!(is_bundle(&bytes));
+    let bundle = Bundle::parse(&bytes).unwrap();
+    assert_eq!(bundle.len(), 2);
+
+    let entries: Vec<_> = bundle.entries().collect();
+    for (entry, (manifest, component)) in entries.iter().zip([
+        (SENSOR, (0..=255).cycle().take(1001).collect::<Vec<u8>>()),
+        (DISPLAY, b"display component".to_vec()),
+    ]) {
+        assert_eq!(entry.name, manifest.name);
+        assert_eq!(entry.export, manifest.export);
+        assert_eq!(
+            entry.interfaces.clone().collect::<Vec<_>>(),
+            manifest.interfaces
+        );
+        assert_eq!(entry.limits, manifest.limits);
+        assert_eq!(entry.gpio.clone().collect::<Vec<_>>(), manifest.gpio);
+        assert_eq!(entry.spi.clone().collect::<Vec<_>>(), manifest.spi);
+        assert_eq!(entry.autostart, manifest.autostart);
+        assert_eq!(entry.component, component.as_slice());
+    }
+}
+
+#[test]
+fn components_are_aligned_for_deserializing_in_place() {
+    let bytes = sample();
+    let bundle = Bundle::parse(&bytes).unwrap();
+    for entry in bundle.entries() {
+        let offset = entry.component.as_ptr() as usize - bytes.as_ptr() as usize;
+        assert_eq!(offset % COMPONENT_ALIGN, 0);
+    }
+}
+
+#[test]
+fn finds_entries_and_permissions_by_name() {
+    let bytes = sample();
+    let bundle = Bundle::parse(&bytes).unwrap();
+
+    let display = bundle.find("pacman").unwrap();
+    assert!(display.gpio.contains("VDDC"));
+    assert!(!display.gpio.contains("VDD"));
+    assert!(bundle.find("ball-screensaver").is_none());
+}
+
+#[test]
+fn empty_bundle_is_valid() {
+    let bytes = write_bundle(&[]).unwrap();
+    assert_eq!(bytes.len(), HEADER_LEN);
+    assert!(Bundle::parse(&bytes).unwrap().is_empty());
+}
+
+#[test]
+fn rejects_other_data() {
+    assert_eq!(Bundle::parse(b"GIMG").unwrap_err(), BundleError::Truncated);
+    assert_eq!(
+        Bundle::parse(&[0; HEADER_LEN]).unwrap_err(),
+        BundleError::BadMagic
+    );
+    assert!(!is_bundle(b"\0asm"));
+
+    let mut bytes = sample();
+    bytes[4] = 2;
+    assert_eq!(
+        Bundle::parse(&bytes).unwrap_err(),
+        BundleError::UnsupportedFormat(2)
+    );
+}
+
+#[test]
+fn rejects_truncated_bundles() {
+    let bytes = sample();
+    // Cut into the manifest, then into the last component
+    assert_eq!(
+        Bundle::parse(&bytes[..HEADER_LEN + 10]).unwrap_err(),
+        BundleError::Truncated
+    );
+    assert_eq!(
+        Bundle::parse(&bytes[..bytes.len() - 1]).unwrap_err(),
+        BundleError::Truncated
+    );
+}
+
+#[test]
+fn rejects_bad_manifests() {
+    let mut bytes = sample();
+    // First entry's name, after the fixed fields and its length byte
+    bytes[HEADER_LEN + 18] = 0xFF;
+    assert_eq!(
+        Bundle::parse(&bytes).unwrap_err(),
+        BundleError::BadString { entry: 0 }
+    );
+
+    let mut bytes = sample();
+    bytes[HEADER_LEN] += 1;
+    assert_eq!(
+        Bundle::parse(&bytes).unwrap_err(),
+        BundleError::Misaligned { entry: 0 }
+    );
+}
+
+#[test]
+fn rejects_padded_manifests() {
+    // Four bytes of padding counted into an empty bundle's manifest
+    let mut bytes = write_bundle(&[]).unwrap();
+    bytes.extend_from_slice(&[0; 4]);
+    bytes[8..12].copy_from_slice(&4u32.to_le_bytes());
+    assert_eq!(
+        Bundle::parse(&bytes).unwrap_err(),
+        BundleError::TrailingManifest(4)
+    );
+}
+
+#[test]
+fn writer_refuses_what_the_format_cannot_hold() {
+    let twice = write_bundle(&[(SENSOR, b"a"), (SENSOR, b"b")]);
+    assert_eq!(twice.unwrap_err(), BundleError::DuplicateName { entry: 1 });
+
+    let long_name = "x".repeat(256);
+    let long = EntryManifest {
+        name: &long_name,
+        ..SENSOR
+    };
+    assert_eq!(
+        write_bundle(&[(SENSOR, b"a"), (long, b"b")]).unwrap_err(),
+        BundleError::TooLong { entry: 1 }
+    );
+}
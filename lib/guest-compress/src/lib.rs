@@ -0,0 +1,271 @@
+//! Optional compression of precompiled components, shared by the compiler
+//! (which compresses them) and the host (which inflates them into heap).
+//!
+//! A compressed component starts with a [`HEADER_LEN`]-byte little-endian
+//! header followed by a raw DEFLATE stream:
+//!
+//! | offset | size | field                          |
+//! |--------|------|--------------------------------|
+//! | 0      | 4    | magic, `b"GZCP"`               |
+//! | 4      | 2    | method, [`METHOD_DEFLATE`]     |
+//! | 6      | 2    | reserved, zero                 |
+//! | 8      | 4    | length once inflated           |
+//! | 12     | 4    | length of the DEFLATE stream   |
+//!
+//! Wasmtime's own artifacts start with the ELF magic, so a host checks
+//! [`is_compressed`] and uses anything else as it is. Compression trades
+//! flash for heap: an uncompressed component runs in place from flash, an
+//! inflated one lives in the heap for as long as it is loaded.
+#![no_std]
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+use miniz_oxide::inflate::TINFLStatus;
+use miniz_oxide::inflate::core::inflate_flags::{
+    TINFL_FLAG_HAS_MORE_INPUT, TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF,
+};
+use miniz_oxide::inflate::core::{DecompressorOxide, decompress as inflate};
+
+pub const MAGIC: [u8; 4] = *b"GZCP";
+pub const METHOD_DEFLATE: u16 = 1;
+pub const HEADER_LEN: usize = 16;
+// Inflated components are deserialized in place, and Wasmtime expects them 16-byte aligned
+pub const ALIGN: usize = 16;
+
+// miniz's highest level; inflating is no slower for it
+const LEVEL: u8 = 10;
+// Input handed to the inflater at a time by `decompress`
+const CHUNK: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressError {
+    BadMagic,
+    UnsupportedMethod(u16),
+    // The header or the stream runs past the end of the bytes given
+    Truncated,
+    // Inflating would need more than the caller allows
+    TooLarge { length: u32, max: usize },
+    // The heap could not hold the inflated component, e.g. because it is fragmented
+    OutOfMemory { length: u32 },
+    Corrupt,
+    // The stream inflates to another length than the header records
+    LengthMismatch { expected: u32 },
+}
+
+impl fmt::Display for CompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressError::BadMagic => write!(f, "not a compressed component (bad magic)"),
+            CompressError::UnsupportedMethod(method) => {
+                write!(f, "unsupported compression method {method}")
+            }
+            CompressError::Truncated => write!(f, "compressed component is truncated"),
+            CompressError::TooLarge { length, max } => {
+                write!(f, "inflates to {length} bytes, at most {max} are available")
+            }
+            CompressError::OutOfMemory { length } => {
+                write!(f, "no room in the heap for the {length} inflated bytes")
+            }
+            CompressError::Corrupt => write!(f, "compressed component is corrupt"),
+            CompressError::LengthMismatch { expected } => {
+                write!(
+                    f,
+                    "does not inflate to the {expected} bytes its header records"
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for CompressError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub method: u16,
+    pub original_len: u32,
+    pub stream_len: u32,
+}
+
+impl Header {
+    /// Checks the header and that the whole stream is present.
+    pub fn parse(bytes: &[u8]) -> Result<Self, CompressError> {
+        let header = bytes.get(..HEADER_LEN).ok_or(CompressError::Truncated)?;
+        if header[0..4] != MAGIC {
+            return Err(CompressError::BadMagic);
+        }
+        let method = u16::from_le_bytes([header[4], header[5]]);
+        if method != METHOD_DEFLATE {
+            return Err(CompressError::UnsupportedMethod(method));
+        }
+        let header = Header {
+            method,
+            original_len: read_u32(header, 8),
+            stream_len: read_u32(header, 12),
+        };
+        if bytes.len() - HEADER_LEN < header.stream_len as usize {
+            return Err(CompressError::Truncated);
+        }
+        Ok(header)
+    }
+}
+
+pub fn is_compressed(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// Header and DEFLATE stream for `component`.
+pub fn compress(component: &[u8]) -> Vec<u8> {
+    let stream = miniz_oxide::deflate::compress_to_vec(component, LEVEL);
+    let mut out = Vec::with_capacity(HEADER_LEN + stream.len());
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&METHOD_DEFLATE.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&(component.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(stream.len() as u32).to_le_bytes());
+    out.extend_from_slice(&stream);
+    out
+}
+
+/// Inflates `bytes` into a fresh [`ALIGN`]ed buffer, refusing anything that
+/// would inflate to more than `max_len` bytes before allocating it.
+pub fn decompress(bytes: &[u8], max_len: usize) -> Result<AlignedBytes, CompressError> {
+    let header = Header::parse(bytes)?;
+    let stream = &bytes[HEADER_LEN..HEADER_LEN + header.stream_len as usize];
+    let mut inflater = Inflater::new(&header, max_len)?;
+    let mut chunks = stream.chunks(CHUNK).peekable();
+    while let Some(chunk) = chunks.next() {
+        inflater.push(chunk, chunks.peek().is_none())?;
+    }
+    inflater.finish()
+}
+
+/// Inflates a stream handed over in pieces, e.g. as it is read from flash
+/// or received, into a buffer sized from its header.
+pub struct Inflater {
+    // Around 11 KiB of Huffman tables, too much for an embassy task's stack
+    state: Box<DecompressorOxide>,
+    out: AlignedBytes,
+    pos: usize,
+    done: bool,
+}
+
+impl Inflater {
+    pub fn new(header: &Header, max_len: usize) -> Result<Self, CompressError> {
+        let length = header.original_len;
+        if length as usize > max_len {
+            return Err(CompressError::TooLarge {
+                length,
+                max: max_len,
+            });
+        }
+        Ok(Inflater {
+            state: Box::default(),
+            out: AlignedBytes::zeroed(length as usize)
+                .ok_or(CompressError::OutOfMemory { length })?,
+            pos: 0,
+            done: false,
+        })
+    }
+
+    /// `last` marks the final piece of the stream.
+    pub fn push(&mut self, mut input: &[u8], last: bool) -> Result<(), CompressError> {
+        let expected = CompressError::LengthMismatch {
+            expected: self.out.len() as u32,
+        };
+        if self.done {
+            // Bytes left over once the stream has ended
+            if input.is_empty() {
+                return Ok(());
+            }
+            return Err(CompressError::Corrupt);
+        }
+        let mut flags = TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+        if !last {
+            flags |= TINFL_FLAG_HAS_MORE_INPUT;
+        }
+        loop {
+            let (status, consumed, written) =
+                inflate(&mut self.state, input, &mut self.out, self.pos, flags);
+            self.pos += written;
+            input = &input[consumed..];
+            match status {
+                TINFLStatus::Done if input.is_empty() => {
+                    self.done = true;
+                    return Ok(());
+                }
+                TINFLStatus::Done => return Err(CompressError::Corrupt),
+                TINFLStatus::NeedsMoreInput if input.is_empty() => return Ok(()),
+                TINFLStatus::NeedsMoreInput => {}
+                TINFLStatus::FailedCannotMakeProgress => return Err(CompressError::Truncated),
+                // The buffer is full; fine if all that is left is the end of
+                // the stream, which may still be in the next piece
+                TINFLStatus::HasMoreOutput if input.is_empty() && !last => return Ok(()),
+                TINFLStatus::HasMoreOutput if consumed == 0 => return Err(expected),
+                TINFLStatus::HasMoreOutput => {}
+                _ => return Err(CompressError::Corrupt),
+            }
+        }
+    }
+
+    pub fn finish(self) -> Result<AlignedBytes, CompressError> {
+        if !self.done {
+            return Err(CompressError::Truncated);
+        }
+        if self.pos != self.out.len() {
+            return Err(CompressError::LengthMismatch {
+                expected: self.out.len() as u32,
+            });
+        }
+        Ok(self.out)
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, align(16))]
+struct Block([u8; ALIGN]);
+
+/// Heap bytes starting on an [`ALIGN`] boundary.
+pub struct AlignedBytes {
+    blocks: Vec<Block>,
+    len: usize,
+}
+
+impl AlignedBytes {
+    // `None` when the allocation fails, rather than aborting like `vec!` would
+    fn zeroed(len: usize) -> Option<Self> {
+        let count = len.div_ceil(ALIGN);
+        let mut blocks = Vec::new();
+        blocks.try_reserve_exact(count).ok()?;
+        blocks.resize(count, Block([0; ALIGN]));
+        Some(AlignedBytes { blocks, len })
+    }
+}
+
+impl Deref for AlignedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // Blocks are plain bytes, and there are at least `len` of them
+        unsafe { core::slice::from_raw_parts(self.blocks.as_ptr().cast(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBytes {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.blocks.as_mut_ptr().cast(), self.len) }
+    }
+}
+
+impl AsRef<[u8]> for AlignedBytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
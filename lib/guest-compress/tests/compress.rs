@@ -0,0 +1,114 @@
+use guest_compress::{
+    ALIGN, CompressError, HEADER_LEN, Header, Inflater, MAGIC, compress, decompress, is_compressed,
+};
+
+// Repetitive like real code, with some noise so not everything matches
+fn sample() -> Vec<u8> {
+    let mut state = 0x1234_5678u32;
+    (0..50_000u32)
+        .map(|i| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            if i % 7 == 0 {
+                (state >> 24) as u8
+            } else {
+                (i % 61) as u8
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn round_trips_aligned() {
+    let original = sample();
+    let compressed = compress(&original);
+    assert!(is_compressed(&compressed));
+    assert!(compressed.len() < original.len() / 2);
+
+    let header = Header::parse(&compressed).unwrap();
+    assert_eq!(header.original_len as usize, original.len());
+    assert_eq!(header.stream_len as usize, compressed.len() - HEADER_LEN);
+
+    let inflated = decompress(&compressed, original.len()).unwrap();
+    assert_eq!(&inflated[..], &original[..]);
+    assert_eq!(inflated.as_ptr() as usize % ALIGN, 0);
+}
+
+#[test]
+fn inflates_from_pieces_of_any_size() {
+    let original = sample();
+    let compressed = compress(&original);
+    let header = Header::parse(&compressed).unwrap();
+    let stream = &compressed[HEADER_LEN..];
+    for size in [1, 3, 100, stream.len()] {
+        let mut inflater = Inflater::new(&header, usize::MAX).unwrap();
+        let mut pieces = stream.chunks(size).peekable();
+        while let Some(piece) = pieces.next() {
+            inflater.push(piece, pieces.peek().is_none()).unwrap();
+        }
+        assert_eq!(&inflater.finish().unwrap()[..], &original[..], "{size}");
+    }
+}
+
+#[test]
+fn uncompressed_artifacts_are_told_apart() {
+    assert!(!is_compressed(b"\x7fELF\x01\x01\x01"));
+    assert!(!is_compressed(b"GBDL"));
+    assert!(!is_compressed(&[]));
+    assert_eq!(
+        Header::parse(b"\x7fELF0123456789ab"),
+        Err(CompressError::BadMagic)
+    );
+}
+
+#[test]
+fn refuses_what_it_cannot_inflate() {
+    let original = sample();
+    let compressed = compress(&original);
+
+    assert_eq!(
+        decompress(&compressed, original.len() - 1).err(),
+        Some(CompressError::TooLarge {
+            length: original.len() as u32,
+            max: original.len() - 1,
+        })
+    );
+    assert_eq!(
+        decompress(&compressed[..compressed.len() - 1], usize::MAX).err(),
+        Some(CompressError::Truncated)
+    );
+
+    let mut method = compressed.clone();
+    method[4] = 2;
+    assert_eq!(
+        decompress(&method, usize::MAX).err(),
+        Some(CompressError::UnsupportedMethod(2))
+    );
+
+    // A header that promises less than the stream holds
+    let mut short = compressed.clone();
+    short[8..12].copy_from_slice(&100u32.to_le_bytes());
+    assert_eq!(
+        decompress(&short, usize::MAX).err(),
+        Some(CompressError::LengthMismatch { expected: 100 })
+    );
+
+    let mut long = compressed.clone();
+    long[8..12].copy_from_slice(&(original.len() as u32 + 1).to_le_bytes());
+    assert_eq!(
+        decompress(&long, usize::MAX).err(),
+        Some(CompressError::LengthMismatch {
+            expected: original.len() as u32 + 1
+        })
+    );
+
+    let mut corrupt = compressed.clone();
+    corrupt[HEADER_LEN..HEADER_LEN + 8].copy_from_slice(&[0xff; 8]);
+    assert!(decompress(&corrupt, usize::MAX).is_err());
+}
+
+#[test]
+fn empty_components_round_trip() {
+    let compressed = compress(&[]);
+    assert!(compressed.starts_with(&MAGIC));
+    assert!(decompress(&compressed, 0).unwrap().is_empty());
+}
@@ -0,0 +1,258 @@
+//! Layout of a precompiled guest as stored in the firmware's `GUESTS` flash
+//! region, shared by the compiler (which writes images) and the host (which
+//! reads them in place).
+//!
+//! Each slot starts with a [`HEADER_LEN`]-byte little-endian header followed
+//! by the `Engine::precompile_component` output:
+//!
+//! | offset | size | field                             |
+//! |--------|------|-----------------------------------|
+//! | 0      | 4    | magic, `b"GIMG"`                  |
+//! | 4      | 2    | header format, [`HEADER_VERSION`] |
+//! | 6      | 2    | reserved, zero                    |
+//! | 8      | 4    | guest version                     |
+//! | 12     | 4    | payload length in bytes           |
+//! | 16     | 4    | CRC-32 of the payload             |
+//! | 20     | 4    | engine configuration fingerprint  |
+//! | 24     | 4    | flags, bit 0 signed               |
+//! | 28     | 4    | reserved, zero                    |
+//!
+//! A signed image is followed by a [`SIGNATURE_LEN`]-byte Ed25519 signature
+//! over the header and payload, so it also covers the fields the CRC does
+//! not. The host only runs images signed with its key, see [`verify_image`].
+#![no_std]
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use ed25519_dalek::{Signature, Signer};
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+pub const MAGIC: [u8; 4] = *b"GIMG";
+pub const HEADER_VERSION: u16 = 3;
+// Keeps the payload 16-byte aligned when the slot is, as Wasmtime expects
+pub const HEADER_LEN: usize = 32;
+pub const SIGNATURE_LEN: usize = 64;
+
+const SIGNED: u32 = 1 << 0;
+
+/// The `GUESTS` region in pico2-quick's memory.x is divided into slots of
+/// this size, one guest each, so a guest can be reflashed without touching
+/// the others.
+pub const SLOT_SIZE: usize = 512 * 1024;
+pub const SLOT_COUNT: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageHeader {
+    pub version: u32,
+    pub length: u32,
+    pub checksum: u32,
+    // `engine_config::FINGERPRINT` of the compiler that produced the payload
+    pub engine: u32,
+    // A signature follows the payload
+    pub signed: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageError {
+    // The slot still holds erased flash, i.e. nothing was ever written to it
+    Empty,
+    BadMagic,
+    UnsupportedHeaderVersion(u16),
+    Truncated { length: u32, available: usize },
+    ChecksumMismatch { expected: u32, actual: u32 },
+    EngineMismatch { image: u32, host: u32 },
+    Unsigned,
+    BadSignature,
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Empty => write!(f, "no guest image in slot"),
+            ImageError::BadMagic => write!(f, "not a guest image (bad magic)"),
+            ImageError::UnsupportedHeaderVersion(version) => {
+                write!(f, "unsupported image header version {version}")
+            }
+            ImageError::Truncated { length, available } => write!(
+                f,
+                "image claims {length} bytes but only {available} fit in the slot"
+            ),
+            ImageError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch (header {expected:#010x}, payload {actual:#010x})"
+            ),
+            ImageError::EngineMismatch { image, host } => write!(
+                f,
+                "image was compiled for engine config {image:#010x}, host runs {host:#010x}"
+            ),
+            ImageError::Unsigned => write!(f, "image is not signed"),
+            ImageError::BadSignature => {
+                write!(f, "image signature does not match the host's key")
+            }
+        }
+    }
+}
+
+impl core::error::Error for ImageError {}
+
+impl ImageHeader {
+    pub fn for_payload(version: u32, engine: u32, payload: &[u8]) -> Self {
+        Self {
+            version,
+            length: payload.len() as u32,
+            checksum: crc32(payload),
+            engine,
+            signed: false,
+        }
+    }
+
+    /// Bytes the whole image takes in its slot, signature included.
+    pub fn image_len(&self) -> usize {
+        let signature = if self.signed { SIGNATURE_LEN } else { 0 };
+        HEADER_LEN + self.length as usize + signature
+    }
+
+    /// Refuses a payload precompiled with different engine settings; checked
+    /// before Wasmtime ever sees it.
+    pub fn check_engine(&self, host: u32) -> Result<(), ImageError> {
+        if self.engine != host {
+            return Err(ImageError::EngineMismatch {
+                image: self.engine,
+                host,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..6].copy_from_slice(&HEADER_VERSION.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.version.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.length.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.engine.to_le_bytes());
+        let flags = if self.signed { SIGNED } else { 0 };
+        bytes[24..28].copy_from_slice(&flags.to_le_bytes());
+        bytes
+    }
+
+    /// Parses the header at the start of `bytes` without looking at the payload.
+    pub fn parse(bytes: &[u8]) -> Result<Self, ImageError> {
+        let Some(header) = bytes.get(..HEADER_LEN) else {
+            return Err(ImageError::Truncated {
+                length: HEADER_LEN as u32,
+                available: bytes.len(),
+            });
+        };
+        if header[0..4] == [0xFF; 4] {
+            return Err(ImageError::Empty);
+        }
+        if header[0..4] != MAGIC {
+            return Err(ImageError::BadMagic);
+        }
+        let header_version = u16::from_le_bytes([header[4], header[5]]);
+        if header_version != HEADER_VERSION {
+            return Err(ImageError::UnsupportedHeaderVersion(header_version));
+        }
+        Ok(Self {
+            version: read_u32(header, 8),
+            length: read_u32(header, 12),
+            checksum: read_u32(header, 16),
+            engine: read_u32(header, 20),
+            signed: read_u32(header, 24) & SIGNED != 0,
+        })
+    }
+}
+
+/// Validates the image at the start of `slot` and returns its header and payload.
+pub fn read_image(slot: &[u8]) -> Result<(ImageHeader, &[u8]), ImageError> {
+    let header = ImageHeader::parse(slot)?;
+    let body = &slot[HEADER_LEN..];
+    let payload = body
+        .get(..header.length as usize)
+        .ok_or(ImageError::Truncated {
+            length: header.length,
+            available: body.len(),
+        })?;
+    let actual = crc32(payload);
+    if actual != header.checksum {
+        return Err(ImageError::ChecksumMismatch {
+            expected: header.checksum,
+            actual,
+        });
+    }
+    Ok((header, payload))
+}
+
+/// Like [`read_image`], but only accepts an image signed with the key
+/// matching `key`. `Component::deserialize` trusts its input completely, so
+/// the host checks this first.
+pub fn verify_image<'a>(
+    slot: &'a [u8],
+    key: &VerifyingKey,
+) -> Result<(ImageHeader, &'a [u8]), ImageError> {
+    let (header, payload) = read_image(slot)?;
+    if !header.signed {
+        return Err(ImageError::Unsigned);
+    }
+    let signed_len = HEADER_LEN + payload.len();
+    // What follows the payload, which should start with the signature
+    let rest = &slot[signed_len..];
+    let signature = rest.get(..SIGNATURE_LEN).ok_or(ImageError::Truncated {
+        length: header.length + SIGNATURE_LEN as u32,
+        available: payload.len() + rest.len(),
+    })?;
+    let signature = Signature::from_slice(signature).map_err(|_| ImageError::BadSignature)?;
+    key.verify_strict(&slot[..signed_len], &signature)
+        .map_err(|_| ImageError::BadSignature)?;
+    Ok((header, payload))
+}
+
+/// Builds the bytes to flash into a slot: header followed by `payload`.
+pub fn write_image(version: u32, engine: u32, payload: &[u8]) -> Vec<u8> {
+    let header = ImageHeader::for_payload(version, engine, payload);
+    let mut image = Vec::with_capacity(header.image_len());
+    image.extend_from_slice(&header.to_bytes());
+    image.extend_from_slice(payload);
+    image
+}
+
+/// Like [`write_image`], with the signature the host checks appended.
+pub fn write_signed_image(version: u32, engine: u32, payload: &[u8], key: &SigningKey) -> Vec<u8> {
+    let header = ImageHeader {
+        signed: true,
+        ..ImageHeader::for_payload(version, engine, payload)
+    };
+    let mut image = Vec::with_capacity(header.image_len());
+    image.extend_from_slice(&header.to_bytes());
+    image.extend_from_slice(payload);
+    let signature = key.sign(&image);
+    image.extend_from_slice(&signature.to_bytes());
+    image
+}
+
+/// CRC-32 (IEEE 802.3), as computed by zlib and `crc32` on the command line.
+pub fn crc32(data: &[u8]) -> u32 {
+    // Bitwise rather than table driven: checked once per boot, and it keeps 1 KiB out of flash
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
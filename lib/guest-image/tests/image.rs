@@ -0,0 +1,176 @@
+use guest_image::{
+    HEADER_LEN, ImageError, ImageHeader, SIGNATURE_LEN, SigningKey, crc32, read_image,
+    verify_image, write_image, write_signed_image,
+};
+
+const ENGINE: u32 = 0x1234_5678;
+
+#[test]
+fn crc32_matches_the_standard_check_value() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(crc32(b""), 0);
+}
+
+#[test]
+fn round_trips_through_a_slot() {
+    let payload: Vec<u8> = (0..=255).cycle().take(1000).collect();
+    let mut slot = write_image(7, ENGINE, &payload);
+    // The rest of the slot is erased flash
+    slot.resize(4096, 0xFF);
+
+    let (header, read) = read_image(&slot).unwrap();
+    assert_eq!(header.version, 7);
+    assert_eq!(header.length, 1000);
+    assert_eq!(header.engine, ENGINE);
+    assert_eq!(read, payload.as_slice());
+}
+
+#[test]
+fn erased_slot_is_empty() {
+    assert_eq!(read_image(&[0xFF; 64]), Err(ImageError::Empty));
+}
+
+#[test]
+fn rejects_other_data() {
+    assert_eq!(read_image(&[0; 64]), Err(ImageError::BadMagic));
+
+    let mut image = write_image(1, ENGINE, b"payload");
+    // Images from before the engine fingerprint was recorded
+    image[4] = 1;
+    assert_eq!(
+        read_image(&image),
+        Err(ImageError::UnsupportedHeaderVersion(1))
+    );
+}
+
+#[test]
+fn detects_corrupted_payload() {
+    let mut image = write_image(1, ENGINE, b"precompiled component");
+    image[HEADER_LEN + 3] ^= 0x01;
+
+    assert!(matches!(
+        read_image(&image),
+        Err(ImageError::ChecksumMismatch { .. })
+    ));
+}
+
+#[test]
+fn detects_payload_longer_than_slot() {
+    let image = write_image(1, ENGINE, &[0xAB; 100]);
+
+    assert_eq!(
+        read_image(&image[..HEADER_LEN + 50]),
+        Err(ImageError::Truncated {
+            length: 100,
+            available: 50,
+        })
+    );
+    assert_eq!(
+        ImageHeader::parse(&image[..10]),
+        Err(ImageError::Truncated {
+            length: HEADER_LEN as u32,
+            available: 10,
+        })
+    );
+}
+
+#[test]
+fn checks_engine_fingerprint() {
+    let image = write_image(1, ENGINE, b"payload");
+    let (header, _) = read_image(&image).unwrap();
+
+    assert_eq!(header.check_engine(ENGINE), Ok(()));
+    assert_eq!(
+        header.check_engine(0xDEAD_BEEF),
+        Err(ImageError::EngineMismatch {
+            image: ENGINE,
+            host: 0xDEAD_BEEF,
+        })
+    );
+}
+
+// Fixed seeds, so failures reproduce; the compiler's `keygen` uses random ones
+fn test_key(seed: u8) -> SigningKey {
+    SigningKey::from_bytes(&[seed; 32])
+}
+
+#[test]
+fn signed_image_verifies_with_the_matching_key() {
+    let key = test_key(1);
+    let mut slot = write_signed_image(3, ENGINE, b"precompiled component", &key);
+    let (header, _) = read_image(&slot).unwrap();
+    assert!(header.signed);
+    assert_eq!(header.image_len(), slot.len());
+    slot.resize(4096, 0xFF);
+
+    let (header, payload) = verify_image(&slot, &key.verifying_key()).unwrap();
+    assert_eq!(header.version, 3);
+    assert_eq!(payload, b"precompiled component");
+}
+
+#[test]
+fn unsigned_and_foreign_images_are_refused() {
+    let key = test_key(1).verifying_key();
+
+    let unsigned = write_image(1, ENGINE, b"payload");
+    assert_eq!(verify_image(&unsigned, &key), Err(ImageError::Unsigned));
+    // Still a valid image for tools that do not check signatures
+    assert!(read_image(&unsigned).is_ok());
+
+    let foreign = write_signed_image(1, ENGINE, b"payload", &test_key(2));
+    assert_eq!(verify_image(&foreign, &key), Err(ImageError::BadSignature));
+}
+
+#[test]
+fn signature_covers_what_the_checksum_does_not() {
+    let key = test_key(1);
+    let mut image = write_signed_image(1, ENGINE, b"payload", &key);
+    // The guest version is outside the CRC
+    image[8] = 2;
+    assert!(read_image(&image).is_ok());
+    assert_eq!(
+        verify_image(&image, &key.verifying_key()),
+        Err(ImageError::BadSignature)
+    );
+
+    let mut image = write_signed_image(1, ENGINE, b"payload", &key);
+    let last = image.len() - 1;
+    image[last] ^= 0x01;
+    assert_eq!(
+        verify_image(&image, &key.verifying_key()),
+        Err(ImageError::BadSignature)
+    );
+}
+
+#[test]
+fn missing_signature_is_truncation() {
+    let key = test_key(1);
+    let image = write_signed_image(1, ENGINE, &[0xAB; 100], &key);
+
+    assert_eq!(
+        verify_image(
+            &image[..HEADER_LEN + 100 + SIGNATURE_LEN / 2],
+            &key.verifying_key()
+        ),
+        Err(ImageError::Truncated {
+            length: 100 + SIGNATURE_LEN as u32,
+            available: 100 + SIGNATURE_LEN / 2,
+        })
+    );
+    // Cut into the payload, so the signature is not even reached
+    assert_eq!(
+        verify_image(&image[..HEADER_LEN + 40], &key.verifying_key()),
+        Err(ImageError::Truncated {
+            length: 100,
+            available: 40,
+        })
+    );
+    // Only the signature's last byte is missing
+    assert_eq!(
+        verify_image(&image[..image.len() - 1], &key.verifying_key()),
+        Err(ImageError::Truncated {
+            length: 100 + SIGNATURE_LEN as u32,
+            available: 100 + SIGNATURE_LEN - 1,
+        })
+    );
+}
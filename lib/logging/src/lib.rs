@@ -0,0 +1,267 @@
+#![no_std]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use wasmtime::component::{HasData, Linker};
+
+mod sink;
+mod throttle;
+
+pub use sink::{CaptureSink, CapturedRecord, LogSink, RingBufferSink};
+pub use throttle::RateLimit;
+
+use throttle::{Admission, Deduplicator, RateLimiter};
+
+#[cfg(feature = "defmt")]
+pub use sink::DefmtSink;
+#[cfg(feature = "log")]
+pub use sink::LogCrateSink;
+#[cfg(feature = "std")]
+pub use sink::StdoutSink;
+#[cfg(feature = "tracing")]
+pub use sink::TracingSink;
+
+wasmtime::component::bindgen!({
+    path: "../../wit/debug.wit",
+    world: "debug-logging-host",
+    additional_derives: [PartialEq, Eq],
+});
+
+pub use my::debug::logging::{Field, Level};
+
+/// A single guest log record, borrowed for the duration of a sink call.
+pub struct LogRecord<'a> {
+    pub guest: &'a str,
+    pub level: Level,
+    pub target: &'a str,
+    pub msg: &'a str,
+    pub fields: &'a [Field],
+}
+
+// Renders as "[guest] target: msg key=value ...", skipping empty parts
+impl fmt::Display for LogRecord<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.guest.is_empty() {
+            write!(f, "[{}] ", self.guest)?;
+        }
+        if !self.target.is_empty() {
+            write!(f, "{}: ", self.target)?;
+        }
+        f.write_str(self.msg)?;
+        for field in self.fields {
+            write!(f, " {}={}", field.key, field.value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Counters for what happened to the records a guest produced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LogStats {
+    pub emitted: u32,
+    pub filtered: u32,
+    pub rate_limited: u32,
+    pub deduplicated: u32,
+}
+
+pub struct LoggingCtx {
+    // Prefixed to every record so output from several guests can be told apart
+    pub guest_name: String,
+    // Records below this level are dropped before reaching the sink
+    pub min_level: Level,
+    pub sink: Box<dyn LogSink>,
+    limiter: Option<RateLimiter>,
+    dedup: Option<Deduplicator>,
+    // Sees every record that passed the level filter and dedup, even those the
+    // limiter drops, so a repeated line takes one slot and not the whole ring
+    recent: Option<RingBufferSink>,
+    stats: LogStats,
+}
+
+impl LoggingCtx {
+    pub fn new(guest_name: impl Into<String>, sink: impl LogSink + 'static) -> Self {
+        Self {
+            guest_name: guest_name.into(),
+            min_level: Level::Info,
+            sink: Box::new(sink),
+            limiter: None,
+            dedup: None,
+            recent: None,
+            stats: LogStats::default(),
+        }
+    }
+
+    pub fn with_min_level(mut self, level: Level) -> Self {
+        self.min_level = level;
+        self
+    }
+
+    /// `clock` returns the current time in milliseconds.
+    pub fn with_rate_limit(
+        mut self,
+        limit: RateLimit,
+        clock: impl Fn() -> u64 + Send + 'static,
+    ) -> Self {
+        self.limiter = Some(RateLimiter::new(limit, Box::new(clock)));
+        self
+    }
+
+    pub fn with_dedup(mut self) -> Self {
+        self.dedup = Some(Deduplicator::default());
+        self
+    }
+
+    /// Keeps the last `capacity` records in RAM for [`LoggingCtx::dump_recent`].
+    pub fn with_retention(mut self, capacity: usize) -> Self {
+        self.recent = Some(RingBufferSink::new(capacity));
+        self
+    }
+
+    pub fn stats(&self) -> LogStats {
+        self.stats
+    }
+
+    pub fn enabled(&self, level: Level) -> bool {
+        level as u8 >= self.min_level as u8
+    }
+
+    pub fn emit(&mut self, level: Level, target: &str, msg: &str, fields: &[Field]) {
+        if !self.enabled(level) {
+            self.stats.filtered += 1;
+            return;
+        }
+        let record = LogRecord {
+            guest: &self.guest_name,
+            level,
+            target,
+            msg,
+            fields,
+        };
+
+        if let Some(dedup) = &mut self.dedup {
+            if dedup.is_repeat(&record) {
+                self.stats.deduplicated += 1;
+                return;
+            }
+            if let Some((last, repeats)) = dedup.take_repeats() {
+                report_repeats(self.sink.as_mut(), self.recent.as_mut(), last, repeats);
+            }
+            dedup.remember(&record);
+        }
+
+        if let Some(recent) = &mut self.recent {
+            recent.emit(&record);
+        }
+
+        if let Some(limiter) = &mut self.limiter {
+            match limiter.admit() {
+                Admission::Dropped => {
+                    self.stats.rate_limited += 1;
+                    return;
+                }
+                Admission::Allowed { dropped } if dropped > 0 => {
+                    report_dropped(self.sink.as_mut(), &self.guest_name, dropped);
+                }
+                Admission::Allowed { .. } => {}
+            }
+        }
+
+        self.sink.emit(&record);
+        self.stats.emitted += 1;
+    }
+
+    /// Reports any pending repeat and drop counts without waiting for the next
+    /// record. The next record is never taken for a repeat of an earlier one,
+    /// so a restarted guest's first line is not swallowed.
+    pub fn flush(&mut self) {
+        if let Some(dedup) = &mut self.dedup {
+            if let Some((last, repeats)) = dedup.take_repeats() {
+                report_repeats(self.sink.as_mut(), self.recent.as_mut(), last, repeats);
+            }
+            dedup.forget();
+        }
+        if let Some(limiter) = &mut self.limiter {
+            let dropped = limiter.take_dropped();
+            if dropped > 0 {
+                report_dropped(self.sink.as_mut(), &self.guest_name, dropped);
+            }
+        }
+    }
+
+    /// Flushes pending counts, then replays the retained records into `sink`,
+    /// oldest first. Meant to be called after a trap to show what led up to it.
+    pub fn dump_recent(&mut self, sink: &mut dyn LogSink) {
+        self.flush();
+        if let Some(recent) = &self.recent {
+            recent.drain_into(sink);
+        }
+    }
+}
+
+fn report_repeats(
+    sink: &mut dyn LogSink,
+    recent: Option<&mut RingBufferSink>,
+    last: &CapturedRecord,
+    repeats: u32,
+) {
+    let msg = format!("last message repeated {} times", repeats);
+    let record = LogRecord {
+        msg: &msg,
+        fields: &[],
+        ..last.as_record()
+    };
+    sink.emit(&record);
+    if let Some(recent) = recent {
+        recent.emit(&record);
+    }
+}
+
+fn report_dropped(sink: &mut dyn LogSink, guest: &str, dropped: u32) {
+    let msg = format!("{} records dropped by rate limit", dropped);
+    sink.emit(&LogRecord {
+        guest,
+        level: Level::Warn,
+        target: "logging",
+        msg: &msg,
+        fields: &[],
+    });
+}
+
+pub trait LoggingView {
+    fn logging_ctx(&mut self) -> &mut LoggingCtx;
+}
+
+pub struct LoggingImpl<'a, T> {
+    pub host: &'a mut T,
+}
+
+impl<'a, T: LoggingView> my::debug::logging::Host for LoggingImpl<'a, T> {
+    fn log(&mut self, msg: String) {
+        self.host.logging_ctx().emit(Level::Info, "", &msg, &[]);
+    }
+
+    fn log_record(&mut self, level: Level, target: String, msg: String, fields: Vec<Field>) {
+        self.host.logging_ctx().emit(level, &target, &msg, &fields);
+    }
+
+    fn enabled(&mut self, level: Level) -> bool {
+        self.host.logging_ctx().enabled(level)
+    }
+}
+
+pub struct LoggingBindingMarker<T>(PhantomData<T>);
+impl<T: LoggingView + 'static> HasData for LoggingBindingMarker<T> {
+    type Data<'a> = LoggingImpl<'a, T>;
+}
+pub fn add_to_linker<T: LoggingView + 'static>(linker: &mut Linker<T>) -> wasmtime::Result<()> {
+    my::debug::logging::add_to_linker::<T, LoggingBindingMarker<T>>(linker, |host| LoggingImpl {
+        host,
+    })
+}
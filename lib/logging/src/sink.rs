@@ -0,0 +1,243 @@
+use alloc::collections::VecDeque;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+
+use critical_section::Mutex;
+
+use crate::{Field, Level, LogRecord};
+
+/// Destination for guest log records that passed the level filter. `Send`
+/// because async stores require the whole host state to be.
+pub trait LogSink: Send {
+    fn emit(&mut self, record: &LogRecord<'_>);
+}
+
+// Fan out to two sinks, e.g. `(DefmtSink, ring.clone())`
+impl<A: LogSink, B: LogSink> LogSink for (A, B) {
+    fn emit(&mut self, record: &LogRecord<'_>) {
+        self.0.emit(record);
+        self.1.emit(record);
+    }
+}
+
+/// Owned copy of a [`LogRecord`], kept by the in-memory sinks.
+#[derive(Clone)]
+pub struct CapturedRecord {
+    pub guest: String,
+    pub level: Level,
+    pub target: String,
+    pub msg: String,
+    pub fields: Vec<Field>,
+}
+
+impl CapturedRecord {
+    pub fn as_record(&self) -> LogRecord<'_> {
+        LogRecord {
+            guest: &self.guest,
+            level: self.level,
+            target: &self.target,
+            msg: &self.msg,
+            fields: &self.fields,
+        }
+    }
+}
+
+impl From<&LogRecord<'_>> for CapturedRecord {
+    fn from(record: &LogRecord<'_>) -> Self {
+        Self {
+            guest: record.guest.to_string(),
+            level: record.level,
+            target: record.target.to_string(),
+            msg: record.msg.to_string(),
+            fields: record.fields.to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for CapturedRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_record().fmt(f)
+    }
+}
+
+/// Keeps every record in memory. Clones share the same storage, so a test
+/// can hand one clone to the `LoggingCtx` and inspect the other.
+#[derive(Clone)]
+pub struct CaptureSink {
+    records: Arc<Mutex<RefCell<Vec<CapturedRecord>>>>,
+}
+
+impl Default for CaptureSink {
+    fn default() -> Self {
+        Self {
+            records: Arc::new(Mutex::new(RefCell::new(Vec::new()))),
+        }
+    }
+}
+
+impl CaptureSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn records(&self) -> Vec<CapturedRecord> {
+        critical_section::with(|cs| self.records.borrow_ref(cs).clone())
+    }
+
+    pub fn take(&self) -> Vec<CapturedRecord> {
+        critical_section::with(|cs| self.records.borrow(cs).take())
+    }
+}
+
+impl LogSink for CaptureSink {
+    fn emit(&mut self, record: &LogRecord<'_>) {
+        let record = record.into();
+        critical_section::with(|cs| self.records.borrow_ref_mut(cs).push(record));
+    }
+}
+
+/// Keeps the most recent `capacity` records, evicting the oldest. Clones
+/// share the same buffer.
+#[derive(Clone)]
+pub struct RingBufferSink {
+    capacity: usize,
+    records: Arc<Mutex<RefCell<VecDeque<CapturedRecord>>>>,
+}
+
+impl RingBufferSink {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: Arc::new(Mutex::new(RefCell::new(VecDeque::with_capacity(capacity)))),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the retained records, oldest first.
+    pub fn snapshot(&self) -> Vec<CapturedRecord> {
+        critical_section::with(|cs| self.records.borrow_ref(cs).iter().cloned().collect())
+    }
+
+    /// Replays the retained records into `sink`, oldest first, and empties the buffer.
+    pub fn drain_into(&self, sink: &mut dyn LogSink) {
+        // Sinks may block (defmt, stdout), so replay outside the critical section
+        let records = critical_section::with(|cs| self.records.borrow(cs).take());
+        for record in &records {
+            sink.emit(&record.as_record());
+        }
+    }
+}
+
+impl LogSink for RingBufferSink {
+    fn emit(&mut self, record: &LogRecord<'_>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let record = record.into();
+        critical_section::with(|cs| {
+            let mut records = self.records.borrow_ref_mut(cs);
+            if records.len() == self.capacity {
+                records.pop_front();
+            }
+            records.push_back(record);
+        });
+    }
+}
+
+/// Prints records over defmt, using the macro matching the record's level.
+#[cfg(feature = "defmt")]
+pub struct DefmtSink;
+
+#[cfg(feature = "defmt")]
+impl LogSink for DefmtSink {
+    fn emit(&mut self, record: &LogRecord<'_>) {
+        let line = alloc::format!("{}", record);
+        let line = line.as_str();
+        match record.level {
+            Level::Trace => defmt::trace!("{}", line),
+            Level::Debug => defmt::debug!("{}", line),
+            Level::Info => defmt::info!("{}", line),
+            Level::Warn => defmt::warn!("{}", line),
+            Level::Error => defmt::error!("{}", line),
+        }
+    }
+}
+
+/// Prints records to stdout, one per line, prefixed with the level.
+#[cfg(feature = "std")]
+pub struct StdoutSink;
+
+#[cfg(feature = "std")]
+impl LogSink for StdoutSink {
+    fn emit(&mut self, record: &LogRecord<'_>) {
+        std::println!("{:<5} {}", level_name(record.level), record);
+    }
+}
+
+/// Forwards records to the `log` crate facade. The guest name becomes the
+/// `log` target so host-side filters (e.g. `RUST_LOG=pacman=warn`) apply per guest.
+#[cfg(feature = "log")]
+pub struct LogCrateSink;
+
+#[cfg(feature = "log")]
+impl LogSink for LogCrateSink {
+    fn emit(&mut self, record: &LogRecord<'_>) {
+        let level = match record.level {
+            Level::Trace => log::Level::Trace,
+            Level::Debug => log::Level::Debug,
+            Level::Info => log::Level::Info,
+            Level::Warn => log::Level::Warn,
+            Level::Error => log::Level::Error,
+        };
+        let target = if record.guest.is_empty() {
+            "guest"
+        } else {
+            record.guest
+        };
+        let unprefixed = LogRecord {
+            guest: "",
+            ..*record
+        };
+        log::log!(target: target, level, "{}", unprefixed);
+    }
+}
+
+/// Forwards records to `tracing` as events. The guest name goes in a `guest`
+/// field, since a `tracing` target has to be known at compile time.
+#[cfg(feature = "tracing")]
+pub struct TracingSink;
+
+#[cfg(feature = "tracing")]
+impl LogSink for TracingSink {
+    fn emit(&mut self, record: &LogRecord<'_>) {
+        let guest = record.guest;
+        let unprefixed = LogRecord {
+            guest: "",
+            ..*record
+        };
+        match record.level {
+            Level::Trace => tracing::trace!(guest, "{}", unprefixed),
+            Level::Debug => tracing::debug!(guest, "{}", unprefixed),
+            Level::Info => tracing::info!(guest, "{}", unprefixed),
+            Level::Warn => tracing::warn!(guest, "{}", unprefixed),
+            Level::Error => tracing::error!(guest, "{}", unprefixed),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Trace => "TRACE",
+        Level::Debug => "DEBUG",
+        Level::Info => "INFO",
+        Level::Warn => "WARN",
+        Level::Error => "ERROR",
+    }
+}
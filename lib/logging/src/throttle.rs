@@ -0,0 +1,105 @@
+use alloc::boxed::Box;
+
+use crate::{CapturedRecord, LogRecord};
+
+/// Allows at most `max_records` records per `window_ms` milliseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    pub max_records: u32,
+    pub window_ms: u64,
+}
+
+pub(crate) enum Admission {
+    // `dropped` is the number of records rejected in the window that just closed
+    Allowed { dropped: u32 },
+    Dropped,
+}
+
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    // Milliseconds since an arbitrary epoch, e.g. `embassy_time::Instant::now().as_millis()`
+    clock: Box<dyn Fn() -> u64 + Send>,
+    window_start: Option<u64>,
+    used: u32,
+    dropped: u32,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit, clock: Box<dyn Fn() -> u64 + Send>) -> Self {
+        Self {
+            limit,
+            clock,
+            window_start: None,
+            used: 0,
+            dropped: 0,
+        }
+    }
+
+    pub(crate) fn admit(&mut self) -> Admission {
+        let now = (self.clock)();
+        let mut closed_dropped = 0;
+        let window_open = self
+            .window_start
+            .is_some_and(|start| now.saturating_sub(start) < self.limit.window_ms);
+        if !window_open {
+            closed_dropped = self.take_dropped();
+            self.window_start = Some(now);
+            self.used = 0;
+        }
+
+        if self.used >= self.limit.max_records {
+            self.dropped += 1;
+            return Admission::Dropped;
+        }
+        self.used += 1;
+        Admission::Allowed {
+            dropped: closed_dropped,
+        }
+    }
+
+    pub(crate) fn take_dropped(&mut self) -> u32 {
+        core::mem::take(&mut self.dropped)
+    }
+}
+
+/// Collapses runs of identical records into the first one plus a repeat count.
+#[derive(Default)]
+pub(crate) struct Deduplicator {
+    last: Option<CapturedRecord>,
+    repeats: u32,
+}
+
+impl Deduplicator {
+    /// Returns true if `record` repeats the previous one and should be suppressed.
+    pub(crate) fn is_repeat(&mut self, record: &LogRecord<'_>) -> bool {
+        let same = self.last.as_ref().is_some_and(|last| {
+            last.level == record.level
+                && last.target == record.target
+                && last.msg == record.msg
+                && last.fields == record.fields
+        });
+        if same {
+            self.repeats += 1;
+        }
+        same
+    }
+
+    pub(crate) fn remember(&mut self, record: &LogRecord<'_>) {
+        self.last = Some(record.into());
+    }
+
+    /// Starts over, so the next record is never a repeat.
+    pub(crate) fn forget(&mut self) {
+        self.last = None;
+        self.repeats = 0;
+    }
+
+    /// Takes the pending repeat count along with the record it refers to.
+    pub(crate) fn take_repeats(&mut self) -> Option<(&CapturedRecord, u32)> {
+        let repeats = core::mem::take(&mut self.repeats);
+        match &self.last {
+            Some(last) if repeats > 0 => Some((last, repeats)),
+            _ => None,
+        }
+    }
+}
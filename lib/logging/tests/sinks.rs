@@ -0,0 +1,227 @@
+use std::fmt;
+use std::sync::Mutex;
+
+use logging::my::debug::logging::Host;
+use logging::{
+    CaptureSink, Field, Level, LogCrateSink, LoggingCtx, LoggingImpl, LoggingView, RingBufferSink,
+    TracingSink,
+};
+use tracing::field::Visit;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+struct TestState {
+    logging_ctx: LoggingCtx,
+}
+
+impl LoggingView for TestState {
+    fn logging_ctx(&mut self) -> &mut LoggingCtx {
+        &mut self.logging_ctx
+    }
+}
+
+fn field(key: &str, value: &str) -> Field {
+    Field {
+        key: key.into(),
+        value: value.into(),
+    }
+}
+
+#[test]
+fn prefixes_guest_name_and_formats_fields() {
+    let capture = CaptureSink::new();
+    let mut state = TestState {
+        logging_ctx: LoggingCtx::new("bme280", capture.clone()),
+    };
+    let mut host = LoggingImpl { host: &mut state };
+
+    host.log("hello".into());
+    host.log_record(
+        Level::Warn,
+        "sensor".into(),
+        "Unexpected chip ID".into(),
+        vec![field("expected", "0x60"), field("got", "0x58")],
+    );
+
+    let lines: Vec<String> = capture.records().iter().map(|r| r.to_string()).collect();
+    assert_eq!(
+        lines,
+        [
+            "[bme280] hello",
+            "[bme280] sensor: Unexpected chip ID expected=0x60 got=0x58",
+        ]
+    );
+}
+
+#[test]
+fn drops_records_below_min_level() {
+    let capture = CaptureSink::new();
+    let mut state = TestState {
+        logging_ctx: LoggingCtx::new("pacman", capture.clone()).with_min_level(Level::Warn),
+    };
+    let mut host = LoggingImpl { host: &mut state };
+
+    assert!(!host.enabled(Level::Info));
+    assert!(host.enabled(Level::Error));
+
+    host.log("frame".into());
+    host.log_record(Level::Debug, "".into(), "verbose".into(), vec![]);
+    host.log_record(Level::Error, "".into(), "boom".into(), vec![]);
+
+    let records = capture.take();
+    assert_eq!(records.len(), 1);
+    assert!(records[0].level == Level::Error);
+    assert!(capture.records().is_empty());
+}
+
+#[test]
+fn ring_buffer_keeps_most_recent_records() {
+    let ring = RingBufferSink::new(2);
+    let mut state = TestState {
+        logging_ctx: LoggingCtx::new("", ring.clone()),
+    };
+    let mut host = LoggingImpl { host: &mut state };
+
+    for msg in ["one", "two", "three"] {
+        host.log(msg.into());
+    }
+
+    let retained: Vec<String> = ring.snapshot().into_iter().map(|r| r.msg).collect();
+    assert_eq!(retained, ["two", "three"]);
+
+    let mut capture = CaptureSink::new();
+    ring.drain_into(&mut capture);
+    assert_eq!(capture.records().len(), 2);
+    assert!(ring.snapshot().is_empty());
+}
+
+#[test]
+fn tuple_sink_fans_out() {
+    let capture = CaptureSink::new();
+    let ring = RingBufferSink::new(4);
+    let mut ctx = LoggingCtx::new("driver", (capture.clone(), ring.clone()));
+
+    ctx.emit(Level::Info, "", "on", &[]);
+
+    assert_eq!(capture.records().len(), 1);
+    assert_eq!(ring.snapshot().len(), 1);
+}
+
+// What reached the `log` facade: level, target and message
+static LOGGED: Mutex<Vec<(log::Level, String, String)>> = Mutex::new(Vec::new());
+
+struct TestLogger;
+
+impl log::Log for TestLogger {
+    fn enabled(&self, _: &log::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        let logged = (
+            record.level(),
+            record.target().to_string(),
+            record.args().to_string(),
+        );
+        LOGGED.lock().unwrap().push(logged);
+    }
+
+    fn flush(&self) {}
+}
+
+#[test]
+fn log_crate_sink_targets_the_guest() {
+    log::set_logger(&TestLogger).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+    let mut ctx = LoggingCtx::new("bme280", LogCrateSink).with_min_level(Level::Trace);
+
+    ctx.emit(
+        Level::Warn,
+        "sensor",
+        "Unexpected chip ID",
+        &[field("got", "0x58")],
+    );
+    ctx.emit(Level::Trace, "", "tick", &[]);
+
+    assert_eq!(
+        *LOGGED.lock().unwrap(),
+        [
+            (
+                log::Level::Warn,
+                "bme280".to_string(),
+                "sensor: Unexpected chip ID got=0x58".to_string()
+            ),
+            (log::Level::Trace, "bme280".to_string(), "tick".to_string()),
+        ]
+    );
+}
+
+// Each event's level and fields, as "name=value" in the order they were recorded
+#[derive(Clone, Default)]
+struct TestSubscriber(std::sync::Arc<Mutex<Vec<(tracing::Level, String)>>>);
+
+struct Fields(String);
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.0 += &format!(" {field}={value}");
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+        self.0 += &format!(" {field}={value:?}");
+    }
+}
+
+impl Subscriber for TestSubscriber {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, _: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields(String::new());
+        event.record(&mut fields);
+        let level = *event.metadata().level();
+        self.0
+            .lock()
+            .unwrap()
+            .push((level, fields.0.trim_start().to_string()));
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+#[test]
+fn tracing_sink_puts_the_guest_in_a_field() {
+    let subscriber = TestSubscriber::default();
+    let events = subscriber.0.clone();
+    let mut ctx = LoggingCtx::new("pacman", TracingSink).with_min_level(Level::Trace);
+
+    tracing::subscriber::with_default(subscriber, || {
+        ctx.emit(Level::Error, "ghost", "Caught", &[field("lives", "2")]);
+        ctx.emit(Level::Debug, "", "frame", &[]);
+    });
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            (
+                tracing::Level::ERROR,
+                "message=ghost: Caught lives=2 guest=pacman".to_string()
+            ),
+            (
+                tracing::Level::DEBUG,
+                "message=frame guest=pacman".to_string()
+            ),
+        ]
+    );
+}
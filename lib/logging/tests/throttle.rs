@@ -0,0 +1,190 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use logging::{CaptureSink, Level, LogStats, LoggingCtx, RateLimit};
+
+fn manual_clock() -> (Arc<AtomicU64>, impl Fn() -> u64 + Send) {
+    let now = Arc::new(AtomicU64::new(0));
+    let clock = {
+        let now = now.clone();
+        move || now.load(Ordering::Relaxed)
+    };
+    (now, clock)
+}
+
+fn messages(capture: &CaptureSink) -> Vec<String> {
+    capture.records().into_iter().map(|r| r.msg).collect()
+}
+
+#[test]
+fn rate_limit_drops_and_reports_count() {
+    let (now, clock) = manual_clock();
+    let capture = CaptureSink::new();
+    let limit = RateLimit {
+        max_records: 5,
+        window_ms: 1000,
+    };
+    let mut ctx = LoggingCtx::new("pacman", capture.clone()).with_rate_limit(limit, clock);
+
+    for frame in 0..20 {
+        ctx.emit(Level::Info, "", &format!("frame {frame}"), &[]);
+    }
+    assert_eq!(capture.records().len(), 5);
+    assert_eq!(ctx.stats().rate_limited, 15);
+
+    now.store(1000, Ordering::Relaxed);
+    ctx.emit(Level::Info, "", "frame 20", &[]);
+
+    let tail = &messages(&capture)[5..];
+    assert_eq!(tail, ["15 records dropped by rate limit", "frame 20"]);
+    assert_eq!(
+        ctx.stats(),
+        LogStats {
+            emitted: 6,
+            filtered: 0,
+            rate_limited: 15,
+            deduplicated: 0,
+        }
+    );
+}
+
+#[test]
+fn flush_reports_drops_within_the_window() {
+    let (_now, clock) = manual_clock();
+    let capture = CaptureSink::new();
+    let limit = RateLimit {
+        max_records: 1,
+        window_ms: 1000,
+    };
+    let mut ctx = LoggingCtx::new("pacman", capture.clone()).with_rate_limit(limit, clock);
+
+    for _ in 0..4 {
+        ctx.emit(Level::Info, "", "tick", &[]);
+    }
+    ctx.flush();
+
+    assert_eq!(
+        messages(&capture),
+        ["tick", "3 records dropped by rate limit"]
+    );
+}
+
+#[test]
+fn dedup_collapses_identical_records() {
+    let capture = CaptureSink::new();
+    let mut ctx = LoggingCtx::new("pacman", capture.clone())
+        .with_min_level(Level::Debug)
+        .with_dedup();
+
+    for _ in 0..58 {
+        ctx.emit(Level::Debug, "render", "frame presented", &[]);
+    }
+    ctx.emit(Level::Info, "render", "display off", &[]);
+
+    let lines: Vec<String> = capture.records().iter().map(|r| r.to_string()).collect();
+    assert_eq!(
+        lines,
+        [
+            "[pacman] render: frame presented",
+            "[pacman] render: last message repeated 57 times",
+            "[pacman] render: display off",
+        ]
+    );
+    assert_eq!(ctx.stats().deduplicated, 57);
+}
+
+#[test]
+fn repeats_do_not_use_rate_limit_budget() {
+    let (_now, clock) = manual_clock();
+    let capture = CaptureSink::new();
+    let limit = RateLimit {
+        max_records: 2,
+        window_ms: 1000,
+    };
+    let mut ctx = LoggingCtx::new("pacman", capture.clone())
+        .with_dedup()
+        .with_rate_limit(limit, clock);
+
+    for _ in 0..10 {
+        ctx.emit(Level::Info, "", "same", &[]);
+    }
+    ctx.emit(Level::Info, "", "different", &[]);
+
+    assert_eq!(
+        messages(&capture),
+        ["same", "last message repeated 9 times", "different"]
+    );
+    assert_eq!(ctx.stats().rate_limited, 0);
+}
+
+#[test]
+fn retention_keeps_records_the_limiter_dropped() {
+    let (_now, clock) = manual_clock();
+    let capture = CaptureSink::new();
+    let limit = RateLimit {
+        max_records: 1,
+        window_ms: 1000,
+    };
+    let mut ctx = LoggingCtx::new("pacman", capture.clone())
+        .with_min_level(Level::Debug)
+        .with_rate_limit(limit, clock)
+        .with_retention(3);
+
+    ctx.emit(Level::Trace, "", "filtered", &[]);
+    for step in 0..5 {
+        ctx.emit(Level::Debug, "", &format!("step {step}"), &[]);
+    }
+    assert_eq!(messages(&capture), ["step 0"]);
+    assert_eq!(ctx.stats().filtered, 1);
+
+    let dump = CaptureSink::new();
+    ctx.dump_recent(&mut dump.clone());
+    assert_eq!(messages(&dump), ["step 2", "step 3", "step 4"]);
+    assert_eq!(
+        messages(&capture),
+        ["step 0", "4 records dropped by rate limit"]
+    );
+
+    let empty = CaptureSink::new();
+    ctx.dump_recent(&mut empty.clone());
+    assert!(empty.records().is_empty());
+}
+
+#[test]
+fn retention_keeps_one_slot_for_repeats() {
+    let capture = CaptureSink::new();
+    let mut ctx = LoggingCtx::new("pacman", capture.clone())
+        .with_dedup()
+        .with_retention(3);
+
+    ctx.emit(Level::Info, "", "ghost spawned", &[]);
+    for _ in 0..40 {
+        ctx.emit(Level::Info, "", "frame", &[]);
+    }
+    ctx.emit(Level::Error, "", "out of tiles", &[]);
+
+    let dump = CaptureSink::new();
+    ctx.dump_recent(&mut dump.clone());
+    assert_eq!(
+        messages(&dump),
+        ["frame", "last message repeated 39 times", "out of tiles"]
+    );
+}
+
+#[test]
+fn flush_forgets_the_last_record() {
+    let capture = CaptureSink::new();
+    let mut ctx = LoggingCtx::new("sensor", capture.clone()).with_dedup();
+
+    ctx.emit(Level::Info, "", "starting", &[]);
+    ctx.emit(Level::Info, "", "starting", &[]);
+    // As on a restart
+    ctx.flush();
+    ctx.emit(Level::Info, "", "starting", &[]);
+
+    assert_eq!(
+        messages(&capture),
+        ["starting", "last message repeated 1 times", "starting"]
+    );
+    assert_eq!(ctx.stats().deduplicated, 1);
+}
@@ -1,16 +1,20 @@
 #![no_std]
 extern crate alloc;
 
+use alloc::boxed::Box;
 use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
 use core::marker::PhantomData;
 
-use embassy_rp::gpio::Output; // <-- Added Output
-use embassy_rp::peripherals::SPI0;
-use embassy_rp::spi::{Blocking, Spi};
 use wasmtime::component::{HasData, Linker, Resource, ResourceTable};
 
+#[cfg(feature = "rp")]
+mod rp;
+
+#[cfg(feature = "rp")]
+pub use rp::{RpSpiDevice, SharedSpi};
+
 // Adjust path depending on your workspace root
 wasmtime::component::bindgen!({
     path: "../../wit/spi.wit",
@@ -24,10 +28,65 @@ pub struct ActiveSpiDriver {
     pub id: u8,
 }
 
+/// One step of a [`SpiDevice::transaction`], as in embedded-hal.
+pub enum Operation<'a> {
+    Read(&'a mut [u8]),
+    Write(&'a [u8]),
+    // Read into the first buffer while writing the second, of the same length
+    Transfer(&'a mut [u8], &'a [u8]),
+    DelayNs(u32),
+}
+
+/// The bus reported an error; guests only learn which call failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BusError;
+
+/// What a guest's `spi0` is backed by: a chip select on the board's bus
+/// ([`RpSpiDevice`], with the `rp` feature) or a simulated device.
+pub trait SpiDevice: Send {
+    /// Runs `operations` in order, with the device selected for all of them.
+    fn transaction(&mut self, operations: &mut [Operation<'_>]) -> Result<(), BusError>;
+
+    /// Deselects the device, for use when its guest is reset.
+    fn release(&mut self) {}
+}
+
 pub struct SpiCtx {
     pub table: ResourceTable,
-    pub spi: Spi<'static, SPI0, Blocking>,
-    pub cs: Output<'static>, // <-- Added the CS pin
+    // None for a guest that was given no SPI device; it then sees no `spi0`
+    pub device: Option<Box<dyn SpiDevice>>,
+}
+
+impl SpiCtx {
+    pub fn new(device: Option<Box<dyn SpiDevice>>) -> Self {
+        Self {
+            table: ResourceTable::new(),
+            device,
+        }
+    }
+
+    /// Closes every open device handle and releases chip select, for use
+    /// after a guest failed and before it is re-instantiated.
+    pub fn reset(&mut self) {
+        self.table = ResourceTable::new();
+        if let Some(device) = &mut self.device {
+            device.release();
+        }
+    }
+
+    fn transaction(
+        &mut self,
+        operations: &mut [Operation<'_>],
+        failed: &str,
+    ) -> Result<(), wasi::spi::spi::Error> {
+        // Devices are only opened by guests that have one, so this is not expected
+        let Some(device) = &mut self.device else {
+            return Err(wasi::spi::spi::Error::Other("No SPI device".to_string()));
+        };
+        device
+            .transaction(operations)
+            .map_err(|BusError| wasi::spi::spi::Error::Other(failed.to_string()))
+    }
 }
 
 pub trait SpiView {
@@ -40,6 +99,9 @@ pub struct SpiImpl<'a, T> {
 
 impl<'a, T: SpiView> wasi::spi::spi::Host for SpiImpl<'a, T> {
     fn get_device_names(&mut self) -> Vec<String> {
+        if self.host.spi_ctx().device.is_none() {
+            return Vec::new();
+        }
         vec!["spi0".to_string()]
     }
 
@@ -47,7 +109,7 @@ impl<'a, T: SpiView> wasi::spi::spi::Host for SpiImpl<'a, T> {
         &mut self,
         name: String,
     ) -> Result<Resource<ActiveSpiDriver>, wasi::spi::spi::Error> {
-        if name == "spi0" {
+        if name == "spi0" && self.host.spi_ctx().device.is_some() {
             let handle = self
                 .host
                 .spi_ctx()
@@ -76,12 +138,9 @@ impl<'a, T: SpiView> wasi::spi::spi::HostSpiDevice for SpiImpl<'a, T> {
         len: u64,
     ) -> Result<Vec<u8>, wasi::spi::spi::Error> {
         let mut buf = vec![0u8; len as usize];
-
-        self.host.spi_ctx().cs.set_low(); // <-- Pull CS low
-        let res = self.host.spi_ctx().spi.blocking_read(&mut buf);
-        self.host.spi_ctx().cs.set_high(); // <-- Pull CS high
-
-        res.map_err(|_| wasi::spi::spi::Error::Other("Read failed".to_string()))?;
+        self.host
+            .spi_ctx()
+            .transaction(&mut [Operation::Read(&mut buf)], "Read failed")?;
         Ok(buf)
     }
 
@@ -90,12 +149,9 @@ impl<'a, T: SpiView> wasi::spi::spi::HostSpiDevice for SpiImpl<'a, T> {
         _handle: Resource<ActiveSpiDriver>,
         data: Vec<u8>,
     ) -> Result<(), wasi::spi::spi::Error> {
-        self.host.spi_ctx().cs.set_low(); // <-- Pull CS low
-        let res = self.host.spi_ctx().spi.blocking_write(&data);
-        self.host.spi_ctx().cs.set_high(); // <-- Pull CS high
-
-        res.map_err(|_| wasi::spi::spi::Error::Other("Write failed".to_string()))?;
-        Ok(())
+        self.host
+            .spi_ctx()
+            .transaction(&mut [Operation::Write(&data)], "Write failed")
     }
 
     fn transfer(
@@ -104,16 +160,10 @@ impl<'a, T: SpiView> wasi::spi::spi::HostSpiDevice for SpiImpl<'a, T> {
         data: Vec<u8>,
     ) -> Result<Vec<u8>, wasi::spi::spi::Error> {
         let mut read_buf = vec![0u8; data.len()];
-
-        self.host.spi_ctx().cs.set_low(); // <-- Pull CS low
-        let res = self
-            .host
-            .spi_ctx()
-            .spi
-            .blocking_transfer(&mut read_buf, &data);
-        self.host.spi_ctx().cs.set_high(); // <-- Pull CS high
-
-        res.map_err(|_| wasi::spi::spi::Error::Other("Transfer failed".to_string()))?;
+        self.host.spi_ctx().transaction(
+            &mut [Operation::Transfer(&mut read_buf, &data)],
+            "Transfer failed",
+        )?;
         Ok(read_buf)
     }
 
@@ -122,50 +172,43 @@ impl<'a, T: SpiView> wasi::spi::spi::HostSpiDevice for SpiImpl<'a, T> {
         _handle: Resource<ActiveSpiDriver>,
         operations: Vec<wasi::spi::spi::Operation>,
     ) -> Result<Vec<wasi::spi::spi::OperationResult>, wasi::spi::spi::Error> {
-        let mut results = Vec::new();
-
-        self.host.spi_ctx().cs.set_low(); // <-- Lock CS low for the entire transaction
-
-        for op in operations {
-            match op {
-                wasi::spi::spi::Operation::Read(len) => {
-                    let mut buf = vec![0u8; len as usize];
-                    if self.host.spi_ctx().spi.blocking_read(&mut buf).is_err() {
-                        self.host.spi_ctx().cs.set_high(); // Safety release
-                        return Err(wasi::spi::spi::Error::Other("Read error".to_string()));
-                    }
-                    results.push(wasi::spi::spi::OperationResult::Read(buf));
-                }
-                wasi::spi::spi::Operation::Write(data) => {
-                    if self.host.spi_ctx().spi.blocking_write(&data).is_err() {
-                        self.host.spi_ctx().cs.set_high(); // Safety release
-                        return Err(wasi::spi::spi::Error::Other("Write error".to_string()));
-                    }
-                    results.push(wasi::spi::spi::OperationResult::Write);
-                }
-                wasi::spi::spi::Operation::Transfer(data) => {
-                    let mut read_buf = vec![0u8; data.len()];
-                    if self
-                        .host
-                        .spi_ctx()
-                        .spi
-                        .blocking_transfer(&mut read_buf, &data)
-                        .is_err()
-                    {
-                        self.host.spi_ctx().cs.set_high(); // Safety release
-                        return Err(wasi::spi::spi::Error::Other("Transfer error".to_string()));
-                    }
-                    results.push(wasi::spi::spi::OperationResult::Transfer(read_buf));
-                }
-                wasi::spi::spi::Operation::DelayNs(ns) => {
-                    embassy_time::block_for(embassy_time::Duration::from_nanos(ns as u64));
-                    results.push(wasi::spi::spi::OperationResult::Delay);
-                }
-            }
-        }
-
-        self.host.spi_ctx().cs.set_high(); // <-- Release CS high
-        Ok(results)
+        use wasi::spi::spi::{Operation as Op, OperationResult};
+
+        // Buffers for what the device sends back, one per operation
+        let mut reads: Vec<Vec<u8>> = operations
+            .iter()
+            .map(|op| match op {
+                Op::Read(len) => vec![0u8; *len as usize],
+                Op::Transfer(data) => vec![0u8; data.len()],
+                Op::Write(_) | Op::DelayNs(_) => Vec::new(),
+            })
+            .collect();
+        let mut ops: Vec<Operation<'_>> = operations
+            .iter()
+            .zip(&mut reads)
+            .map(|(op, read)| match op {
+                Op::Read(_) => Operation::Read(read),
+                Op::Write(data) => Operation::Write(data),
+                Op::Transfer(data) => Operation::Transfer(read, data),
+                Op::DelayNs(ns) => Operation::DelayNs(*ns),
+            })
+            .collect();
+        // Hold CS low (and the bus) for the entire transaction
+        self.host
+            .spi_ctx()
+            .transaction(&mut ops, "Transaction failed")?;
+        drop(ops);
+
+        Ok(operations
+            .iter()
+            .zip(reads)
+            .map(|(op, read)| match op {
+                Op::Read(_) => OperationResult::Read(read),
+                Op::Write(_) => OperationResult::Write,
+                Op::Transfer(_) => OperationResult::Transfer(read),
+                Op::DelayNs(_) => OperationResult::Delay,
+            })
+            .collect())
     }
 
     fn drop(&mut self, rep: Resource<ActiveSpiDriver>) -> wasmtime::Result<()> {
@@ -0,0 +1,55 @@
+use core::cell::RefCell;
+
+use embassy_rp::gpio::Output;
+use embassy_rp::peripherals::SPI0;
+use embassy_rp::spi::{Blocking, Spi};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+
+use crate::{BusError, Operation, SpiDevice};
+
+/// One SPI peripheral shared by every guest on it, each with its own chip
+/// select. Guests run on the thread-mode executor and a transfer never spans
+/// an await point, so a thread-mode lock is enough.
+pub type SharedSpi = Mutex<ThreadModeRawMutex, RefCell<Spi<'static, SPI0, Blocking>>>;
+
+/// A device on SPI0, told apart from the others on the bus by its chip select.
+pub struct RpSpiDevice {
+    bus: &'static SharedSpi,
+    cs: Output<'static>,
+}
+
+impl RpSpiDevice {
+    pub fn new(bus: &'static SharedSpi, cs: Output<'static>) -> Self {
+        Self { bus, cs }
+    }
+}
+
+impl SpiDevice for RpSpiDevice {
+    fn transaction(&mut self, operations: &mut [Operation<'_>]) -> Result<(), BusError> {
+        // Hold CS low (and the bus) for the entire transaction
+        self.cs.set_low();
+        let result = self.bus.lock(|spi| {
+            let spi = &mut *spi.borrow_mut();
+            for op in operations {
+                match op {
+                    Operation::Read(buf) => spi.blocking_read(buf),
+                    Operation::Write(data) => spi.blocking_write(data),
+                    Operation::Transfer(read, write) => spi.blocking_transfer(read, write),
+                    Operation::DelayNs(ns) => {
+                        embassy_time::block_for(embassy_time::Duration::from_nanos(*ns as u64));
+                        Ok(())
+                    }
+                }
+                .map_err(|_| BusError)?;
+            }
+            Ok(())
+        });
+        self.cs.set_high();
+        result
+    }
+
+    fn release(&mut self) {
+        self.cs.set_high();
+    }
+}
@@ -0,0 +1,176 @@
+//! Fuel-based preemption of guests.
+//!
+//! Epoch interruption needs 64-bit atomics, which the RP2350 does not have,
+//! so guests are metered with fuel instead. The store yields every
+//! [`FuelBudget::slice`] units and [`supervise`] asks a [`BudgetPolicy`]
+//! whether the guest may continue. A guest waiting in an async host function
+//! such as `delay-ms` also leaves its call pending; [`HostCalls`] tells the
+//! two apart, so only fuel yields count as slices.
+
+use alloc::sync::Arc;
+use core::future::{Future, poll_fn};
+use core::pin::pin;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::task::Poll;
+
+use wasmtime::{CallHook, Store};
+
+#[derive(Clone, Copy, Debug)]
+pub struct FuelBudget {
+    // Fuel units (roughly wasm instructions) the guest runs before the host is consulted
+    pub slice: u64,
+}
+
+impl FuelBudget {
+    /// Requires an engine built with `consume_fuel` and `async_support`.
+    pub fn apply<T>(&self, store: &mut Store<T>) -> wasmtime::Result<()> {
+        // The total is effectively unlimited; the policy decides when a guest has had enough
+        store.set_fuel(u64::MAX)?;
+        store.fuel_async_yield_interval(Some(self.slice))
+    }
+}
+
+/// What to do with a guest that used up its slice.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Resume,
+    Restart,
+    Kill,
+}
+
+pub trait BudgetPolicy {
+    /// Called each time the guest exhausts a slice of fuel; `slices` counts
+    /// from 1. Waits in the host are not slices and never reach the policy.
+    fn on_exhausted(&mut self, slices: u32) -> Verdict;
+}
+
+impl<F: FnMut(u32) -> Verdict> BudgetPolicy for F {
+    fn on_exhausted(&mut self, slices: u32) -> Verdict {
+        self(slices)
+    }
+}
+
+pub enum Outcome<R> {
+    Finished(R),
+    Trapped(wasmtime::Error),
+    Restart { slices: u32 },
+    Killed { slices: u32 },
+}
+
+/// Follows a store's calls into the host through its call hook.
+#[derive(Clone)]
+pub struct HostCalls(Arc<HostCallState>);
+
+struct HostCallState {
+    // Calls made since the hook was installed
+    count: AtomicU32,
+    // Set between `CallingHost` and `ReturningFromHost`, e.g. for the length of a `delay-ms`
+    in_host: AtomicBool,
+}
+
+impl HostCalls {
+    /// Replaces any call hook previously set on `store`.
+    pub fn install<T>(store: &mut Store<T>) -> Self {
+        let calls = HostCalls(Arc::new(HostCallState {
+            count: AtomicU32::new(0),
+            in_host: AtomicBool::new(false),
+        }));
+        let state = calls.0.clone();
+        store.call_hook(move |_, hook| {
+            match hook {
+                CallHook::CallingHost => {
+                    state.count.fetch_add(1, Ordering::Relaxed);
+                    state.in_host.store(true, Ordering::Relaxed);
+                }
+                CallHook::ReturningFromHost => state.in_host.store(false, Ordering::Relaxed),
+                _ => {}
+            }
+            Ok(())
+        });
+        calls
+    }
+
+    pub fn count(&self) -> u32 {
+        self.0.count.load(Ordering::Relaxed)
+    }
+
+    /// Whether the guest is inside a host function right now.
+    pub fn in_host(&self) -> bool {
+        self.0.in_host.load(Ordering::Relaxed)
+    }
+}
+
+/// Drives a guest call such as `call_run(&mut store)` to completion, consulting
+/// `policy` whenever the guest runs out of fuel.
+///
+/// `calls` must come from the same store as `call`. A `Pending` while the
+/// guest is in a host function is the host waiting (e.g. on a timer in
+/// `delay-ms`), which is neither a slice nor a chance to stop the guest;
+/// callers that need to take a waiting guest down race `supervise` against
+/// their own signal. On `Restart` or `Kill` the call is dropped
+/// mid-execution; the instance must not be used again, so callers should
+/// start from a fresh `Store` (see `Store::into_data`).
+pub async fn supervise<R>(
+    call: impl Future<Output = wasmtime::Result<R>>,
+    calls: &HostCalls,
+    mut policy: impl BudgetPolicy,
+) -> Outcome<R> {
+    let mut call = pin!(call);
+    let mut slices = 0;
+    poll_fn(|cx| match call.as_mut().poll(cx) {
+        Poll::Ready(Ok(value)) => Poll::Ready(Outcome::Finished(value)),
+        Poll::Ready(Err(trap)) => Poll::Ready(Outcome::Trapped(trap)),
+        // The host future (e.g. a timer) will wake the task
+        Poll::Pending if calls.in_host() => Poll::Pending,
+        Poll::Pending => {
+            slices += 1;
+            match policy.on_exhausted(slices) {
+                // Wasmtime already woke the task, so the executor polls us again
+                Verdict::Resume => Poll::Pending,
+                Verdict::Restart => Poll::Ready(Outcome::Restart { slices }),
+                Verdict::Kill => Poll::Ready(Outcome::Killed { slices }),
+            }
+        }
+    })
+    .await
+}
+
+/// Policy that resumes guests as long as they keep calling into the host,
+/// and gives up on one that spins for `max_quiet_slices` slices without
+/// a single host call.
+pub struct Watchdog {
+    calls: HostCalls,
+    last_seen: u32,
+    quiet_slices: u32,
+    max_quiet_slices: u32,
+    on_stuck: Verdict,
+}
+
+impl Watchdog {
+    pub fn new(calls: &HostCalls, max_quiet_slices: u32, on_stuck: Verdict) -> Self {
+        Self {
+            calls: calls.clone(),
+            last_seen: 0,
+            quiet_slices: 0,
+            max_quiet_slices,
+            on_stuck,
+        }
+    }
+}
+
+impl BudgetPolicy for Watchdog {
+    fn on_exhausted(&mut self, _slices: u32) -> Verdict {
+        let calls = self.calls.count();
+        if calls == self.last_seen {
+            self.quiet_slices += 1;
+        } else {
+            self.last_seen = calls;
+            self.quiet_slices = 0;
+        }
+        if self.quiet_slices >= self.max_quiet_slices {
+            self.on_stuck
+        } else {
+            Verdict::Resume
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Finds a guest's entry point without compile-time bindings for its world.
+
+use alloc::string::{String, ToString};
+use core::fmt;
+
+use wasmtime::component::types::ComponentItem;
+use wasmtime::component::{Component, ComponentExportIndex};
+
+/// Interface from `wit/app.wit` that conforming guests export.
+pub const RUN_INTERFACE: &str = "my:app/run";
+pub const RUN_FUNC: &str = "run";
+
+/// Returned by [`find_entry_point`] for a component that does not follow the
+/// `my:app/run` convention.
+#[derive(Debug)]
+pub struct NoEntryPoint {
+    interface: String,
+}
+
+impl fmt::Display for NoEntryPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "component exports neither `{}` nor a top-level `{RUN_FUNC}` function",
+            self.interface
+        )
+    }
+}
+
+impl core::error::Error for NoEntryPoint {}
+
+/// Looks up `run` in the exported `my:app/run` interface, falling back to a
+/// top-level `run` function as exported by worlds written before the
+/// convention (e.g. `export run: func();`).
+///
+/// Call it once per component and reuse the index with
+/// `instance.get_typed_func::<(), ()>(&mut store, &index)`, which also checks
+/// the signature.
+pub fn find_entry_point(component: &Component) -> Result<ComponentExportIndex, NoEntryPoint> {
+    find_entry_point_in(component, RUN_INTERFACE)
+}
+
+/// Like [`find_entry_point`], for guests whose `run` lives in another
+/// exported interface (a bundle manifest's entry export).
+pub fn find_entry_point_in(
+    component: &Component,
+    interface: &str,
+) -> Result<ComponentExportIndex, NoEntryPoint> {
+    let scope = component
+        .get_export(None, interface)
+        .filter(|(item, _)| matches!(item, ComponentItem::ComponentInstance(_)));
+    let scope = scope.as_ref().map(|(_, index)| index);
+    match component.get_export(scope, RUN_FUNC) {
+        Some((ComponentItem::ComponentFunc(_), index)) => Ok(index),
+        _ => Err(NoEntryPoint {
+            interface: interface.to_string(),
+        }),
+    }
+}
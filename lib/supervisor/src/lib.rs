@@ -0,0 +1,16 @@
+#![no_std]
+extern crate alloc;
+
+mod budget;
+mod entry;
+mod limits;
+mod policy;
+mod restart;
+mod trap;
+
+pub use budget::{BudgetPolicy, FuelBudget, HostCalls, Outcome, Verdict, Watchdog, supervise};
+pub use entry::{NoEntryPoint, RUN_FUNC, RUN_INTERFACE, find_entry_point, find_entry_point_in};
+pub use limits::{Denial, GuestLimiter, GuestLimits};
+pub use policy::{GUEST_BUDGET, GUEST_LIMITS, GUEST_MAX_QUIET_SLICES, GUEST_RESTART_POLICY};
+pub use restart::{RestartDecision, RestartPolicy, RestartTracker};
+pub use trap::TrapReport;
@@ -0,0 +1,210 @@
+//! Caps on what a guest may allocate from the host heap.
+//!
+//! A component declares its own memory and table sizes, and the host has
+//! only a fixed `embedded_alloc` heap to give out. [`GuestLimiter`] refuses
+//! growth past [`GuestLimits`] and remembers the refusal so the host can
+//! report it; the guest just sees `memory.grow` return -1.
+
+use core::fmt;
+
+use logging::{Level, LoggingCtx};
+use wasmtime::ResourceLimiter;
+
+#[derive(Clone, Copy, Debug)]
+pub struct GuestLimits {
+    // Per linear memory, in bytes
+    pub memory_bytes: usize,
+    // Per table, in elements
+    pub table_elements: usize,
+    // Core module instances, across all components in the store
+    pub instances: usize,
+    pub memories: usize,
+    pub tables: usize,
+}
+
+/// A growth request that [`GuestLimiter`] turned down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Denial {
+    Memory {
+        current: usize,
+        desired: usize,
+        limit: usize,
+    },
+    Table {
+        current: usize,
+        desired: usize,
+        limit: usize,
+    },
+}
+
+impl fmt::Display for Denial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Denial::Memory {
+                current,
+                desired,
+                limit,
+            } => write!(
+                f,
+                "denied memory growth from {current} to {desired} bytes (limit {limit})"
+            ),
+            Denial::Table {
+                current,
+                desired,
+                limit,
+            } => write!(
+                f,
+                "denied table growth from {current} to {desired} elements (limit {limit})"
+            ),
+        }
+    }
+}
+
+/// Enforces [`GuestLimits`] for one store. Hook it up with
+/// `store.limiter(|state| &mut state.limiter)`, or give the host state a
+/// `ResourceLimiter` that also reports denials with [`impl_resource_limiter`].
+pub struct GuestLimiter {
+    limits: GuestLimits,
+    denials: u32,
+    pending: Option<Denial>,
+}
+
+impl GuestLimiter {
+    pub fn new(limits: GuestLimits) -> Self {
+        Self {
+            limits,
+            denials: 0,
+            pending: None,
+        }
+    }
+
+    pub fn limits(&self) -> &GuestLimits {
+        &self.limits
+    }
+
+    /// Number of growth requests denied so far.
+    pub fn denials(&self) -> u32 {
+        self.denials
+    }
+
+    /// Takes the most recent denial that has not been reported yet.
+    pub fn take_denial(&mut self) -> Option<Denial> {
+        self.pending.take()
+    }
+
+    /// Logs the pending denial, if any, as a warning in `log`.
+    pub fn report_denial(&mut self, log: &mut LoggingCtx) {
+        if let Some(denial) = self.take_denial() {
+            let msg = alloc::format!("{}", denial);
+            log.emit(Level::Warn, "limits", &msg, &[]);
+        }
+    }
+
+    fn deny(&mut self, denial: Denial) -> wasmtime::Result<bool> {
+        self.denials += 1;
+        self.pending = Some(denial);
+        Ok(false)
+    }
+}
+
+impl ResourceLimiter for GuestLimiter {
+    fn memory_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        let limit = self.limits.memory_bytes;
+        if desired > limit {
+            return self.deny(Denial::Memory {
+                current,
+                desired,
+                limit,
+            });
+        }
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        let limit = self.limits.table_elements;
+        if desired > limit {
+            return self.deny(Denial::Table {
+                current,
+                desired,
+                limit,
+            });
+        }
+        Ok(true)
+    }
+
+    fn instances(&self) -> usize {
+        self.limits.instances
+    }
+
+    fn memories(&self) -> usize {
+        self.limits.memories
+    }
+
+    fn tables(&self) -> usize {
+        self.limits.tables
+    }
+}
+
+/// Implements `ResourceLimiter` for a host state with a `limiter: GuestLimiter`
+/// and a `logging_ctx: LoggingCtx`. Denied growth is logged in the guest's
+/// name, so it shows up next to its own records.
+#[macro_export]
+macro_rules! impl_resource_limiter {
+    ($state:ty) => {
+        impl ::wasmtime::ResourceLimiter for $state {
+            fn memory_growing(
+                &mut self,
+                current: usize,
+                desired: usize,
+                maximum: Option<usize>,
+            ) -> ::wasmtime::Result<bool> {
+                let allowed = ::wasmtime::ResourceLimiter::memory_growing(
+                    &mut self.limiter,
+                    current,
+                    desired,
+                    maximum,
+                )?;
+                self.limiter.report_denial(&mut self.logging_ctx);
+                Ok(allowed)
+            }
+
+            fn table_growing(
+                &mut self,
+                current: usize,
+                desired: usize,
+                maximum: Option<usize>,
+            ) -> ::wasmtime::Result<bool> {
+                let allowed = ::wasmtime::ResourceLimiter::table_growing(
+                    &mut self.limiter,
+                    current,
+                    desired,
+                    maximum,
+                )?;
+                self.limiter.report_denial(&mut self.logging_ctx);
+                Ok(allowed)
+            }
+
+            fn instances(&self) -> usize {
+                ::wasmtime::ResourceLimiter::instances(&self.limiter)
+            }
+
+            fn memories(&self) -> usize {
+                ::wasmtime::ResourceLimiter::memories(&self.limiter)
+            }
+
+            fn tables(&self) -> usize {
+                ::wasmtime::ResourceLimiter::tables(&self.limiter)
+            }
+        }
+    };
+}
@@ -0,0 +1,28 @@
+//! What the firmware runs every guest under. The simulator uses the same
+//! values, so a guest that behaves there behaves on the board.
+
+use engine_config::GUEST_MEMORY_BYTES;
+
+use crate::{FuelBudget, GuestLimits, RestartPolicy};
+
+// Each guest yields to the host every slice, which is also when the executor
+// switches to the other guests. One that makes no host calls for
+// GUEST_MAX_QUIET_SLICES slices in a row is treated as stuck and restarted
+pub const GUEST_BUDGET: FuelBudget = FuelBudget { slice: 100_000 };
+pub const GUEST_MAX_QUIET_SLICES: u32 = 50;
+
+pub const GUEST_RESTART_POLICY: RestartPolicy = RestartPolicy::ExponentialBackoff {
+    initial_ms: 500,
+    max_ms: 30_000,
+    max_restarts: Some(10),
+};
+
+// The component's own declarations are not trusted
+pub const GUEST_LIMITS: GuestLimits = GuestLimits {
+    memory_bytes: GUEST_MEMORY_BYTES,
+    table_elements: 256,
+    instances: 8,
+    // The display guest has one for the app and one for its plugged driver
+    memories: 2,
+    tables: 4,
+};
@@ -0,0 +1,66 @@
+/// When to bring a failed guest back up. A guest fails when it traps, fails
+/// to instantiate, or is restarted by its [`BudgetPolicy`](crate::BudgetPolicy).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestartPolicy {
+    Never,
+    Always,
+    MaxRestarts(u32),
+    // Waits `initial_ms`, doubling after every failure up to `max_ms`
+    ExponentialBackoff {
+        initial_ms: u64,
+        max_ms: u64,
+        max_restarts: Option<u32>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestartDecision {
+    Restart { delay_ms: u64 },
+    GiveUp,
+}
+
+/// Applies a [`RestartPolicy`] across the failures of one guest.
+pub struct RestartTracker {
+    policy: RestartPolicy,
+    restarts: u32,
+}
+
+impl RestartTracker {
+    pub fn new(policy: RestartPolicy) -> Self {
+        Self {
+            policy,
+            restarts: 0,
+        }
+    }
+
+    pub fn restarts(&self) -> u32 {
+        self.restarts
+    }
+
+    pub fn on_failure(&mut self) -> RestartDecision {
+        let decision = match self.policy {
+            RestartPolicy::Never => RestartDecision::GiveUp,
+            RestartPolicy::Always => RestartDecision::Restart { delay_ms: 0 },
+            RestartPolicy::MaxRestarts(max) if self.restarts < max => {
+                RestartDecision::Restart { delay_ms: 0 }
+            }
+            RestartPolicy::MaxRestarts(_) => RestartDecision::GiveUp,
+            RestartPolicy::ExponentialBackoff {
+                max_restarts: Some(max),
+                ..
+            } if self.restarts >= max => RestartDecision::GiveUp,
+            RestartPolicy::ExponentialBackoff {
+                initial_ms, max_ms, ..
+            } => {
+                let factor = 1u64.checked_shl(self.restarts).unwrap_or(u64::MAX);
+                RestartDecision::Restart {
+                    delay_ms: initial_ms.saturating_mul(factor).min(max_ms),
+                }
+            }
+        };
+        if let RestartDecision::Restart { .. } = decision {
+            self.restarts += 1;
+        }
+        decision
+    }
+}
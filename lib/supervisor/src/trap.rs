@@ -0,0 +1,42 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use wasmtime::WasmBacktrace;
+
+/// Printable summary of a guest failure, for logging over defmt where the
+/// multi-line `Display` of `wasmtime::Error` is awkward.
+pub struct TrapReport {
+    pub reason: String,
+    // One entry per wasm frame, innermost first: "module!function @ 0xoffset"
+    pub frames: Vec<String>,
+}
+
+impl TrapReport {
+    pub fn new(error: &wasmtime::Error) -> Self {
+        let frames = error
+            .downcast_ref::<WasmBacktrace>()
+            .map(|backtrace| {
+                backtrace
+                    .frames()
+                    .iter()
+                    .map(|frame| {
+                        let module = frame.module().name().unwrap_or("<unknown>");
+                        let func = match frame.func_name() {
+                            Some(name) => name.to_string(),
+                            None => format!("<wasm function {}>", frame.func_index()),
+                        };
+                        match frame.module_offset() {
+                            Some(offset) => format!("{}!{} @ {:#x}", module, func, offset),
+                            None => format!("{}!{}", module, func),
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            reason: error.root_cause().to_string(),
+            frames,
+        }
+    }
+}
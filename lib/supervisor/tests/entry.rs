@@ -0,0 +1,109 @@
+use supervisor::{NoEntryPoint, find_entry_point, find_entry_point_in};
+use wasmtime::component::{Component, Linker};
+use wasmtime::{Engine, Store};
+
+const INTERFACE: &str = r#"
+(component
+  (core module $m (func (export "run")))
+  (core instance $i (instantiate $m))
+  (func $run (canon lift (core func $i "run")))
+  (instance $app (export "run" (func $run)))
+  (export "my:app/run" (instance $app)))
+"#;
+
+const TOP_LEVEL: &str = r#"
+(component
+  (core module $m (func (export "run")))
+  (core instance $i (instantiate $m))
+  (func (export "run") (canon lift (core func $i "run"))))
+"#;
+
+const NEITHER: &str = r#"
+(component
+  (core module $m (func (export "start")))
+  (core instance $i (instantiate $m))
+  (func (export "start") (canon lift (core func $i "start"))))
+"#;
+
+// `run` is there, but as an instance rather than a function
+const WRONG_KIND: &str = r#"
+(component
+  (instance $empty)
+  (export "run" (instance $empty)))
+"#;
+
+fn run(wat: &str) -> wasmtime::Result<()> {
+    let engine = Engine::default();
+    let component = Component::new(&engine, wat)?;
+    let entry = find_entry_point(&component)?;
+    let mut store = Store::new(&engine, ());
+    let instance = Linker::new(&engine).instantiate(&mut store, &component)?;
+    let run = instance.get_typed_func::<(), ()>(&mut store, &entry)?;
+    run.call(&mut store, ())
+}
+
+#[test]
+fn runs_the_my_app_run_interface() {
+    run(INTERFACE).unwrap();
+}
+
+#[test]
+fn falls_back_to_a_top_level_run_function() {
+    run(TOP_LEVEL).unwrap();
+}
+
+#[test]
+fn reports_components_without_an_entry_point() {
+    let error = run(NEITHER).unwrap_err();
+    assert!(error.downcast_ref::<NoEntryPoint>().is_some());
+    assert_eq!(
+        error.to_string(),
+        "component exports neither `my:app/run` nor a top-level `run` function"
+    );
+}
+
+#[test]
+fn looks_in_the_interface_a_manifest_names() {
+    let engine = Engine::default();
+    let wat = INTERFACE.replace("my:app/run", "my:app/daemon");
+    let component = Component::new(&engine, &wat).unwrap();
+
+    assert!(find_entry_point_in(&component, "my:app/daemon").is_ok());
+    let error = find_entry_point(&component).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "component exports neither `my:app/run` nor a top-level `run` function"
+    );
+}
+
+#[test]
+fn ignores_exports_named_run_that_are_not_functions() {
+    let error = run(WRONG_KIND).unwrap_err();
+    assert!(error.downcast_ref::<NoEntryPoint>().is_some());
+}
+
+#[test]
+fn checks_the_entry_point_signature() {
+    let engine = Engine::default();
+    let component = Component::new(
+        &engine,
+        r#"
+        (component
+          (core module $m (func (export "run") (param i32)))
+          (core instance $i (instantiate $m))
+          (func (export "run") (param "x" u32) (canon lift (core func $i "run"))))
+        "#,
+    )
+    .unwrap();
+    let entry = find_entry_point(&component).unwrap();
+    let mut store = Store::new(&engine, ());
+    let instance = Linker::new(&engine)
+        .instantiate(&mut store, &component)
+        .unwrap();
+
+    assert!(
+        instance
+            .get_typed_func::<(), ()>(&mut store, &entry)
+            .is_err()
+    );
+}
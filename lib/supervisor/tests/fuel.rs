@@ -0,0 +1,258 @@
+use std::future::{Future, poll_fn};
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+
+use supervisor::{FuelBudget, HostCalls, Outcome, Verdict, Watchdog, supervise};
+use wasmtime::component::{Component, Linker};
+use wasmtime::{Config, Engine, Store};
+
+const SPIN: &str = r#"
+(component
+  (core module $m
+    (func (export "run")
+      (loop $spin (br $spin))))
+  (core instance $i (instantiate $m))
+  (func (export "run") (canon lift (core func $i "run"))))
+"#;
+
+// Spins forever too, but calls the host `tick` import on every iteration
+const TICK: &str = r#"
+(component
+  (import "tick" (func $tick))
+  (core func $tick_lowered (canon lower (func $tick)))
+  (core module $m
+    (import "host" "tick" (func $tick))
+    (func (export "run")
+      (loop $spin (call $tick) (br $spin))))
+  (core instance $host (export "tick" (func $tick_lowered)))
+  (core instance $i (instantiate $m (with "host" (instance $host))))
+  (func (export "run") (canon lift (core func $i "run"))))
+"#;
+
+const COUNT: &str = r#"
+(component
+  (core module $m
+    (func (export "run") (local $i i32)
+      (loop $count
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br_if $count (i32.lt_u (local.get $i) (i32.const 100000))))))
+  (core instance $i (instantiate $m))
+  (func (export "run") (canon lift (core func $i "run"))))
+"#;
+
+// Calls the async host `wait` import a few times, doing next to no work of its own
+const WAIT: &str = r#"
+(component
+  (import "wait" (func $wait))
+  (core func $wait_lowered (canon lower (func $wait)))
+  (core module $m
+    (import "host" "wait" (func $wait))
+    (func (export "run")
+      (call $wait) (call $wait) (call $wait)))
+  (core instance $host (export "wait" (func $wait_lowered)))
+  (core instance $i (instantiate $m (with "host" (instance $host))))
+  (func (export "run") (canon lift (core func $i "run"))))
+"#;
+
+const TRAP: &str = r#"
+(component
+  (core module $m
+    (func (export "run") unreachable))
+  (core instance $i (instantiate $m))
+  (func (export "run") (canon lift (core func $i "run"))))
+"#;
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+// Pending once, like a host function waiting on a timer
+async fn yield_once() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+fn engine() -> Engine {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    config.async_support(true);
+    Engine::new(&config).unwrap()
+}
+
+fn store(engine: &Engine) -> (Store<()>, HostCalls) {
+    let mut store = Store::new(engine, ());
+    FuelBudget { slice: 1_000 }.apply(&mut store).unwrap();
+    let calls = HostCalls::install(&mut store);
+    (store, calls)
+}
+
+fn instantiate(
+    engine: &Engine,
+    store: &mut Store<()>,
+    wat: &str,
+) -> wasmtime::component::TypedFunc<(), ()> {
+    let component = Component::new(engine, wat).unwrap();
+    let mut linker = Linker::new(engine);
+    linker.root().func_wrap("tick", |_, (): ()| Ok(())).unwrap();
+    linker
+        .root()
+        .func_wrap_async("wait", |_, (): ()| {
+            Box::new(async {
+                yield_once().await;
+                Ok(())
+            })
+        })
+        .unwrap();
+    let instance = block_on(linker.instantiate_async(&mut *store, &component)).unwrap();
+    instance
+        .get_typed_func::<(), ()>(&mut *store, "run")
+        .unwrap()
+}
+
+#[test]
+fn infinite_loop_is_killed_after_budget() {
+    let engine = engine();
+    let (mut store, calls) = store(&engine);
+    let run = instantiate(&engine, &mut store, SPIN);
+
+    let mut consulted = 0;
+    let outcome = block_on(supervise(
+        run.call_async(&mut store, ()),
+        &calls,
+        |slices| {
+            consulted = slices;
+            if slices < 3 {
+                Verdict::Resume
+            } else {
+                Verdict::Kill
+            }
+        },
+    ));
+
+    assert!(matches!(outcome, Outcome::Killed { slices: 3 }));
+    assert_eq!(consulted, 3);
+}
+
+#[test]
+fn restarted_guest_runs_in_a_fresh_store() {
+    let engine = engine();
+    let (mut store, calls) = store(&engine);
+    let run = instantiate(&engine, &mut store, SPIN);
+
+    let outcome = block_on(supervise(run.call_async(&mut store, ()), &calls, |_| {
+        Verdict::Restart
+    }));
+    assert!(matches!(outcome, Outcome::Restart { slices: 1 }));
+
+    drop(store);
+    let (mut store, calls) = self::store(&engine);
+    let run = instantiate(&engine, &mut store, COUNT);
+    let outcome = block_on(supervise(run.call_async(&mut store, ()), &calls, |_| {
+        Verdict::Resume
+    }));
+    assert!(matches!(outcome, Outcome::Finished(())));
+}
+
+#[test]
+fn finite_guest_finishes_across_slices() {
+    let engine = engine();
+    let (mut store, calls) = store(&engine);
+    let run = instantiate(&engine, &mut store, COUNT);
+
+    let mut slices_used = 0;
+    let outcome = block_on(supervise(
+        run.call_async(&mut store, ()),
+        &calls,
+        |slices| {
+            slices_used = slices;
+            Verdict::Resume
+        },
+    ));
+
+    assert!(matches!(outcome, Outcome::Finished(())));
+    assert!(
+        slices_used > 1,
+        "expected several yields, got {slices_used}"
+    );
+}
+
+#[test]
+fn traps_are_reported() {
+    let engine = engine();
+    let (mut store, calls) = store(&engine);
+    let run = instantiate(&engine, &mut store, TRAP);
+
+    let outcome = block_on(supervise(run.call_async(&mut store, ()), &calls, |_| {
+        Verdict::Resume
+    }));
+
+    let Outcome::Trapped(trap) = outcome else {
+        panic!("expected a trap");
+    };
+    assert_eq!(
+        trap.downcast_ref::<wasmtime::Trap>(),
+        Some(&wasmtime::Trap::UnreachableCodeReached)
+    );
+}
+
+#[test]
+fn watchdog_restarts_guest_that_never_calls_the_host() {
+    let engine = engine();
+    let (mut store, calls) = store(&engine);
+    let watchdog = Watchdog::new(&calls, 5, Verdict::Restart);
+    let run = instantiate(&engine, &mut store, SPIN);
+
+    let outcome = block_on(supervise(run.call_async(&mut store, ()), &calls, watchdog));
+
+    assert!(matches!(outcome, Outcome::Restart { slices: 5 }));
+}
+
+#[test]
+fn watchdog_resumes_guest_that_keeps_calling_the_host() {
+    let engine = engine();
+    let (mut store, calls) = store(&engine);
+    let mut watchdog = Watchdog::new(&calls, 5, Verdict::Restart);
+    let run = instantiate(&engine, &mut store, TICK);
+
+    let outcome = block_on(supervise(
+        run.call_async(&mut store, ()),
+        &calls,
+        |slices| {
+            use supervisor::BudgetPolicy;
+            match watchdog.on_exhausted(slices) {
+                Verdict::Resume if slices >= 20 => Verdict::Kill,
+                verdict => verdict,
+            }
+        },
+    ));
+
+    assert!(matches!(outcome, Outcome::Killed { slices: 20 }));
+}
+
+#[test]
+fn waits_in_the_host_are_not_slices() {
+    let engine = engine();
+    let (mut store, calls) = store(&engine);
+    let run = instantiate(&engine, &mut store, WAIT);
+
+    let outcome = block_on(supervise(run.call_async(&mut store, ()), &calls, |_| {
+        Verdict::Kill
+    }));
+
+    assert!(matches!(outcome, Outcome::Finished(())));
+    assert_eq!(calls.count(), 3);
+}
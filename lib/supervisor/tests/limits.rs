@@ -0,0 +1,133 @@
+use logging::{CaptureSink, Level, LoggingCtx};
+use supervisor::{Denial, GuestLimiter, GuestLimits};
+use wasmtime::component::{Component, Linker};
+use wasmtime::{Engine, Store};
+
+const PAGE: usize = 64 * 1024;
+
+// `grow(pages)` returns the previous size in pages, or -1 if growth was denied
+const GROW: &str = r#"
+(component
+  (core module $m
+    (memory 1)
+    (func (export "grow") (param i32) (result i32)
+      (memory.grow (local.get 0))))
+  (core instance $i (instantiate $m))
+  (func (export "grow") (param "pages" u32) (result s32)
+    (canon lift (core func $i "grow"))))
+"#;
+
+const LIMITS: GuestLimits = GuestLimits {
+    memory_bytes: 2 * PAGE,
+    table_elements: 64,
+    instances: 4,
+    memories: 1,
+    tables: 1,
+};
+
+// Like the hosts' `HostState`: denials are reported through the guest's log
+struct State {
+    limiter: GuestLimiter,
+    logging_ctx: LoggingCtx,
+}
+
+supervisor::impl_resource_limiter!(State);
+
+fn store(engine: &Engine, limits: GuestLimits, sink: &CaptureSink) -> Store<State> {
+    let mut store = Store::new(
+        engine,
+        State {
+            limiter: GuestLimiter::new(limits),
+            logging_ctx: LoggingCtx::new("grower", sink.clone()),
+        },
+    );
+    store.limiter(|state| state);
+    store
+}
+
+#[test]
+fn growth_within_limit_is_allowed() {
+    let engine = Engine::default();
+    let component = Component::new(&engine, GROW).unwrap();
+    let sink = CaptureSink::new();
+    let mut store = store(&engine, LIMITS, &sink);
+    let instance = Linker::new(&engine)
+        .instantiate(&mut store, &component)
+        .unwrap();
+    let grow = instance
+        .get_typed_func::<(u32,), (i32,)>(&mut store, "grow")
+        .unwrap();
+
+    assert_eq!(grow.call(&mut store, (1,)).unwrap(), (1,));
+    assert_eq!(store.data().limiter.denials(), 0);
+    assert!(sink.records().is_empty());
+}
+
+#[test]
+fn growth_past_limit_is_denied_and_logged() {
+    let engine = Engine::default();
+    let component = Component::new(&engine, GROW).unwrap();
+    let sink = CaptureSink::new();
+    let mut store = store(&engine, LIMITS, &sink);
+    let instance = Linker::new(&engine)
+        .instantiate(&mut store, &component)
+        .unwrap();
+    let grow = instance
+        .get_typed_func::<(u32,), (i32,)>(&mut store, "grow")
+        .unwrap();
+
+    // The guest keeps running, it just sees the usual out-of-memory result
+    assert_eq!(grow.call(&mut store, (4,)).unwrap(), (-1,));
+    assert_eq!(store.data().limiter.denials(), 1);
+
+    let records = sink.records();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].level, Level::Warn);
+    assert_eq!(records[0].target, "limits");
+    assert_eq!(
+        records[0].msg,
+        Denial::Memory {
+            current: PAGE,
+            desired: 5 * PAGE,
+            limit: 2 * PAGE,
+        }
+        .to_string()
+    );
+}
+
+#[test]
+fn initial_memory_over_limit_fails_instantiation() {
+    let engine = Engine::default();
+    let component = Component::new(&engine, GROW).unwrap();
+    let sink = CaptureSink::new();
+    let limits = GuestLimits {
+        memory_bytes: PAGE / 2,
+        ..LIMITS
+    };
+    let mut store = store(&engine, limits, &sink);
+
+    assert!(
+        Linker::new(&engine)
+            .instantiate(&mut store, &component)
+            .is_err()
+    );
+    assert_eq!(sink.records().len(), 1);
+}
+
+#[test]
+fn instance_cap_fails_instantiation() {
+    let engine = Engine::default();
+    let component = Component::new(&engine, GROW).unwrap();
+    let sink = CaptureSink::new();
+    let limits = GuestLimits {
+        instances: 0,
+        ..LIMITS
+    };
+    let mut store = store(&engine, limits, &sink);
+
+    assert!(
+        Linker::new(&engine)
+            .instantiate(&mut store, &component)
+            .is_err()
+    );
+}
@@ -0,0 +1,105 @@
+use supervisor::{RestartDecision, RestartPolicy, RestartTracker, TrapReport};
+use wasmtime::component::{Component, Linker};
+use wasmtime::{Engine, Store};
+
+fn decisions(policy: RestartPolicy, failures: usize) -> Vec<RestartDecision> {
+    let mut tracker = RestartTracker::new(policy);
+    (0..failures).map(|_| tracker.on_failure()).collect()
+}
+
+#[test]
+fn never_gives_up_immediately() {
+    assert_eq!(
+        decisions(RestartPolicy::Never, 2),
+        [RestartDecision::GiveUp, RestartDecision::GiveUp]
+    );
+}
+
+#[test]
+fn always_restarts_without_delay() {
+    let mut tracker = RestartTracker::new(RestartPolicy::Always);
+    for _ in 0..100 {
+        assert_eq!(
+            tracker.on_failure(),
+            RestartDecision::Restart { delay_ms: 0 }
+        );
+    }
+    assert_eq!(tracker.restarts(), 100);
+}
+
+#[test]
+fn max_restarts_stops_after_limit() {
+    assert_eq!(
+        decisions(RestartPolicy::MaxRestarts(2), 4),
+        [
+            RestartDecision::Restart { delay_ms: 0 },
+            RestartDecision::Restart { delay_ms: 0 },
+            RestartDecision::GiveUp,
+            RestartDecision::GiveUp,
+        ]
+    );
+}
+
+#[test]
+fn backoff_doubles_up_to_cap() {
+    let policy = RestartPolicy::ExponentialBackoff {
+        initial_ms: 100,
+        max_ms: 1_000,
+        max_restarts: None,
+    };
+    let delays: Vec<u64> = decisions(policy, 6)
+        .into_iter()
+        .map(|decision| match decision {
+            RestartDecision::Restart { delay_ms } => delay_ms,
+            RestartDecision::GiveUp => panic!("unbounded backoff gave up"),
+        })
+        .collect();
+    assert_eq!(delays, [100, 200, 400, 800, 1_000, 1_000]);
+}
+
+#[test]
+fn backoff_respects_max_restarts() {
+    let policy = RestartPolicy::ExponentialBackoff {
+        initial_ms: 10,
+        max_ms: 10_000,
+        max_restarts: Some(1),
+    };
+    assert_eq!(
+        decisions(policy, 2),
+        [
+            RestartDecision::Restart { delay_ms: 10 },
+            RestartDecision::GiveUp
+        ]
+    );
+}
+
+#[test]
+fn trap_report_includes_reason_and_frames() {
+    let engine = Engine::default();
+    let component = Component::new(
+        &engine,
+        r#"
+        (component
+          (core module $m
+            (func $explode unreachable)
+            (func (export "run") call $explode))
+          (core instance $i (instantiate $m))
+          (func (export "run") (canon lift (core func $i "run"))))
+        "#,
+    )
+    .unwrap();
+    let mut store = Store::new(&engine, ());
+    let instance = Linker::new(&engine)
+        .instantiate(&mut store, &component)
+        .unwrap();
+    let run = instance
+        .get_typed_func::<(), ()>(&mut store, "run")
+        .unwrap();
+
+    let error = run.call(&mut store, ()).unwrap_err();
+    let report = TrapReport::new(&error);
+
+    assert!(report.reason.contains("unreachable"), "{}", report.reason);
+    assert_eq!(report.frames.len(), 2);
+    assert!(report.frames[0].contains("explode"), "{:?}", report.frames);
+}
@@ -0,0 +1,99 @@
+use alloc::vec::Vec;
+
+use guest_image::crc32;
+
+/// Every frame starts with these two bytes, so the decoder can find the next
+/// frame after line noise or a dropped byte.
+pub const SYNC: [u8; 2] = [0xA5, 0x5A];
+// Largest payload of a single frame; Data frames carry a 4-byte offset plus up to MAX_CHUNK bytes
+pub const MAX_PAYLOAD: usize = 4 + crate::MAX_CHUNK;
+// sync, kind, 2-byte length ... 4-byte CRC
+const OVERHEAD: usize = 2 + 1 + 2 + 4;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: u8,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameError {
+    TooLong(u16),
+    BadChecksum,
+}
+
+impl Frame {
+    pub fn new(kind: u8, payload: Vec<u8>) -> Self {
+        Self { kind, payload }
+    }
+
+    /// `SYNC`, kind, little-endian payload length, payload, then a CRC-32
+    /// over everything after the sync bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(OVERHEAD + self.payload.len());
+        bytes.extend_from_slice(&SYNC);
+        bytes.push(self.kind);
+        bytes.extend_from_slice(&(self.payload.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&self.payload);
+        let crc = crc32(&bytes[SYNC.len()..]);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+}
+
+/// Reassembles frames from a byte stream, one byte at a time so it can sit
+/// directly behind a UART read.
+#[derive(Default)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a frame (or the reason it was dropped) once its last byte arrives.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Frame, FrameError>> {
+        // Skip anything that does not start with SYNC
+        if self.buf.len() < SYNC.len() {
+            if byte == SYNC[self.buf.len()] {
+                self.buf.push(byte);
+            } else {
+                self.buf.clear();
+                if byte == SYNC[0] {
+                    self.buf.push(byte);
+                }
+            }
+            return None;
+        }
+
+        self.buf.push(byte);
+        if self.buf.len() < 5 {
+            return None;
+        }
+        let len = u16::from_le_bytes([self.buf[3], self.buf[4]]);
+        if len as usize > MAX_PAYLOAD {
+            self.buf.clear();
+            return Some(Err(FrameError::TooLong(len)));
+        }
+        if self.buf.len() < OVERHEAD + len as usize {
+            return None;
+        }
+
+        let body_end = self.buf.len() - 4;
+        let crc = u32::from_le_bytes([
+            self.buf[body_end],
+            self.buf[body_end + 1],
+            self.buf[body_end + 2],
+            self.buf[body_end + 3],
+        ]);
+        let result = if crc32(&self.buf[SYNC.len()..body_end]) == crc {
+            Ok(Frame::new(self.buf[2], self.buf[5..body_end].to_vec()))
+        } else {
+            Err(FrameError::BadChecksum)
+        };
+        self.buf.clear();
+        Some(result)
+    }
+}
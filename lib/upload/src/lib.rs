@@ -0,0 +1,26 @@
+//! Framed protocol for replacing a guest image over a serial link.
+//!
+//! The uploader (`compiler upload`) drives [`upload`] over a [`Transport`];
+//! the firmware feeds received bytes through a [`FrameDecoder`] into a
+//! [`Receiver`], which writes the guest partition through [`SlotFlash`].
+//! Neither side does any I/O of its own, so both run on Linux in tests.
+#![no_std]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+extern crate std;
+
+mod frame;
+mod message;
+mod receiver;
+mod uploader;
+
+/// Largest image chunk carried by one `Data` request.
+pub const MAX_CHUNK: usize = 1024;
+
+pub use frame::{Frame, FrameDecoder, FrameError, MAX_PAYLOAD, SYNC};
+pub use message::{Request, Response, UploadError};
+pub use receiver::{Receiver, SlotFlash};
+#[cfg(feature = "std")]
+pub use uploader::IoTransport;
+pub use uploader::{Transport, UploadFailed, upload};
@@ -0,0 +1,160 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::frame::Frame;
+
+const BEGIN: u8 = 0x01;
+const DATA: u8 = 0x02;
+const COMMIT: u8 = 0x03;
+const ABORT: u8 = 0x04;
+
+const ACK: u8 = 0x80;
+const COMMITTED: u8 = 0x81;
+const ERROR: u8 = 0x82;
+
+/// Sent by the uploader. An upload is `Begin`, `Data` for every chunk of the
+/// image in order, then `Commit`; each is answered before the next is sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Request<'a> {
+    // `length` is the full image, header included, as written by the compiler
+    Begin { slot: u8, length: u32 },
+    Data { offset: u32, bytes: &'a [u8] },
+    Commit,
+    Abort,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Response {
+    Ack,
+    // The image passed every check and the guest in that slot is being restarted
+    Committed { version: u32 },
+    Error(UploadError),
+}
+
+/// Why the device refused a request. Any error other than `BadFrame` ends
+/// the upload in progress; a corrupted frame can simply be sent again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UploadError {
+    BadFrame,
+    BadRequest,
+    BadSlot,
+    TooLarge,
+    NotStarted,
+    OutOfOrder,
+    Incomplete,
+    Flash,
+    // Header or checksum did not match the data received
+    InvalidImage,
+    // The running engine refused to load the image, e.g. built for another Wasmtime
+    Incompatible,
+}
+
+impl UploadError {
+    const ALL: [UploadError; 10] = [
+        UploadError::BadFrame,
+        UploadError::BadRequest,
+        UploadError::BadSlot,
+        UploadError::TooLarge,
+        UploadError::NotStarted,
+        UploadError::OutOfOrder,
+        UploadError::Incomplete,
+        UploadError::Flash,
+        UploadError::InvalidImage,
+        UploadError::Incompatible,
+    ];
+
+    fn code(self) -> u8 {
+        self as u8 + 1
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        Self::ALL.get(code.checked_sub(1)? as usize).copied()
+    }
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            UploadError::BadFrame => "frame was corrupted in transit",
+            UploadError::BadRequest => "malformed request",
+            UploadError::BadSlot => "no such guest slot",
+            UploadError::TooLarge => "image does not fit in the slot",
+            UploadError::NotStarted => "no upload in progress",
+            UploadError::OutOfOrder => "data chunk out of order",
+            UploadError::Incomplete => "commit before all data was received",
+            UploadError::Flash => "flash write failed",
+            UploadError::InvalidImage => "image header or checksum is invalid",
+            UploadError::Incompatible => "image rejected by the running engine",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl core::error::Error for UploadError {}
+
+impl<'a> Request<'a> {
+    pub fn to_frame(&self) -> Frame {
+        match *self {
+            Request::Begin { slot, length } => {
+                let mut payload = Vec::with_capacity(5);
+                payload.push(slot);
+                payload.extend_from_slice(&length.to_le_bytes());
+                Frame::new(BEGIN, payload)
+            }
+            Request::Data { offset, bytes } => {
+                let mut payload = Vec::with_capacity(4 + bytes.len());
+                payload.extend_from_slice(&offset.to_le_bytes());
+                payload.extend_from_slice(bytes);
+                Frame::new(DATA, payload)
+            }
+            Request::Commit => Frame::new(COMMIT, Vec::new()),
+            Request::Abort => Frame::new(ABORT, Vec::new()),
+        }
+    }
+
+    pub fn parse(frame: &'a Frame) -> Result<Self, UploadError> {
+        let payload = frame.payload.as_slice();
+        match (frame.kind, payload.len()) {
+            (BEGIN, 5) => Ok(Request::Begin {
+                slot: payload[0],
+                length: read_u32(&payload[1..]),
+            }),
+            (DATA, 4..) => Ok(Request::Data {
+                offset: read_u32(payload),
+                bytes: &payload[4..],
+            }),
+            (COMMIT, 0) => Ok(Request::Commit),
+            (ABORT, 0) => Ok(Request::Abort),
+            _ => Err(UploadError::BadRequest),
+        }
+    }
+}
+
+impl Response {
+    pub fn to_frame(&self) -> Frame {
+        match *self {
+            Response::Ack => Frame::new(ACK, Vec::new()),
+            Response::Committed { version } => {
+                Frame::new(COMMITTED, version.to_le_bytes().to_vec())
+            }
+            Response::Error(error) => Frame::new(ERROR, alloc::vec![error.code()]),
+        }
+    }
+
+    /// `None` if the frame is not a response this version understands.
+    pub fn parse(frame: &Frame) -> Option<Self> {
+        let payload = frame.payload.as_slice();
+        match (frame.kind, payload.len()) {
+            (ACK, 0) => Some(Response::Ack),
+            (COMMITTED, 4) => Some(Response::Committed {
+                version: read_u32(payload),
+            }),
+            (ERROR, 1) => UploadError::from_code(payload[0]).map(Response::Error),
+            _ => None,
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
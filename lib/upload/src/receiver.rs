@@ -0,0 +1,163 @@
+use guest_image::{HEADER_LEN, read_image};
+
+use crate::{Request, Response, UploadError};
+
+/// The guest partition as seen by the device.
+pub trait SlotFlash {
+    type Error;
+
+    /// Smallest erasable unit; slot sizes are a multiple of it.
+    const ERASE_SIZE: u32;
+
+    /// Erases `len` bytes at `offset` in `slot`; both are multiples of `ERASE_SIZE`.
+    fn erase(&mut self, slot: usize, offset: u32, len: u32) -> Result<(), Self::Error>;
+    fn write(&mut self, slot: usize, offset: u32, bytes: &[u8]) -> Result<(), Self::Error>;
+    /// The whole slot, as the host would read it at boot.
+    fn read(&self, slot: usize) -> &[u8];
+}
+
+struct Transfer {
+    slot: usize,
+    length: u32,
+    received: u32,
+    // Everything below this offset has been erased and may be written
+    erased: u32,
+}
+
+/// Device side of the upload protocol, independent of the transport.
+///
+/// Sectors are erased just ahead of the data, so no single request keeps
+/// the flash busy for long. The first sector goes on `Begin`, which also
+/// means the old image is gone as soon as an upload starts.
+pub struct Receiver {
+    slot_count: usize,
+    slot_size: u32,
+    transfer: Option<Transfer>,
+}
+
+impl Receiver {
+    pub fn new(slot_count: usize, slot_size: u32) -> Self {
+        Self {
+            slot_count,
+            slot_size,
+            transfer: None,
+        }
+    }
+
+    /// Slot being written by the upload in progress, if any.
+    pub fn active_slot(&self) -> Option<usize> {
+        self.transfer.as_ref().map(|transfer| transfer.slot)
+    }
+
+    /// Applies `request` to `flash`. On `Commit`, `verify` gets the new image,
+    /// signature included, once its checksum was checked and can reject it.
+    pub fn handle<F: SlotFlash>(
+        &mut self,
+        request: Request<'_>,
+        flash: &mut F,
+        verify: impl FnOnce(&[u8]) -> bool,
+    ) -> Response {
+        let result = match request {
+            Request::Begin { slot, length } => self.begin(slot as usize, length, flash),
+            Request::Data { offset, bytes } => self.data(offset, bytes, flash),
+            Request::Commit => self.commit(flash, verify),
+            Request::Abort => {
+                self.transfer = None;
+                Ok(Response::Ack)
+            }
+        };
+        result.unwrap_or_else(|error| {
+            self.transfer = None;
+            Response::Error(error)
+        })
+    }
+
+    fn begin<F: SlotFlash>(
+        &mut self,
+        slot: usize,
+        length: u32,
+        flash: &mut F,
+    ) -> Result<Response, UploadError> {
+        self.transfer = None;
+        if slot >= self.slot_count {
+            return Err(UploadError::BadSlot);
+        }
+        if length > self.slot_size {
+            return Err(UploadError::TooLarge);
+        }
+        if (length as usize) < HEADER_LEN {
+            return Err(UploadError::BadRequest);
+        }
+        flash
+            .erase(slot, 0, F::ERASE_SIZE)
+            .map_err(|_| UploadError::Flash)?;
+        self.transfer = Some(Transfer {
+            slot,
+            length,
+            received: 0,
+            erased: F::ERASE_SIZE,
+        });
+        Ok(Response::Ack)
+    }
+
+    fn data<F: SlotFlash>(
+        &mut self,
+        offset: u32,
+        bytes: &[u8],
+        flash: &mut F,
+    ) -> Result<Response, UploadError> {
+        let transfer = self.transfer.as_mut().ok_or(UploadError::NotStarted)?;
+        if offset != transfer.received {
+            return Err(UploadError::OutOfOrder);
+        }
+        let end = offset
+            .checked_add(bytes.len() as u32)
+            .filter(|&end| end <= transfer.length)
+            .ok_or(UploadError::TooLarge)?;
+
+        while transfer.erased < end {
+            flash
+                .erase(transfer.slot, transfer.erased, F::ERASE_SIZE)
+                .map_err(|_| UploadError::Flash)?;
+            transfer.erased += F::ERASE_SIZE;
+        }
+        flash
+            .write(transfer.slot, offset, bytes)
+            .map_err(|_| UploadError::Flash)?;
+        transfer.received = end;
+        Ok(Response::Ack)
+    }
+
+    fn commit<F: SlotFlash>(
+        &mut self,
+        flash: &mut F,
+        verify: impl FnOnce(&[u8]) -> bool,
+    ) -> Result<Response, UploadError> {
+        let transfer = self.transfer.take().ok_or(UploadError::NotStarted)?;
+        if transfer.received != transfer.length {
+            return Err(UploadError::Incomplete);
+        }
+
+        let image = &flash.read(transfer.slot)[..transfer.length as usize];
+        let checked = match read_image(image) {
+            Ok((header, _)) if header.image_len() == image.len() => {
+                if verify(image) {
+                    Ok(header.version)
+                } else {
+                    Err(UploadError::Incompatible)
+                }
+            }
+            _ => Err(UploadError::InvalidImage),
+        };
+        match checked {
+            Ok(version) => Ok(Response::Committed { version }),
+            Err(error) => {
+                // Leave an empty slot rather than an image the host would refuse at boot
+                flash
+                    .erase(transfer.slot, 0, F::ERASE_SIZE)
+                    .map_err(|_| UploadError::Flash)?;
+                Err(error)
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use core::fmt;
+
+use crate::{FrameDecoder, MAX_CHUNK, Request, Response, UploadError};
+
+// Attempts per request when the device reports a corrupted frame
+const RETRIES: usize = 3;
+
+/// Byte stream to the device, e.g. a serial port.
+pub trait Transport {
+    type Error;
+
+    fn send(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+    /// Blocks until at least one byte arrives; `Ok(0)` means the link closed.
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+}
+
+/// Any `Read + Write` stream, such as a serial device opened as a file.
+#[cfg(feature = "std")]
+pub struct IoTransport<T>(pub T);
+
+#[cfg(feature = "std")]
+impl<T: std::io::Read + std::io::Write> Transport for IoTransport<T> {
+    type Error = std::io::Error;
+
+    fn send(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.write_all(bytes)?;
+        self.0.flush()
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf)
+    }
+}
+
+#[derive(Debug)]
+pub enum UploadFailed<E> {
+    Transport(E),
+    Device(UploadError),
+    Closed,
+    // The device answered with something that is not a valid response to the request
+    BadResponse,
+}
+
+impl<E: fmt::Display> fmt::Display for UploadFailed<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadFailed::Transport(error) => write!(f, "transport error: {error}"),
+            UploadFailed::Device(error) => write!(f, "device refused upload: {error}"),
+            UploadFailed::Closed => write!(f, "connection closed by device"),
+            UploadFailed::BadResponse => write!(f, "unexpected response from device"),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> core::error::Error for UploadFailed<E> {}
+
+/// Writes `image` (a compiler-built slot image) into `slot` on the device
+/// and returns the version the device reports once it has verified it.
+/// `progress` is called with the bytes sent so far and the total.
+pub fn upload<T: Transport>(
+    transport: &mut T,
+    slot: u8,
+    image: &[u8],
+    mut progress: impl FnMut(usize, usize),
+) -> Result<u32, UploadFailed<T::Error>> {
+    let mut decoder = FrameDecoder::new();
+    let begin = Request::Begin {
+        slot,
+        length: image.len() as u32,
+    };
+    expect_ack(request(transport, &mut decoder, begin)?)?;
+
+    let mut sent = 0;
+    for chunk in image.chunks(MAX_CHUNK) {
+        let data = Request::Data {
+            offset: sent as u32,
+            bytes: chunk,
+        };
+        expect_ack(request(transport, &mut decoder, data)?)?;
+        sent += chunk.len();
+        progress(sent, image.len());
+    }
+
+    match request(transport, &mut decoder, Request::Commit)? {
+        Response::Committed { version } => Ok(version),
+        _ => Err(UploadFailed::BadResponse),
+    }
+}
+
+fn expect_ack<E>(response: Response) -> Result<(), UploadFailed<E>> {
+    match response {
+        Response::Ack => Ok(()),
+        _ => Err(UploadFailed::BadResponse),
+    }
+}
+
+// Sends `request` and waits for the answer, resending if the device saw a corrupted frame
+fn request<T: Transport>(
+    transport: &mut T,
+    decoder: &mut FrameDecoder,
+    request: Request<'_>,
+) -> Result<Response, UploadFailed<T::Error>> {
+    let bytes = request.to_frame().encode();
+    for attempt in 1..=RETRIES {
+        transport.send(&bytes).map_err(UploadFailed::Transport)?;
+        match receive(transport, decoder)? {
+            Response::Error(UploadError::BadFrame) if attempt < RETRIES => continue,
+            Response::Error(error) => return Err(UploadFailed::Device(error)),
+            response => return Ok(response),
+        }
+    }
+    Err(UploadFailed::Device(UploadError::BadFrame))
+}
+
+fn receive<T: Transport>(
+    transport: &mut T,
+    decoder: &mut FrameDecoder,
+) -> Result<Response, UploadFailed<T::Error>> {
+    let mut buf = [0u8; 64];
+    loop {
+        let n = transport.recv(&mut buf).map_err(UploadFailed::Transport)?;
+        if n == 0 {
+            return Err(UploadFailed::Closed);
+        }
+        // Responses are tiny and the device never sends unprompted, so the first frame is ours
+        for &byte in &buf[..n] {
+            if let Some(frame) = decoder.push(byte) {
+                let frame = frame.map_err(|_| UploadFailed::BadResponse)?;
+                return Response::parse(&frame).ok_or(UploadFailed::BadResponse);
+            }
+        }
+    }
+}
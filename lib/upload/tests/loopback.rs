@@ -0,0 +1,287 @@
+use std::collections::VecDeque;
+
+use guest_image::{
+    HEADER_LEN, SigningKey, VerifyingKey, verify_image, write_image, write_signed_image,
+};
+use upload::{
+    FrameDecoder, Receiver, Request, Response, SlotFlash, Transport, UploadError, UploadFailed,
+    upload,
+};
+
+const SLOT_SIZE: u32 = 16 * 1024;
+const ERASE: u32 = 4096;
+
+// NOR flash semantics: writes can only clear bits, so writing unerased flash is a bug
+struct RamFlash {
+    slots: Vec<Vec<u8>>,
+    erases: usize,
+}
+
+impl RamFlash {
+    fn new(slots: usize) -> Self {
+        Self {
+            slots: vec![vec![0; SLOT_SIZE as usize]; slots],
+            erases: 0,
+        }
+    }
+}
+
+impl SlotFlash for RamFlash {
+    type Error = ();
+    const ERASE_SIZE: u32 = ERASE;
+
+    fn erase(&mut self, slot: usize, offset: u32, len: u32) -> Result<(), ()> {
+        assert_eq!(offset % ERASE, 0);
+        assert_eq!(len % ERASE, 0);
+        self.erases += 1;
+        self.slots[slot][offset as usize..(offset + len) as usize].fill(0xFF);
+        Ok(())
+    }
+
+    fn write(&mut self, slot: usize, offset: u32, bytes: &[u8]) -> Result<(), ()> {
+        let target = &mut self.slots[slot][offset as usize..offset as usize + bytes.len()];
+        assert!(target.iter().all(|&b| b == 0xFF), "write to unerased flash");
+        target.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn read(&self, slot: usize) -> &[u8] {
+        &self.slots[slot]
+    }
+}
+
+// The device end of an in-memory serial link, answering frames as they complete
+struct Loopback {
+    decoder: FrameDecoder,
+    receiver: Receiver,
+    flash: RamFlash,
+    accept: bool,
+    // When set, only images signed with it are committed, as in pico2-quick
+    key: Option<VerifyingKey>,
+    to_host: VecDeque<u8>,
+    // Flips a bit in the next frame the host sends, to simulate line noise
+    corrupt_next: bool,
+}
+
+impl Loopback {
+    fn new() -> Self {
+        Self {
+            decoder: FrameDecoder::new(),
+            receiver: Receiver::new(2, SLOT_SIZE),
+            flash: RamFlash::new(2),
+            accept: true,
+            key: None,
+            to_host: VecDeque::new(),
+            corrupt_next: false,
+        }
+    }
+}
+
+impl Transport for Loopback {
+    type Error = ();
+
+    fn send(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        let mut bytes = bytes.to_vec();
+        if std::mem::take(&mut self.corrupt_next) {
+            let last = bytes.len() - 1;
+            bytes[last] ^= 0x40;
+        }
+        for byte in bytes {
+            let Some(frame) = self.decoder.push(byte) else {
+                continue;
+            };
+            let accept = self.accept;
+            let key = self.key;
+            let response = match frame {
+                Ok(frame) => match Request::parse(&frame) {
+                    Ok(request) => self.receiver.handle(request, &mut self.flash, |image| {
+                        accept && key.is_none_or(|key| verify_image(image, &key).is_ok())
+                    }),
+                    Err(error) => Response::Error(error),
+                },
+                Err(_) => Response::Error(UploadError::BadFrame),
+            };
+            self.to_host.extend(response.to_frame().encode());
+        }
+        Ok(())
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let n = buf.len().min(self.to_host.len());
+        for slot in &mut buf[..n] {
+            *slot = self.to_host.pop_front().unwrap();
+        }
+        Ok(n)
+    }
+}
+
+fn image(len: usize) -> Vec<u8> {
+    let payload: Vec<u8> = (0..len).map(|i| (i * 7 + 3) as u8).collect();
+    write_image(42, 0, &payload)
+}
+
+#[test]
+fn uploads_image_into_slot() {
+    let mut link = Loopback::new();
+    let image = image(9000);
+    let mut last_progress = 0;
+
+    let version = upload(&mut link, 1, &image, |sent, total| {
+        assert!(sent > last_progress && total == image.len());
+        last_progress = sent;
+    })
+    .unwrap();
+
+    assert_eq!(version, 42);
+    assert_eq!(last_progress, image.len());
+    assert_eq!(&link.flash.slots[1][..image.len()], image.as_slice());
+    // Only the sectors the image covers were erased
+    assert_eq!(link.flash.erases, image.len().div_ceil(ERASE as usize));
+    assert!(link.flash.slots[0].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn engine_rejection_leaves_an_empty_slot() {
+    let mut link = Loopback::new();
+    link.accept = false;
+
+    let result = upload(&mut link, 0, &image(100), |_, _| {});
+
+    assert!(matches!(
+        result,
+        Err(UploadFailed::Device(UploadError::Incompatible))
+    ));
+    assert!(link.flash.slots[0][..HEADER_LEN].iter().all(|&b| b == 0xFF));
+}
+
+#[test]
+fn corrupted_image_is_refused() {
+    let mut link = Loopback::new();
+    let mut image = image(2000);
+    image[HEADER_LEN + 10] ^= 0xFF;
+
+    let result = upload(&mut link, 0, &image, |_, _| {});
+
+    assert!(matches!(
+        result,
+        Err(UploadFailed::Device(UploadError::InvalidImage))
+    ));
+}
+
+#[test]
+fn image_larger_than_slot_is_refused_up_front() {
+    let mut link = Loopback::new();
+
+    let result = upload(&mut link, 0, &image(SLOT_SIZE as usize), |_, _| {});
+
+    assert!(matches!(
+        result,
+        Err(UploadFailed::Device(UploadError::TooLarge))
+    ));
+    assert_eq!(link.flash.erases, 0);
+}
+
+#[test]
+fn unknown_slot_is_refused() {
+    let mut link = Loopback::new();
+
+    let result = upload(&mut link, 2, &image(100), |_, _| {});
+
+    assert!(matches!(
+        result,
+        Err(UploadFailed::Device(UploadError::BadSlot))
+    ));
+}
+
+#[test]
+fn corrupted_frame_is_resent() {
+    let mut link = Loopback::new();
+    link.corrupt_next = true;
+
+    assert_eq!(upload(&mut link, 0, &image(3000), |_, _| {}).unwrap(), 42);
+}
+
+#[test]
+fn data_must_arrive_in_order() {
+    let mut receiver = Receiver::new(1, SLOT_SIZE);
+    let mut flash = RamFlash::new(1);
+
+    let data = Request::Data {
+        offset: 0,
+        bytes: &[1, 2, 3],
+    };
+    assert_eq!(
+        receiver.handle(data, &mut flash, |_| true),
+        Response::Error(UploadError::NotStarted)
+    );
+
+    let begin = Request::Begin {
+        slot: 0,
+        length: 100,
+    };
+    assert_eq!(receiver.handle(begin, &mut flash, |_| true), Response::Ack);
+    assert_eq!(receiver.active_slot(), Some(0));
+    let skipped = Request::Data {
+        offset: 10,
+        bytes: &[1, 2, 3],
+    };
+    assert_eq!(
+        receiver.handle(skipped, &mut flash, |_| true),
+        Response::Error(UploadError::OutOfOrder)
+    );
+    // Errors end the transfer
+    assert_eq!(receiver.active_slot(), None);
+}
+
+#[test]
+fn commit_requires_every_byte() {
+    let mut receiver = Receiver::new(1, SLOT_SIZE);
+    let mut flash = RamFlash::new(1);
+    let image = image(500);
+
+    let begin = Request::Begin {
+        slot: 0,
+        length: image.len() as u32,
+    };
+    receiver.handle(begin, &mut flash, |_| true);
+    let data = Request::Data {
+        offset: 0,
+        bytes: &image[..200],
+    };
+    receiver.handle(data, &mut flash, |_| true);
+
+    assert_eq!(
+        receiver.handle(Request::Commit, &mut flash, |_| true),
+        Response::Error(UploadError::Incomplete)
+    );
+}
+
+#[test]
+fn decoder_resyncs_after_garbage() {
+    let frame = Request::Commit.to_frame();
+    let mut stream = vec![0x00, 0xA5, 0x13, 0xA5];
+    stream.extend(frame.encode());
+
+    let mut decoder = FrameDecoder::new();
+    let frames: Vec<_> = stream.into_iter().filter_map(|b| decoder.push(b)).collect();
+
+    assert_eq!(frames, [Ok(frame)]);
+}
+
+#[test]
+fn signature_is_uploaded_with_the_image() {
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let mut link = Loopback::new();
+    link.key = Some(key.verifying_key());
+    let payload = vec![0x5A; 5000];
+
+    let signed = write_signed_image(3, 0, &payload, &key);
+    assert_eq!(upload(&mut link, 0, &signed, |_, _| {}).unwrap(), 3);
+    assert_eq!(&link.flash.slots[0][..signed.len()], signed.as_slice());
+
+    let unsigned = write_image(4, 0, &payload);
+    assert!(matches!(
+        upload(&mut link, 0, &unsigned, |_, _| {}),
+        Err(UploadFailed::Device(UploadError::Incompatible))
+    ));
+}
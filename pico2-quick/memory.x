@@ -3,8 +3,15 @@ MEMORY {
      * The RP2350 has either external or internal flash.
      *
      * 2 MiB is a safe default here, although a Pico 2 has 4 MiB.
+     * The first half holds the firmware, the second half the guests.
      */
-    FLASH : ORIGIN = 0x10000000, LENGTH = 2048K
+    FLASH : ORIGIN = 0x10000000, LENGTH = 1024K
+    /*
+     * Precompiled guest images, flashed independently of the firmware.
+     * Split into slots of guest_image::SLOT_SIZE (512K), one guest each,
+     * every slot starting with a guest_image header.
+     */
+    GUESTS : ORIGIN = 0x10100000, LENGTH = 1024K
     /*
      * RAM consists of 8 banks, SRAM0-SRAM7, with a striped mapping.
      * This is usually good for performance, as it distributes load on
@@ -71,5 +78,8 @@ SECTIONS {
 
 } INSERT AFTER .uninit;
 
+__guests_start = ORIGIN(GUESTS);
+__guests_end = ORIGIN(GUESTS) + LENGTH(GUESTS);
+
 PROVIDE(start_to_end = __end_block_addr - __start_block_addr);
 PROVIDE(end_to_start = __start_block_addr - __end_block_addr);
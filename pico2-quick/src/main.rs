@@ -3,47 +3,357 @@
 
 extern crate alloc;
 
+use alloc::boxed::Box;
 use alloc::collections::BTreeMap;
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::ptr::NonNull;
 use defmt::info;
 use embassy_executor::Spawner;
+use embassy_futures::select::{Either, select};
+use embassy_rp::bind_interrupts;
+use embassy_rp::flash::{Blocking as FlashBlocking, FLASH_BASE, Flash};
 use embassy_rp::gpio::{Level, Output};
+use embassy_rp::peripherals::{FLASH, UART0};
 use embassy_rp::spi::{Config as RpSpiConfig, Phase, Polarity, Spi};
+use embassy_rp::uart::{BufferedInterruptHandler, BufferedUart, Config as UartConfig};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Instant, Timer};
 use embedded_alloc::Heap;
+use embedded_io_async::{Read, Write};
 use {defmt_rtt as _, panic_probe as _};
 
-use wasmtime::component::{Component, HasSelf, Linker, ResourceTable};
-use wasmtime::{Config, Engine, Store};
+use wasmtime::component::{Component, ComponentExportIndex, Linker};
+use wasmtime::{Engine, Store};
 
 // Import contexts and views
 use delay::{DelayCtx, DelayView};
-use gpio::{GpioCtx, GpioView};
-use spi::{SpiCtx, SpiView};
+use diagnostics::{DiagnosticsCtx, DiagnosticsView, HeapSource, TrackingHeap};
+use engine_config::{FINGERPRINT, HEAP_SIZE};
+use gpio::{GpioCtx, GpioView, Level as PinLevel};
+use guest_bundle::{Bundle, Entry, is_bundle};
+use guest_compress::{AlignedBytes, is_compressed};
+use guest_image::{SLOT_COUNT, SLOT_SIZE, VerifyingKey, verify_image};
+use logging::{DefmtSink, Level as LogLevel, LoggingCtx, LoggingView, RateLimit};
+use spi::{RpSpiDevice, SharedSpi, SpiCtx, SpiDevice, SpiView};
+use supervisor::{
+    BudgetPolicy, FuelBudget, GUEST_BUDGET, GUEST_LIMITS, GUEST_MAX_QUIET_SLICES,
+    GUEST_RESTART_POLICY, GuestLimiter, GuestLimits, HostCalls, Outcome, RUN_INTERFACE,
+    RestartDecision, RestartTracker, TrapReport, Verdict, Watchdog, find_entry_point_in, supervise,
+};
+use upload::{FrameDecoder, Receiver, Request, Response, SlotFlash, UploadError};
 
-wasmtime::component::bindgen!({
-    path: "../guests/temperature-sensor/wit",
-    world: "guest",
-});
+const LOG_RETENTION: usize = 32;
+
+// How often heap usage is reported while a guest runs, in fuel slices
+const HEAP_REPORT_SLICES: u32 = 100;
+
+// Guests `main` can spawn, one task each, across all slots and bundles
+const MAX_GUESTS: usize = 4;
 
-const HEAP_SIZE: usize = 470 * 1024;
+// Pico 2 (W25Q32); only the GUESTS region is ever written
+const FLASH_SIZE: usize = 4 * 1024 * 1024;
+
+bind_interrupts!(struct Irqs {
+    UART0_IRQ => BufferedInterruptHandler<UART0>;
+});
 
 #[global_allocator]
-static HEAP: Heap = Heap::empty();
+static HEAP: TrackingHeap<Heap> = TrackingHeap::new(Heap::empty());
+
+fn report_heap(guest: &str, stage: &str) {
+    info!("[{}] Heap {}: {}", guest, stage, HEAP.stats());
+}
+
+const _: () = assert!(MAX_GUESTS >= SLOT_COUNT, "every slot needs a guest task");
+
+// Written by `compiler keygen`. Deserializing trusts the bytes completely,
+// so only images that verify against it get that far
+const GUEST_SIGNING_KEY: &[u8; 32] = include_bytes!("../guest-signing.pub");
+
+// What the `add_to_linker` calls in `main` provide, also what `compiler compile`
+// checks guests against
+const HOST_CAPABILITIES: &str = include_str!("../host-capabilities.txt");
+
+// The first interface a bundled guest imports that the linker does not provide.
+// Versions are left to the linker, which refuses incompatible ones when instantiating
+fn unlinked_interface<'a>(entry: &Entry<'a>) -> Option<&'a str> {
+    let unversioned = |name: &'a str| name.split('@').next().unwrap_or(name);
+    let provided = |name| {
+        HOST_CAPABILITIES
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim())
+            .any(|line| !line.is_empty() && unversioned(line) == unversioned(name))
+    };
+    entry.interfaces.clone().find(|name| !provided(name))
+}
+
+fn signing_key() -> VerifyingKey {
+    VerifyingKey::from_bytes(GUEST_SIGNING_KEY)
+        .expect("guest-signing.pub is not an Ed25519 public key")
+}
+
+// Start of the GUESTS region in memory.x
+unsafe extern "C" {
+    static __guests_start: u8;
+}
+
+// Flash is memory mapped (XIP), so images are used in place with
+// `Component::deserialize_raw` and only instance state comes out of the heap,
+// unless the component is compressed, see `inflate`
+fn guest_slot(slot: usize) -> &'static [u8] {
+    unsafe {
+        let start = (&raw const __guests_start).add(slot * SLOT_SIZE);
+        core::slice::from_raw_parts(start, SLOT_SIZE)
+    }
+}
+
+// Offset of a slot from the start of flash, as the flash driver addresses it
+fn guest_slot_offset(slot: usize) -> u32 {
+    let start = (&raw const __guests_start) as usize + slot * SLOT_SIZE;
+    (start - FLASH_BASE as usize) as u32
+}
+
+// The guest partition as written by the upload task. Slots are read back
+// through XIP, which the flash driver flushes after every erase and write
+struct GuestFlash(Flash<'static, FLASH, FlashBlocking, FLASH_SIZE>);
+
+impl SlotFlash for GuestFlash {
+    type Error = embassy_rp::flash::Error;
+    const ERASE_SIZE: u32 = embassy_rp::flash::ERASE_SIZE as u32;
+
+    fn erase(&mut self, slot: usize, offset: u32, len: u32) -> Result<(), Self::Error> {
+        let start = guest_slot_offset(slot) + offset;
+        self.0.blocking_erase(start, start + len)
+    }
+
+    fn write(&mut self, slot: usize, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0
+            .blocking_write(guest_slot_offset(slot) + offset, bytes)
+    }
+
+    fn read(&self, slot: usize) -> &[u8] {
+        guest_slot(slot)
+    }
+}
+
+// How the upload task takes a guest off its slot: it raises `stop`, waits for
+// `stopped` (the component no longer points into the slot), rewrites the slot
+// and raises `reload` when the transfer is over, successful or not
+struct GuestControl {
+    stop: Signal<CriticalSectionRawMutex, ()>,
+    stopped: Signal<CriticalSectionRawMutex, ()>,
+    reload: Signal<CriticalSectionRawMutex, ()>,
+}
+
+impl GuestControl {
+    const fn new() -> Self {
+        Self {
+            stop: Signal::new(),
+            stopped: Signal::new(),
+            reload: Signal::new(),
+        }
+    }
+}
+
+// Indexed like the guests `discover_guests` returned
+static CONTROL: [GuestControl; MAX_GUESTS] = [const { GuestControl::new() }; MAX_GUESTS];
+
+// A guest found at boot: an entry of the bundle in a slot, or the single
+// component in it. Any component exporting `my:app/run` (see wit/app.wit) can run
+struct GuestSpec {
+    name: &'static str,
+    // Index into the GUESTS flash region, see `guest_slot`
+    slot: usize,
+    // Looked up by name in the slot's bundle, rather than being the whole image
+    bundled: bool,
+    // Interface holding `run`, see `find_entry_point_in`
+    export: &'static str,
+    limits: GuestLimits,
+    budget: FuelBudget,
+}
+
+impl GuestSpec {
+    // A manifest can only tighten the host's limits
+    fn for_entry(slot: usize, entry: &Entry) -> Self {
+        let memory_bytes = match entry.limits.memory_bytes {
+            0 => GUEST_LIMITS.memory_bytes,
+            bytes => (bytes as usize).min(GUEST_LIMITS.memory_bytes),
+        };
+        let slice = match entry.limits.fuel_slice {
+            0 => GUEST_BUDGET.slice,
+            fuel => (fuel as u64).min(GUEST_BUDGET.slice),
+        };
+        Self {
+            name: leak_str(entry.name),
+            slot,
+            bundled: true,
+            export: leak_str(entry.export),
+            limits: GuestLimits {
+                memory_bytes,
+                ..GUEST_LIMITS
+            },
+            budget: FuelBudget { slice },
+        }
+    }
+
+    fn for_slot(slot: usize) -> Self {
+        Self {
+            name: SLOT_DEFAULTS[slot].name,
+            slot,
+            bundled: false,
+            export: RUN_INTERFACE,
+            limits: GUEST_LIMITS,
+            budget: GUEST_BUDGET,
+        }
+    }
+}
+
+// Names from a bundle are copied out of flash, which an upload may rewrite
+fn leak_str(s: &str) -> &'static str {
+    Box::leak(s.into())
+}
+
+// Who runs from a slot holding a single component rather than a bundle, and
+// the devices it is given
+struct SlotDefault {
+    name: &'static str,
+    spi: &'static [&'static str],
+    gpio: &'static [&'static str],
+}
+
+const SLOT_DEFAULTS: [SlotDefault; SLOT_COUNT] = [
+    SlotDefault {
+        name: "temperature-sensor",
+        spi: &["sensor"],
+        gpio: &[],
+    },
+    SlotDefault {
+        name: "pacman",
+        spi: &["display"],
+        gpio: &["DC", "RES", "VBATC", "VDDC"],
+    },
+];
+
+// Devices guests are given by the names bundle manifests use, each to one guest at most
+struct Board {
+    // Chip selects on SPI0
+    spi: Vec<(&'static str, Output<'static>)>,
+    // With the level a pin is driven back to when its guest is reset
+    gpio: Vec<(&'static str, Output<'static>, Option<PinLevel>)>,
+}
+
+// What `Board::grant` handed out
+struct Grant {
+    cs: Option<Output<'static>>,
+    pins: Vec<(&'static str, Output<'static>, Option<PinLevel>)>,
+}
+
+impl Board {
+    // All of the devices or none, so a refused guest takes nothing from the others
+    fn grant(&mut self, spi: &[&str], gpio: &[&str]) -> Result<Grant, String> {
+        if spi.len() > 1 {
+            return Err("a guest can only be given one SPI device".to_string());
+        }
+        for name in spi {
+            if !self.spi.iter().any(|(device, _)| device == name) {
+                return Err(alloc::format!("SPI device {name} is unknown or taken"));
+            }
+        }
+        for (i, label) in gpio.iter().enumerate() {
+            if !self.gpio.iter().any(|(pin, ..)| pin == label) || gpio[..i].contains(label) {
+                return Err(alloc::format!("pin {label} is unknown or taken"));
+            }
+        }
+
+        let cs = spi.first().map(|name| {
+            let index = self.spi.iter().position(|(device, _)| device == name);
+            self.spi.swap_remove(index.unwrap()).1
+        });
+        let pins = gpio
+            .iter()
+            .map(|label| {
+                let index = self.gpio.iter().position(|(pin, ..)| pin == label);
+                self.gpio.swap_remove(index.unwrap())
+            })
+            .collect();
+        Ok(Grant { cs, pins })
+    }
+}
 
 // --- Host State ---
+// One per guest: each guest only sees the pins and chip select it was given
 pub struct HostState {
     pub spi_ctx: SpiCtx,
     pub gpio_ctx: GpioCtx,
     pub delay_ctx: DelayCtx,
+    pub logging_ctx: LoggingCtx,
+    pub limiter: GuestLimiter,
+    pub diagnostics_ctx: DiagnosticsCtx,
 }
 
-impl my::debug::logging::Host for HostState {
-    fn log(&mut self, msg: alloc::string::String) {
-        // Print the guest's string directly to probe-rs using defmt!
-        defmt::info!("[Guest] {}", msg.as_str());
+impl HostState {
+    fn new(
+        name: &'static str,
+        spi: &'static SharedSpi,
+        cs: Option<Output<'static>>,
+        limits: GuestLimits,
+    ) -> Self {
+        Self {
+            spi_ctx: SpiCtx::new(
+                cs.map(|cs| Box::new(RpSpiDevice::new(spi, cs)) as Box<dyn SpiDevice>),
+            ),
+            gpio_ctx: GpioCtx {
+                pins: BTreeMap::new(),
+                safe_levels: BTreeMap::new(),
+            },
+            delay_ctx: DelayCtx::new(),
+            logging_ctx: LoggingCtx::new(name, DefmtSink)
+                .with_min_level(LogLevel::Debug)
+                .with_dedup()
+                // defmt over RTT is synchronous, so a guest logging every frame stalls everything
+                .with_rate_limit(
+                    RateLimit {
+                        max_records: 20,
+                        window_ms: 1000,
+                    },
+                    || Instant::now().as_millis(),
+                )
+                .with_retention(LOG_RETENTION),
+            limiter: GuestLimiter::new(limits),
+            diagnostics_ctx: DiagnosticsCtx::new(&HEAP),
+        }
+    }
+
+    // Gives the guest a pin under `label`, driven back to `safe` when the guest is reset
+    fn with_pin(mut self, label: &str, pin: Output<'static>, safe: Option<PinLevel>) -> Self {
+        if let Some(level) = safe {
+            self.gpio_ctx.safe_levels.insert(label.to_string(), level);
+        }
+        self.gpio_ctx.pins.insert(label.to_string(), Box::new(pin));
+        self
+    }
+
+    fn with_pins(self, pins: Vec<(&'static str, Output<'static>, Option<PinLevel>)>) -> Self {
+        pins.into_iter().fold(self, |state, (label, pin, safe)| {
+            state.with_pin(label, pin, safe)
+        })
+    }
+
+    // Puts the hardware back in a known state before the guest is instantiated again
+    fn reset(&mut self) {
+        self.spi_ctx.reset();
+        self.gpio_ctx.reset();
+        self.logging_ctx.flush();
     }
 }
 
+supervisor::impl_resource_limiter!(HostState);
+
 impl SpiView for HostState {
     fn spi_ctx(&mut self) -> &mut SpiCtx {
         &mut self.spi_ctx
@@ -62,6 +372,18 @@ impl DelayView for HostState {
     }
 }
 
+impl LoggingView for HostState {
+    fn logging_ctx(&mut self) -> &mut LoggingCtx {
+        &mut self.logging_ctx
+    }
+}
+
+impl DiagnosticsView for HostState {
+    fn diagnostics_ctx(&mut self) -> &mut DiagnosticsCtx {
+        &mut self.diagnostics_ctx
+    }
+}
+
 // --- Wasmtime TLS Hooks ---
 static mut TLS_PTR: *mut u8 = core::ptr::null_mut();
 #[unsafe(no_mangle)]
@@ -76,30 +398,27 @@ pub extern "C" fn wasmtime_tls_set(ptr: *mut u8) {
 }
 
 #[embassy_executor::main]
-async fn main(_spawner: Spawner) {
+async fn main(spawner: Spawner) {
     let p = embassy_rp::init(Default::default());
 
     // Initialize Heap
     {
         use core::mem::MaybeUninit;
         static mut HEAP_MEM: [MaybeUninit<u8>; HEAP_SIZE] = [MaybeUninit::uninit(); HEAP_SIZE];
-        unsafe { HEAP.init(core::ptr::addr_of_mut!(HEAP_MEM) as usize, HEAP_SIZE) }
+        unsafe {
+            HEAP.inner()
+                .init(core::ptr::addr_of_mut!(HEAP_MEM) as usize, HEAP_SIZE)
+        }
+        HEAP.set_capacity(HEAP_SIZE);
     }
 
     info!("Heap initialized.");
 
-    let mut config = Config::new();
-    config.target("pulley32").unwrap();
-    config.wasm_component_model(true);
-    config.gc_support(false);
-    config.signals_based_traps(false);
-    config.memory_init_cow(false);
-    config.memory_guard_size(0);
-    config.memory_reservation(0);
-    config.max_wasm_stack(16 * 1024); // Limit internal stack size
-    config.memory_reservation_for_growth(0);
+    // Shared with the compiler, so precompiled guests match this engine
+    let config = engine_config::config().unwrap();
 
-    let engine = Engine::new(&config).expect("Engine failed");
+    // The engine, linker and bus are shared by every guest task for the life of the program
+    let engine: &'static Engine = Box::leak(Box::new(Engine::new(&config).expect("Engine failed")));
 
     // --- Initialize SPI hardware ---
     let clk = p.PIN_18;
@@ -112,44 +431,592 @@ async fn main(_spawner: Spawner) {
     spi_config.phase = Phase::CaptureOnFirstTransition;
 
     let spi_driver = Spi::new_blocking(p.SPI0, clk, mosi, miso, spi_config);
+    let spi_bus: &'static SharedSpi = Box::leak(Box::new(Mutex::new(RefCell::new(spi_driver))));
 
-    // Create the CS pin (GP17) and default it to High
-    let cs_pin = Output::new(p.PIN_17, Level::High);
-
-    let host_state = HostState {
-        spi_ctx: SpiCtx {
-            table: ResourceTable::new(),
-            spi: spi_driver,
-            cs: cs_pin, // <-- THIS IS THE FIX (Passing the pin to SPI)
-        },
-        gpio_ctx: GpioCtx {
-            pins: BTreeMap::new(), // No pins needed in GPIO map anymore!
-        },
-        delay_ctx: DelayCtx {},
+    let mut board = Board {
+        // Both devices sit on SPI0, told apart by their CS pins
+        spi: vec![
+            ("sensor", Output::new(p.PIN_17, Level::High)),
+            ("display", Output::new(p.PIN_20, Level::High)),
+        ],
+        // Pmod OLED control lines; VBATC and VDDC are active low, so High keeps the panel off
+        gpio: vec![
+            ("DC", Output::new(p.PIN_21, Level::Low), None),
+            ("RES", Output::new(p.PIN_22, Level::High), None),
+            (
+                "VBATC",
+                Output::new(p.PIN_26, Level::High),
+                Some(PinLevel::High),
+            ),
+            (
+                "VDDC",
+                Output::new(p.PIN_27, Level::High),
+                Some(PinLevel::High),
+            ),
+        ],
     };
+    let (specs, states): (Vec<_>, Vec<_>) =
+        discover_guests(&mut board, spi_bus).into_iter().unzip();
+    let specs: &'static [GuestSpec] = Box::leak(specs.into_boxed_slice());
 
-    let mut store = Store::new(&engine, host_state);
-    let mut linker = Linker::new(&engine);
+    let mut linker = Linker::new(engine);
 
+    // Keep host-capabilities.txt in step, the compiler and `unlinked_interface` check
+    // guests' imports against it
     spi::add_to_linker(&mut linker).unwrap();
     gpio::add_to_linker(&mut linker).unwrap();
     delay::add_to_linker(&mut linker).unwrap();
-    my::debug::logging::add_to_linker::<HostState, HasSelf<HostState>>(&mut linker, |state| state)
+    logging::add_to_linker(&mut linker).unwrap();
+    diagnostics::add_to_linker(&mut linker).unwrap();
+    let linker: &'static Linker<HostState> = Box::leak(Box::new(linker));
+
+    for ((spec, control), host_state) in specs.iter().zip(&CONTROL).zip(states) {
+        spawner
+            .spawn(guest_task(spec, control, engine, linker, host_state))
+            .unwrap();
+    }
+
+    // --- Guest upload over UART0 (GP0 TX, GP1 RX), see `compiler upload` ---
+    // The receive buffer holds a whole data frame, since nothing is read while flash is busy
+    let tx_buf = Box::leak(Box::new([0u8; 64]));
+    let rx_buf = Box::leak(Box::new([0u8; 2048]));
+    let uart = BufferedUart::new(
+        p.UART0,
+        p.PIN_0,
+        p.PIN_1,
+        Irqs,
+        tx_buf,
+        rx_buf,
+        UartConfig::default(),
+    );
+    let flash = GuestFlash(Flash::new_blocking(p.FLASH));
+    spawner
+        .spawn(upload_task(uart, flash, engine, specs))
         .unwrap();
+}
+
+// Every autostart entry of a slot's bundle becomes a guest. A slot holding a
+// single component, or nothing usable yet, gets its default guest so an
+// upload can bring it up. Devices are handed out once, here, in slot order;
+// guests added by a later upload need a restart to run
+fn discover_guests(board: &mut Board, spi: &'static SharedSpi) -> Vec<(GuestSpec, HostState)> {
+    let key = signing_key();
+    let mut guests = Vec::new();
+    for (slot, defaults) in SLOT_DEFAULTS.iter().enumerate() {
+        let payload = verify_image(guest_slot(slot), &key)
+            .ok()
+            .filter(|(header, _)| header.check_engine(FINGERPRINT).is_ok())
+            .map(|(_, payload)| payload)
+            .filter(|payload| is_bundle(payload));
+        let candidates: Vec<(GuestSpec, Vec<&str>, Vec<&str>)> = match payload.map(Bundle::parse) {
+            Some(Ok(bundle)) => bundle
+                .entries()
+                .filter(|entry| entry.autostart)
+                .filter(|entry| match unlinked_interface(entry) {
+                    Some(interface) => {
+                        defmt::error!(
+                            "[{}] Not starting guest, it imports {} which the host does not provide",
+                            entry.name,
+                            interface
+                        );
+                        false
+                    }
+                    None => true,
+                })
+                .map(|entry| {
+                    let spec = GuestSpec::for_entry(slot, &entry);
+                    (spec, entry.spi.collect(), entry.gpio.collect())
+                })
+                .collect(),
+            Some(Err(err)) => {
+                defmt::error!(
+                    "Bad bundle in slot {}: {}",
+                    slot,
+                    defmt::Display2Format(&err)
+                );
+                Vec::new()
+            }
+            None => {
+                vec![(
+                    GuestSpec::for_slot(slot),
+                    defaults.spi.into(),
+                    defaults.gpio.into(),
+                )]
+            }
+        };
+
+        for (spec, spi_devices, gpio) in candidates {
+            if guests.len() == MAX_GUESTS {
+                defmt::warn!(
+                    "[{}] Not starting guest, already running {}",
+                    spec.name,
+                    MAX_GUESTS
+                );
+                continue;
+            }
+            match board.grant(&spi_devices, &gpio) {
+                Ok(grant) => {
+                    info!("[{}] Guest found in slot {}", spec.name, spec.slot);
+                    let state =
+                        HostState::new(spec.name, spi, grant.cs, spec.limits).with_pins(grant.pins);
+                    guests.push((spec, state));
+                }
+                Err(err) => {
+                    defmt::error!("[{}] Not starting guest: {}", spec.name, err.as_str());
+                }
+            }
+        }
+    }
+    guests
+}
+
+// A guest ready to run. A compressed component is inflated into `_code`,
+// which the component points into, so it is declared last to be dropped last
+struct LoadedGuest {
+    component: Component,
+    entry: ComponentExportIndex,
+    _code: Option<AlignedBytes>,
+}
 
-    let guest_bytes = include_bytes!("guest.pulley");
+// Inflates a compressed component into the heap; anything else is used in place.
+// The check against free heap only catches the hopeless cases; a fragmented
+// heap can still fail the allocation, which comes back as `OutOfMemory` and
+// leaves the guest down like any other load failure
+fn inflate(component: &[u8]) -> Result<Option<AlignedBytes>, guest_compress::CompressError> {
+    if !is_compressed(component) {
+        return Ok(None);
+    }
+    let stats = HEAP.stats();
+    guest_compress::decompress(component, stats.capacity - stats.used).map(Some)
+}
+
+// Reads the guest's image from its slot; failures are logged and leave the guest down
+fn load_guest(spec: &GuestSpec, engine: &Engine) -> Option<LoadedGuest> {
+    let image = verify_image(guest_slot(spec.slot), &signing_key())
+        .and_then(|(header, payload)| header.check_engine(FINGERPRINT).map(|()| (header, payload)));
+    let (header, payload) = match image {
+        Ok(image) => image,
+        Err(err) => {
+            defmt::error!(
+                "[{}] Not starting guest, slot {}: {}",
+                spec.name,
+                spec.slot,
+                defmt::Display2Format(&err)
+            );
+            return None;
+        }
+    };
+    // The slot may have been rewritten since boot, with a bundle or without one
+    let component = match (spec.bundled, is_bundle(payload)) {
+        (true, true) => match Bundle::parse(payload).map(|bundle| bundle.find(spec.name)) {
+            Ok(Some(entry)) => entry.component,
+            Ok(None) => {
+                defmt::error!(
+                    "[{}] Not starting guest, no longer in the bundle in slot {}",
+                    spec.name,
+                    spec.slot
+                );
+                return None;
+            }
+            Err(err) => {
+                defmt::error!(
+                    "[{}] Not starting guest, slot {}: {}",
+                    spec.name,
+                    spec.slot,
+                    defmt::Display2Format(&err)
+                );
+                return None;
+            }
+        },
+        (false, false) => payload,
+        (bundled, _) => {
+            defmt::error!(
+                "[{}] Not starting guest, slot {} now holds {}; restart to run it",
+                spec.name,
+                spec.slot,
+                if bundled {
+                    "a single component"
+                } else {
+                    "a bundle"
+                }
+            );
+            return None;
+        }
+    };
     info!(
-        "Deserializing component (Size: {} bytes)...",
-        guest_bytes.len()
+        "[{}] Deserializing component v{} from slot {} (Size: {} bytes)...",
+        spec.name,
+        header.version,
+        spec.slot,
+        component.len()
     );
+    let code = match inflate(component) {
+        Ok(code) => code,
+        Err(err) => {
+            defmt::error!(
+                "[{}] Not starting guest: {}",
+                spec.name,
+                defmt::Display2Format(&err)
+            );
+            return None;
+        }
+    };
+    if let Some(code) = &code {
+        info!(
+            "[{}] Inflated component into the heap ({} bytes)",
+            spec.name,
+            code.len()
+        );
+    }
+    let bytes = code.as_deref().unwrap_or(component);
+    let component = match unsafe { Component::deserialize_raw(engine, NonNull::from(bytes)) } {
+        Ok(component) => component,
+        Err(err) => {
+            defmt::error!(
+                "[{}] Not starting guest: {}",
+                spec.name,
+                defmt::Display2Format(&err)
+            );
+            return None;
+        }
+    };
+    report_heap(spec.name, "after deserialize");
 
-    let component = unsafe { Component::deserialize(&engine, guest_bytes) }.unwrap();
+    // Checked before running, a guest without an entry point is never going to run
+    match find_entry_point_in(&component, spec.export) {
+        Ok(entry) => Some(LoadedGuest {
+            component,
+            entry,
+            _code: code,
+        }),
+        Err(err) => {
+            defmt::error!(
+                "[{}] Not starting guest: {}",
+                spec.name,
+                defmt::Display2Format(&err)
+            );
+            None
+        }
+    }
+}
 
-    info!("Instantiating...");
-    let app = Guest::instantiate(&mut store, &component, &linker).unwrap();
+// Owns one guest for the life of the program. Guests share the executor and
+// only give it up at fuel yields and in `delay-ms`, so they are scheduled cooperatively
+#[embassy_executor::task(pool_size = MAX_GUESTS)]
+async fn guest_task(
+    spec: &'static GuestSpec,
+    control: &'static GuestControl,
+    engine: &'static Engine,
+    linker: &'static Linker<HostState>,
+    mut host_state: HostState,
+) {
+    loop {
+        // The component points into its flash slot, so it is dropped before the slot can change
+        if let Some(guest) = load_guest(spec, engine) {
+            host_state = supervise_guest(
+                spec,
+                control,
+                &guest.component,
+                &guest.entry,
+                engine,
+                linker,
+                host_state,
+            )
+            .await;
+        }
 
-    info!("Starting guest...");
-    app.my_temp_sensor_sensor_app()
-        .call_run(&mut store)
-        .unwrap();
+        // However the guest ended, it stays down until an upload replaces it
+        control.stop.wait().await;
+        info!("[{}] Guest stopped for upload", spec.name);
+        control.stopped.signal(());
+        control.reload.wait().await;
+    }
+}
+
+// Runs the guest under its restart policy until it finishes, is given up on
+// or is stopped for an upload
+async fn supervise_guest(
+    spec: &GuestSpec,
+    control: &GuestControl,
+    component: &Component,
+    entry: &ComponentExportIndex,
+    engine: &Engine,
+    linker: &Linker<HostState>,
+    mut host_state: HostState,
+) -> HostState {
+    let name = spec.name;
+    let mut restarts = RestartTracker::new(GUEST_RESTART_POLICY);
+    loop {
+        // A fresh store per run, so an interrupted instance is never reused
+        let mut store = Store::new(engine, host_state);
+        store.limiter(|state| state);
+        spec.budget.apply(&mut store).unwrap();
+
+        let done = match run_guest(spec, control, &mut store, component, entry, linker).await {
+            Outcome::Finished(()) => {
+                info!("[{}] Guest finished.", name);
+                true
+            }
+            Outcome::Killed { slices } => {
+                if !control.stop.signaled() {
+                    defmt::error!("[{}] Guest killed after {} slices", name, slices);
+                }
+                true
+            }
+            Outcome::Restart { slices } => {
+                defmt::warn!(
+                    "[{}] Guest made no host calls for {} slices ({} total)",
+                    name,
+                    GUEST_MAX_QUIET_SLICES,
+                    slices
+                );
+                false
+            }
+            Outcome::Trapped(trap) => {
+                let report = TrapReport::new(&trap);
+                defmt::error!("[{}] Guest trapped: {}", name, report.reason.as_str());
+                for (i, frame) in report.frames.iter().enumerate() {
+                    defmt::error!("  {}: {}", i, frame.as_str());
+                }
+                defmt::error!("[{}] Last {} guest log records:", name, LOG_RETENTION);
+                store.data_mut().logging_ctx.dump_recent(&mut DefmtSink);
+                false
+            }
+        };
+
+        host_state = store.into_data();
+        host_state.reset();
+        if done {
+            return host_state;
+        }
+
+        match restarts.on_failure() {
+            RestartDecision::Restart { delay_ms } => {
+                defmt::warn!(
+                    "[{}] Restarting guest in {} ms (restart {})",
+                    name,
+                    delay_ms,
+                    restarts.restarts()
+                );
+                if let Either::Second(()) =
+                    select(Timer::after_millis(delay_ms), control.stop.wait()).await
+                {
+                    // Raised again for `guest_task`, which acknowledges the stop
+                    control.stop.signal(());
+                    return host_state;
+                }
+            }
+            RestartDecision::GiveUp => {
+                defmt::error!(
+                    "[{}] Giving up on guest after {} restarts",
+                    name,
+                    restarts.restarts()
+                );
+                return host_state;
+            }
+        }
+    }
+}
+
+// Instantiation failures (e.g. a trap in a start function) are reported like runtime traps
+async fn run_guest(
+    spec: &GuestSpec,
+    control: &GuestControl,
+    store: &mut Store<HostState>,
+    component: &Component,
+    entry: &ComponentExportIndex,
+    linker: &Linker<HostState>,
+) -> Outcome<()> {
+    let calls = HostCalls::install(store);
+    let mut watchdog = Watchdog::new(&calls, GUEST_MAX_QUIET_SLICES, Verdict::Restart);
+    info!("[{}] Instantiating...", spec.name);
+    let run = match linker.instantiate_async(&mut *store, component).await {
+        Ok(instance) => instance.get_typed_func::<(), ()>(&mut *store, entry),
+        Err(err) => return Outcome::Trapped(err),
+    };
+    // A `run` with the wrong signature is a broken guest, so it gets the same restart handling
+    let run = match run {
+        Ok(run) => run,
+        Err(err) => return Outcome::Trapped(err),
+    };
+    report_heap(spec.name, "after instantiate");
+
+    info!("[{}] Starting guest...", spec.name);
+    let slices = Cell::new(0);
+    let policy = |n| {
+        slices.set(n);
+        if n % HEAP_REPORT_SLICES == 0 {
+            report_heap(spec.name, "while running");
+        }
+        watchdog.on_exhausted(n)
+    };
+    let call = async {
+        run.call_async(&mut *store, ()).await?;
+        run.post_return_async(&mut *store).await
+    };
+    // Raced rather than checked in `policy`, which a guest waiting in
+    // `delay-ms` does not reach, so a stop never waits for the delay to end
+    match select(supervise(call, &calls, policy), control.stop.wait()).await {
+        Either::First(outcome) => outcome,
+        Either::Second(()) => {
+            // Raised again for `guest_task`, which acknowledges the stop
+            control.stop.signal(());
+            Outcome::Killed {
+                slices: slices.get(),
+            }
+        }
+    }
+}
+
+// Applies one upload request, taking the guests running from the slot down
+// for the length of the transfer. An abandoned transfer leaves them down
+// until the next upload starts
+async fn handle_upload(
+    request: Request<'_>,
+    receiver: &mut Receiver,
+    flash: &mut GuestFlash,
+    stopped: &mut Option<usize>,
+    guests: &[GuestSpec],
+    engine: &Engine,
+) -> Response {
+    if let Request::Begin { slot, .. } = request
+        && *stopped != Some(slot as usize)
+    {
+        if let Some(previous) = stopped.take() {
+            reload_slot(guests, previous);
+        }
+        for (spec, control) in guests.iter().zip(&CONTROL) {
+            if spec.slot == slot as usize {
+                info!("[{}] Stopping guest for upload", spec.name);
+                control.stop.signal(());
+                control.stopped.wait().await;
+            }
+        }
+        *stopped = Some(slot as usize);
+    }
+
+    let response = receiver.handle(request, flash, |image| verify_upload(engine, image));
+    match response {
+        Response::Committed { version } => info!("Upload committed, version {}", version),
+        Response::Error(error) => defmt::warn!("Upload failed: {}", defmt::Display2Format(&error)),
+        Response::Ack => {}
+    }
+
+    if receiver.active_slot().is_none()
+        && let Some(slot) = stopped.take()
+    {
+        reload_slot(guests, slot);
+    }
+    response
+}
+
+fn reload_slot(guests: &[GuestSpec], slot: usize) {
+    for (spec, control) in guests.iter().zip(&CONTROL) {
+        if spec.slot == slot {
+            control.reload.signal(());
+        }
+    }
+}
+
+// The signature keeps out images from anyone without the key, the fingerprint
+// those built with other engine settings and Wasmtime itself those built by
+// another version of it. Every guest in a bundle has to load, whether or not
+// it is running now
+fn verify_upload(engine: &Engine, image: &[u8]) -> bool {
+    let checked = verify_image(image, &signing_key())
+        .and_then(|(header, payload)| header.check_engine(FINGERPRINT).map(|()| payload));
+    let payload = match checked {
+        Ok(payload) => payload,
+        Err(err) => {
+            defmt::warn!("Uploaded image rejected: {}", defmt::Display2Format(&err));
+            return false;
+        }
+    };
+    if !is_bundle(payload) {
+        return verify_component(engine, payload, RUN_INTERFACE);
+    }
+    match Bundle::parse(payload) {
+        Ok(bundle) => bundle.entries().all(|entry| {
+            if let Some(interface) = unlinked_interface(&entry) {
+                defmt::warn!(
+                    "Uploaded bundle rejected: {} imports {} which the host does not provide",
+                    entry.name,
+                    interface
+                );
+                return false;
+            }
+            verify_component(engine, entry.component, entry.export)
+        }),
+        Err(err) => {
+            defmt::warn!("Uploaded bundle rejected: {}", defmt::Display2Format(&err));
+            false
+        }
+    }
+}
+
+// A compressed component is inflated to check it, then freed again
+fn verify_component(engine: &Engine, component: &[u8], export: &str) -> bool {
+    let code = match inflate(component) {
+        Ok(code) => code,
+        Err(err) => {
+            defmt::warn!("Uploaded image rejected: {}", defmt::Display2Format(&err));
+            return false;
+        }
+    };
+    let bytes = code.as_deref().unwrap_or(component);
+    let found = unsafe { Component::deserialize_raw(engine, NonNull::from(bytes)) }
+        .and_then(|component| Ok(find_entry_point_in(&component, export)?));
+    if let Err(err) = &found {
+        defmt::warn!("Uploaded image rejected: {}", defmt::Display2Format(err));
+    }
+    found.is_ok()
+}
+
+// Receives images from `compiler upload`, one request frame at a time, each answered before the next
+#[embassy_executor::task]
+async fn upload_task(
+    mut uart: BufferedUart,
+    mut flash: GuestFlash,
+    engine: &'static Engine,
+    guests: &'static [GuestSpec],
+) {
+    let mut decoder = FrameDecoder::new();
+    let mut receiver = Receiver::new(SLOT_COUNT, SLOT_SIZE as u32);
+    // Slot whose guests were taken down for the transfer in progress
+    let mut stopped = None;
+    let mut buf = [0u8; 64];
+    loop {
+        let n = match uart.read(&mut buf).await {
+            Ok(n) => n,
+            Err(err) => {
+                defmt::warn!("Upload link error: {}", err);
+                continue;
+            }
+        };
+        for &byte in &buf[..n] {
+            let Some(frame) = decoder.push(byte) else {
+                continue;
+            };
+            let response = match frame {
+                Ok(frame) => match Request::parse(&frame) {
+                    Ok(request) => {
+                        handle_upload(
+                            request,
+                            &mut receiver,
+                            &mut flash,
+                            &mut stopped,
+                            guests,
+                            engine,
+                        )
+                        .await
+                    }
+                    Err(error) => Response::Error(error),
+                },
+                Err(_) => Response::Error(UploadError::BadFrame),
+            };
+            if let Err(err) = uart.write_all(&response.to_frame().encode()).await {
+                defmt::warn!("Upload link error: {}", err);
+            }
+        }
+    }
 }
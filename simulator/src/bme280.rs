@@ -0,0 +1,393 @@
+//! A Bosch BME280 on 4-wire SPI, as the temperature-sensor guest drives it.
+//!
+//! The first byte of a transaction is a register address with the read/write
+//! bit as its MSB. A read then clocks out that register and the ones after it;
+//! a write takes a data byte, then any number of further address/data pairs.
+//! In SPI mode the MSB is not part of the address, so registers are at
+//! `0x80..=0xFF` either way.
+//!
+//! Measurements complete instantly: forced mode measures once on the write to
+//! ctrl_meas and goes back to sleep, normal mode measures at the start of every
+//! transaction. A measurement with its oversampling set to skipped leaves the
+//! reset value (0x80000, or 0x8000 for humidity) in the data registers, and
+//! ctrl_hum only takes effect with the next write to ctrl_meas.
+//!
+//! Flagged as protocol errors:
+//! - a write to a register that is read-only or reserved;
+//! - a write whose address byte is not followed by data.
+
+use std::sync::{Arc, Mutex};
+
+use spi::{BusError, Operation, SpiDevice};
+
+use crate::config::{Calibration, RawSpec, SensorSpec};
+use crate::devices::protocol_error;
+
+pub const CHIP_ID: u8 = 0x60;
+
+const CALIB_00: u8 = 0x88;
+const CALIB_26: u8 = 0xE1;
+const ID: u8 = 0xD0;
+const RESET: u8 = 0xE0;
+const CTRL_HUM: u8 = 0xF2;
+const STATUS: u8 = 0xF3;
+const CTRL_MEAS: u8 = 0xF4;
+const CONFIG: u8 = 0xF5;
+const PRESS_MSB: u8 = 0xF7;
+const TEMP_MSB: u8 = 0xFA;
+const HUM_MSB: u8 = 0xFD;
+
+// Written to RESET, anything else is ignored
+const SOFT_RESET: u8 = 0xB6;
+// What a skipped measurement reads as
+const SKIPPED_20: u32 = 0x80000;
+const SKIPPED_16: u16 = 0x8000;
+
+/// The ADC outputs a measurement puts in the data registers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Raw {
+    pub temperature: u32,
+    pub pressure: u32,
+    pub humidity: u16,
+}
+
+/// Readings as a spec or event gives them: degrees C, %RH and hPa, or raw.
+pub struct Setting<'a> {
+    pub temperature: Option<f64>,
+    pub humidity: Option<f64>,
+    pub pressure: Option<f64>,
+    pub raw: &'a RawSpec,
+}
+
+impl Raw {
+    /// `previous` with what `setting` changes, turning degrees C, %RH and hPa
+    /// into the outputs `calibration` compensates to them. Without a
+    /// previous reading, what the setting leaves out is 20 C, 50 %RH and
+    /// 1013.25 hPa.
+    pub fn update(
+        previous: Option<Raw>,
+        setting: &Setting,
+        calibration: &Calibration,
+    ) -> anyhow::Result<Self> {
+        let raw = setting.raw;
+        let temperature = match (raw.temperature, setting.temperature, previous) {
+            (Some(_), Some(_), _) => anyhow::bail!("both temperature and raw.temperature"),
+            (Some(adc), None, _) if adc >= 1 << 20 => {
+                anyhow::bail!("raw.temperature does not fit in 20 bits")
+            }
+            (Some(adc), None, _) => adc,
+            (None, None, Some(previous)) => previous.temperature,
+            (None, celsius, _) => invert(20, celsius.unwrap_or(20.0), |adc| {
+                compensate_temperature(adc, calibration).1
+            }),
+        };
+        let t_fine = compensate_temperature(temperature, calibration).0;
+        let pressure = match (raw.pressure, setting.pressure, previous) {
+            (Some(_), Some(_), _) => anyhow::bail!("both pressure and raw.pressure"),
+            (Some(adc), None, _) if adc >= 1 << 20 => {
+                anyhow::bail!("raw.pressure does not fit in 20 bits")
+            }
+            (Some(adc), None, _) => adc,
+            (None, None, Some(previous)) => previous.pressure,
+            (None, hpa, _) => invert(20, hpa.unwrap_or(1013.25) * 100.0, |adc| {
+                compensate_pressure(adc, t_fine, calibration)
+            }),
+        };
+        let humidity = match (raw.humidity, setting.humidity, previous) {
+            (Some(_), Some(_), _) => anyhow::bail!("both humidity and raw.humidity"),
+            (Some(adc), None, _) => adc,
+            (None, None, Some(previous)) => previous.humidity,
+            (None, rh, _) => invert(16, rh.unwrap_or(50.0), |adc| {
+                compensate_humidity(adc as u16, t_fine, calibration)
+            }) as u16,
+        };
+        Ok(Self {
+            temperature,
+            pressure,
+            humidity,
+        })
+    }
+}
+
+/// What a sensor's ADCs read, shared with the run's event schedule.
+#[derive(Clone)]
+pub struct Readings {
+    raw: Arc<Mutex<Raw>>,
+    calibration: Arc<Calibration>,
+}
+
+impl Readings {
+    pub fn raw(&self) -> Raw {
+        *self.raw.lock().unwrap()
+    }
+
+    /// Takes effect with the next measurement; the data registers keep the last one.
+    pub fn set(&self, raw: Raw) {
+        *self.raw.lock().unwrap() = raw;
+    }
+
+    pub fn calibration(&self) -> &Calibration {
+        &self.calibration
+    }
+}
+
+pub struct Bme280 {
+    name: String,
+    // 0x80..=0xFF
+    registers: [u8; 0x80],
+    readings: Readings,
+    // ctrl_hum as of the last write to ctrl_meas
+    osrs_h: u8,
+}
+
+impl Bme280 {
+    pub fn new(name: &str, spec: &SensorSpec) -> anyhow::Result<Self> {
+        let raw = Raw::update(None, &spec.setting(), &spec.calibration)
+            .map_err(|err| err.context(name.to_string()))?;
+        let mut sensor = Self {
+            name: name.to_string(),
+            registers: [0; 0x80],
+            readings: Readings {
+                raw: Arc::new(Mutex::new(raw)),
+                calibration: Arc::new(spec.calibration.clone()),
+            },
+            osrs_h: 0,
+        };
+        sensor.load_nvm(&spec.calibration);
+        sensor.reset();
+        Ok(sensor)
+    }
+
+    pub fn readings(&self) -> Readings {
+        self.readings.clone()
+    }
+
+    fn register(&mut self, address: u8) -> &mut u8 {
+        &mut self.registers[usize::from(address & 0x7F)]
+    }
+
+    // The calibration and chip ID, which a reset leaves alone
+    fn load_nvm(&mut self, calibration: &Calibration) {
+        let c = calibration;
+        let mut low = Vec::with_capacity(26);
+        low.extend(c.dig_t1.to_le_bytes());
+        for word in [c.dig_t2, c.dig_t3] {
+            low.extend(word.to_le_bytes());
+        }
+        low.extend(c.dig_p1.to_le_bytes());
+        for word in [
+            c.dig_p2, c.dig_p3, c.dig_p4, c.dig_p5, c.dig_p6, c.dig_p7, c.dig_p8, c.dig_p9,
+        ] {
+            low.extend(word.to_le_bytes());
+        }
+        // 0xA0 is reserved
+        low.extend([0, c.dig_h1]);
+        // H4 and H5 are 12 bits each, sharing 0xE5
+        let [h2_lsb, h2_msb] = c.dig_h2.to_le_bytes();
+        let high = [
+            h2_lsb,
+            h2_msb,
+            c.dig_h3,
+            (c.dig_h4 >> 4) as u8,
+            (c.dig_h4 & 0x0F) as u8 | ((c.dig_h5 & 0x0F) << 4) as u8,
+            (c.dig_h5 >> 4) as u8,
+            c.dig_h6 as u8,
+        ];
+        for (offset, byte) in low.into_iter().enumerate() {
+            *self.register(CALIB_00 + offset as u8) = byte;
+        }
+        for (offset, byte) in high.into_iter().enumerate() {
+            *self.register(CALIB_26 + offset as u8) = byte;
+        }
+        *self.register(ID) = CHIP_ID;
+    }
+
+    // Power-on state: sleeping, every measurement skipped
+    fn reset(&mut self) {
+        for address in [CTRL_HUM, STATUS, CTRL_MEAS, CONFIG] {
+            *self.register(address) = 0;
+        }
+        self.osrs_h = 0;
+        self.store(SKIPPED_20, SKIPPED_20, SKIPPED_16);
+    }
+
+    fn store(&mut self, pressure: u32, temperature: u32, humidity: u16) {
+        for (address, adc) in [(PRESS_MSB, pressure), (TEMP_MSB, temperature)] {
+            *self.register(address) = (adc >> 12) as u8;
+            *self.register(address + 1) = (adc >> 4) as u8;
+            *self.register(address + 2) = ((adc & 0x0F) << 4) as u8;
+        }
+        let [msb, lsb] = humidity.to_be_bytes();
+        *self.register(HUM_MSB) = msb;
+        *self.register(HUM_MSB + 1) = lsb;
+    }
+
+    fn measure(&mut self) {
+        let ctrl_meas = *self.register(CTRL_MEAS);
+        let osrs_t = ctrl_meas >> 5;
+        let osrs_p = ctrl_meas >> 2 & 0x07;
+        let raw = self.readings.raw();
+        self.store(
+            if osrs_p == 0 {
+                SKIPPED_20
+            } else {
+                raw.pressure
+            },
+            if osrs_t == 0 {
+                SKIPPED_20
+            } else {
+                raw.temperature
+            },
+            if self.osrs_h == 0 {
+                SKIPPED_16
+            } else {
+                raw.humidity
+            },
+        );
+    }
+
+    fn write(&mut self, address: u8, value: u8) {
+        match address | 0x80 {
+            RESET => {
+                if value == SOFT_RESET {
+                    self.reset();
+                }
+            }
+            CTRL_HUM => *self.register(CTRL_HUM) = value & 0x07,
+            CONFIG => *self.register(CONFIG) = value,
+            CTRL_MEAS => {
+                self.osrs_h = *self.register(CTRL_HUM);
+                *self.register(CTRL_MEAS) = value;
+                // Forced mode, either encoding: measure once and fall back asleep
+                if matches!(value & 0x03, 0b01 | 0b10) {
+                    self.measure();
+                    *self.register(CTRL_MEAS) = value & !0x03;
+                }
+            }
+            register => protocol_error(
+                &self.name,
+                &format!("write to read-only or reserved register {register:#04x}"),
+            ),
+        }
+    }
+}
+
+// Where a transaction is between bytes
+enum Phase {
+    Address,
+    Read(u8),
+    Write(u8),
+}
+
+impl SpiDevice for Bme280 {
+    fn transaction(&mut self, operations: &mut [Operation<'_>]) -> Result<(), BusError> {
+        if *self.register(CTRL_MEAS) & 0x03 == 0x03 {
+            self.measure();
+        }
+        let mut phase = Phase::Address;
+        // One byte in on MOSI, one out on MISO
+        let mut clock = |mosi: u8| match phase {
+            Phase::Address => {
+                phase = if mosi & 0x80 != 0 {
+                    Phase::Read(mosi)
+                } else {
+                    Phase::Write(mosi)
+                };
+                0
+            }
+            Phase::Read(address) => {
+                phase = Phase::Read(address.wrapping_add(1) | 0x80);
+                *self.register(address)
+            }
+            Phase::Write(address) => {
+                self.write(address, mosi);
+                phase = Phase::Address;
+                0
+            }
+        };
+        for op in operations.iter_mut() {
+            match op {
+                Operation::Read(buf) => buf.iter_mut().for_each(|byte| *byte = clock(0)),
+                Operation::Write(data) => data.iter().for_each(|&byte| {
+                    clock(byte);
+                }),
+                Operation::Transfer(read, write) => {
+                    for (miso, &mosi) in read.iter_mut().zip(write.iter()) {
+                        *miso = clock(mosi);
+                    }
+                }
+                Operation::DelayNs(_) => {}
+            }
+        }
+        if let Phase::Write(address) = phase {
+            protocol_error(
+                &self.name,
+                &format!("write to {:#04x} without a data byte", address | 0x80),
+            );
+        }
+        Ok(())
+    }
+}
+
+// The datasheet's floating point compensation, as the guest does it.
+// Temperature in degrees C, with the t_fine the other two depend on.
+fn compensate_temperature(adc: u32, c: &Calibration) -> (f64, f64) {
+    let adc = f64::from(adc);
+    let t1 = f64::from(c.dig_t1);
+    let var1 = (adc / 16384.0 - t1 / 1024.0) * f64::from(c.dig_t2);
+    let var2 = (adc / 131072.0 - t1 / 8192.0).powi(2) * f64::from(c.dig_t3);
+    let t_fine = var1 + var2;
+    (t_fine, t_fine / 5120.0)
+}
+
+// In Pa
+fn compensate_pressure(adc: u32, t_fine: f64, c: &Calibration) -> f64 {
+    let mut var1 = t_fine / 2.0 - 64000.0;
+    let mut var2 = var1 * var1 * f64::from(c.dig_p6) / 32768.0;
+    var2 += var1 * f64::from(c.dig_p5) * 2.0;
+    var2 = var2 / 4.0 + f64::from(c.dig_p4) * 65536.0;
+    var1 = (f64::from(c.dig_p3) * var1 * var1 / 524288.0 + f64::from(c.dig_p2) * var1) / 524288.0;
+    var1 = (1.0 + var1 / 32768.0) * f64::from(c.dig_p1);
+    if var1 == 0.0 {
+        return 0.0;
+    }
+    let mut p = 1048576.0 - f64::from(adc);
+    p = (p - var2 / 4096.0) * 6250.0 / var1;
+    let var1 = f64::from(c.dig_p9) * p * p / 2147483648.0;
+    let var2 = p * f64::from(c.dig_p8) / 32768.0;
+    p + (var1 + var2 + f64::from(c.dig_p7)) / 16.0
+}
+
+// In %RH
+fn compensate_humidity(adc: u16, t_fine: f64, c: &Calibration) -> f64 {
+    let var = t_fine - 76800.0;
+    let var = (f64::from(adc) - (f64::from(c.dig_h4) * 64.0 + f64::from(c.dig_h5) / 16384.0 * var))
+        * (f64::from(c.dig_h2) / 65536.0
+            * (1.0
+                + f64::from(c.dig_h6) / 67108864.0
+                    * var
+                    * (1.0 + f64::from(c.dig_h3) / 67108864.0 * var)));
+    let var = var * (1.0 - f64::from(c.dig_h1) * var / 524288.0);
+    var.clamp(0.0, 100.0)
+}
+
+// The `bits` wide ADC output that `compensate` maps closest to `target`, by
+// bisection; each compensation is monotonic over the ADC's range
+fn invert(bits: u32, target: f64, compensate: impl Fn(u32) -> f64) -> u32 {
+    let (mut low, mut high) = (0, (1 << bits) - 1);
+    let rising = compensate(high) > compensate(low);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if (compensate(mid) < target) == rising {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    // `low` is the first output past the target, the one before may be closer
+    if low > 0 && (compensate(low - 1) - target).abs() <= (compensate(low) - target).abs() {
+        low - 1
+    } else {
+        low
+    }
+}
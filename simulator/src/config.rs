@@ -0,0 +1,297 @@
+//! The TOML file the simulator reads: the guests to run, as in a bundle
+//! manifest, and the simulated devices standing in for the board's.
+//!
+//! ```toml
+//! [[guest]]
+//! name = "temperature-sensor"
+//! component = "../target/wasm32-unknown-unknown/release/temperature_sensor.wasm"
+//! spi = ["sensor"]
+//! # Optional: export = "my:app/run", memory = 131072, fuel = 100000, gpio = [...]
+//!
+//! [[spi]]
+//! name = "sensor"
+//! model = "bme280"
+//! temperature = 21.5   # degrees C, default 20
+//! humidity = 40.0      # %RH, default 50
+//! pressure = 1013.25   # hPa, default 1013.25
+//! # Or what the ADCs read instead: raw = { temperature = 519888, humidity = 27000 }
+//! # Optional, default the datasheet's example part: [spi.calibration] dig_t1 = 27504, ...
+//!
+//! [[spi]]
+//! name = "echo"
+//! model = "loopback"
+//!
+//! [[spi]]
+//! name = "display"
+//! model = "ssd1306"
+//! dc = "DC"          # data/command pin, must be listed below
+//! res = "RES"        # optional reset pin
+//! frames = "frames"  # optional: a file per frame, see `format`
+//! format = "pbm"     # or "png"
+//! ascii = true       # print each frame
+//!
+//! [[pin]]
+//! label = "VDDC"
+//! level = "high"   # at start, default low
+//! safe = "high"    # driven back to when the guest is reset
+//!
+//! [[event]]
+//! at = 60000       # milliseconds since the start
+//! pin = "RES"      # drive a pin, whoever it is granted to
+//! level = "low"
+//!
+//! [[event]]
+//! at = 300000
+//! sensor = "sensor"        # a bme280: what it reads from the next measurement on,
+//! temperature = 30.0       # as at its [[spi]] entry; what is left out keeps its raw value
+//! ```
+//!
+//! Paths are relative to the file. Each device and pin goes to one guest at most.
+//! Events at the same time happen in the order listed, and before any guest
+//! that wakes then runs; with `--virtual-time` a run is the same every time.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::bme280::Setting;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SimConfig {
+    #[serde(rename = "guest")]
+    pub guests: Vec<GuestSpec>,
+    #[serde(rename = "spi", default)]
+    pub spi: Vec<SpiSpec>,
+    #[serde(rename = "pin", default)]
+    pub pins: Vec<PinSpec>,
+    #[serde(rename = "event", default)]
+    pub events: Vec<EventSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuestSpec {
+    pub name: String,
+    // A component or core module (binary or text), or a precompiled pulley64 one
+    pub component: PathBuf,
+    #[serde(default = "default_export")]
+    pub export: String,
+    // Bytes per linear memory, capped by the host's own limit
+    pub memory: Option<usize>,
+    // Fuel per slice between yields to the host
+    pub fuel: Option<u64>,
+    #[serde(default)]
+    pub gpio: Vec<String>,
+    #[serde(default)]
+    pub spi: Vec<String>,
+}
+
+fn default_export() -> String {
+    supervisor::RUN_INTERFACE.to_string()
+}
+
+/// A simulated SPI device, by the name guests are given it under.
+#[derive(Deserialize)]
+#[serde(tag = "model", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum SpiSpec {
+    // MISO wired to MOSI: a transfer reads back what it wrote, a read zeros
+    Loopback { name: String },
+    // The PmodOLED's 128x32 panel, see `crate::ssd1306`
+    Ssd1306(DisplaySpec),
+    // The temperature sensor's part, see `crate::bme280`
+    Bme280(SensorSpec),
+}
+
+impl SpiSpec {
+    pub fn name(&self) -> &str {
+        match self {
+            SpiSpec::Loopback { name } => name,
+            SpiSpec::Ssd1306(display) => &display.name,
+            SpiSpec::Bme280(sensor) => &sensor.name,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DisplaySpec {
+    pub name: String,
+    pub dc: String,
+    pub res: Option<String>,
+    // Directory the frames are written to, one file each
+    pub frames: Option<PathBuf>,
+    #[serde(default)]
+    pub format: FrameFormat,
+    #[serde(default)]
+    pub ascii: bool,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameFormat {
+    // Plain (P1) PBM, which diffs as text
+    #[default]
+    Pbm,
+    Png,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SensorSpec {
+    pub name: String,
+    // Degrees C, %RH and hPa, turned into the ADC outputs that compensate to them
+    pub temperature: Option<f64>,
+    pub humidity: Option<f64>,
+    pub pressure: Option<f64>,
+    #[serde(default)]
+    pub raw: RawSpec,
+    #[serde(default)]
+    pub calibration: Calibration,
+}
+
+impl SensorSpec {
+    pub fn setting(&self) -> Setting<'_> {
+        Setting {
+            temperature: self.temperature,
+            humidity: self.humidity,
+            pressure: self.pressure,
+            raw: &self.raw,
+        }
+    }
+}
+
+/// ADC outputs, as the data registers hold them: 20 bits for temperature and
+/// pressure, 16 for humidity.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RawSpec {
+    pub temperature: Option<u32>,
+    pub humidity: Option<u16>,
+    pub pressure: Option<u32>,
+}
+
+/// The trimming parameters in a BME280's NVM, named as in the datasheet.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Calibration {
+    pub dig_t1: u16,
+    pub dig_t2: i16,
+    pub dig_t3: i16,
+    pub dig_p1: u16,
+    pub dig_p2: i16,
+    pub dig_p3: i16,
+    pub dig_p4: i16,
+    pub dig_p5: i16,
+    pub dig_p6: i16,
+    pub dig_p7: i16,
+    pub dig_p8: i16,
+    pub dig_p9: i16,
+    pub dig_h1: u8,
+    pub dig_h2: i16,
+    pub dig_h3: u8,
+    pub dig_h4: i16,
+    pub dig_h5: i16,
+    pub dig_h6: i8,
+}
+
+// The datasheet's worked example for temperature and pressure, and a typical part's humidity
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            dig_t1: 27504,
+            dig_t2: 26435,
+            dig_t3: -1000,
+            dig_p1: 36477,
+            dig_p2: -10685,
+            dig_p3: 3024,
+            dig_p4: 2855,
+            dig_p5: 140,
+            dig_p6: -7,
+            dig_p7: 15500,
+            dig_p8: -14600,
+            dig_p9: 6000,
+            dig_h1: 75,
+            dig_h2: 362,
+            dig_h3: 0,
+            dig_h4: 313,
+            dig_h5: 50,
+            dig_h6: 30,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PinSpec {
+    pub label: String,
+    #[serde(default)]
+    pub level: PinLevel,
+    pub safe: Option<PinLevel>,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PinLevel {
+    #[default]
+    Low,
+    High,
+}
+
+impl From<PinLevel> for gpio::Level {
+    fn from(level: PinLevel) -> Self {
+        match level {
+            PinLevel::Low => gpio::Level::Low,
+            PinLevel::High => gpio::Level::High,
+        }
+    }
+}
+
+/// Something that happens to the simulated devices at a set time: either a
+/// pin driven to `level`, or a `sensor` given new readings.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventSpec {
+    pub at: u64,
+    pub pin: Option<String>,
+    pub level: Option<PinLevel>,
+    pub sensor: Option<String>,
+    pub temperature: Option<f64>,
+    pub humidity: Option<f64>,
+    pub pressure: Option<f64>,
+    #[serde(default)]
+    pub raw: RawSpec,
+}
+
+impl EventSpec {
+    pub fn setting(&self) -> Setting<'_> {
+        Setting {
+            temperature: self.temperature,
+            humidity: self.humidity,
+            pressure: self.pressure,
+            raw: &self.raw,
+        }
+    }
+}
+
+pub fn load(path: &Path) -> anyhow::Result<SimConfig> {
+    let text = fs::read_to_string(path).with_context(|| path.display().to_string())?;
+    let mut config: SimConfig =
+        toml::from_str(&text).with_context(|| path.display().to_string())?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for guest in &mut config.guests {
+        guest.component = dir.join(&guest.component);
+    }
+    for spi in &mut config.spi {
+        if let SpiSpec::Ssd1306(DisplaySpec {
+            frames: Some(frames),
+            ..
+        }) = spi
+        {
+            *frames = dir.join(&*frames);
+        }
+    }
+    Ok(config)
+}
@@ -0,0 +1,350 @@
+//! Simulated stand-ins for the board's devices, behind the same `spi` and
+//! `gpio` traits the firmware implements for its hardware.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::poll_fn;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+
+use delay::VirtualClock;
+use futures::task::AtomicWaker;
+use gpio::{Level, OutputPin};
+use spi::{BusError, Operation, SpiDevice};
+
+use crate::bme280::{Bme280, Readings};
+use crate::config::{PinSpec, SpiSpec};
+use crate::ssd1306::Ssd1306;
+
+/// Protocol errors flagged by any device model, i.e. things the real part
+/// would not have done what the guest meant for.
+static PROTOCOL_ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn protocol_errors() -> usize {
+    PROTOCOL_ERRORS.load(Ordering::Relaxed)
+}
+
+/// Prints a device model's protocol error; `device` is the name guests open it under.
+pub fn protocol_error(device: &str, msg: &str) {
+    PROTOCOL_ERRORS.fetch_add(1, Ordering::Relaxed);
+    println!("spi {device}: protocol error: {msg}");
+}
+
+/// Prints `--trace` lines, stamped with the time in virtual time so they line
+/// up with the event schedule's.
+#[derive(Clone)]
+pub struct Trace {
+    clock: Option<VirtualClock>,
+}
+
+impl Trace {
+    pub fn new(clock: Option<&VirtualClock>) -> Self {
+        Self {
+            clock: clock.cloned(),
+        }
+    }
+
+    fn line(&self, line: fmt::Arguments<'_>) {
+        match &self.clock {
+            Some(clock) => println!("At {} ms: {line}", clock.now_ms()),
+            None => println!("{line}"),
+        }
+    }
+}
+
+/// How many frames each display renders before the run stops, for `--frames`.
+#[derive(Clone)]
+pub struct FrameQuota(Arc<QuotaState>);
+
+struct QuotaState {
+    frames: u32,
+    // Displays that have not rendered all their frames yet
+    short: AtomicUsize,
+    waker: AtomicWaker,
+}
+
+impl FrameQuota {
+    pub fn new(frames: u32) -> Self {
+        Self(Arc::new(QuotaState {
+            frames,
+            short: AtomicUsize::new(0),
+            waker: AtomicWaker::new(),
+        }))
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.0.frames
+    }
+
+    // Counts a display in, before it renders anything
+    pub fn join(&self) {
+        self.0.short.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // A display has rendered its last frame
+    pub fn reached(&self) {
+        if self.0.short.fetch_sub(1, Ordering::Relaxed) == 1 {
+            self.0.waker.wake();
+        }
+    }
+
+    /// Resolves once every display has rendered its frames.
+    pub async fn met(&self) {
+        poll_fn(|cx| {
+            self.0.waker.register(cx.waker());
+            if self.0.short.load(Ordering::Relaxed) == 0 {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+/// Level of every simulated pin, by label. Device models read the pins wired
+/// to them, e.g. a display's data/command line.
+#[derive(Clone, Default)]
+pub struct Pins(Arc<Mutex<BTreeMap<String, PinState>>>);
+
+#[derive(Clone, Copy)]
+struct PinState {
+    level: Level,
+    // Times driven low, so a model can tell it was reset between two transactions
+    falls: u32,
+}
+
+impl Pins {
+    pub fn level(&self, label: &str) -> Option<Level> {
+        self.0.lock().unwrap().get(label).map(|pin| pin.level)
+    }
+
+    pub fn falls(&self, label: &str) -> u32 {
+        self.0.lock().unwrap().get(label).map_or(0, |pin| pin.falls)
+    }
+
+    // The level it had before, None if the pin is not listed
+    pub fn set(&self, label: &str, level: Level) -> Option<Level> {
+        let mut pins = self.0.lock().unwrap();
+        let previous = pins.get(label).copied();
+        let falls = previous.map_or(0, |pin| pin.falls);
+        let fell = previous.is_some_and(|pin| pin.level == Level::High) && level == Level::Low;
+        let state = PinState {
+            level,
+            falls: falls + u32::from(fell),
+        };
+        pins.insert(label.to_string(), state);
+        previous.map(|pin| pin.level)
+    }
+}
+
+pub struct SimPin {
+    label: String,
+    pins: Pins,
+    trace: Option<Trace>,
+}
+
+impl OutputPin for SimPin {
+    fn set_level(&mut self, level: Level) {
+        let previous = self.pins.set(&self.label, level);
+        if let Some(trace) = &self.trace
+            && previous != Some(level)
+        {
+            trace.line(format_args!("pin {}: {}", self.label, level_name(level)));
+        }
+    }
+}
+
+pub fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Low => "low",
+        Level::High => "high",
+    }
+}
+
+pub struct Loopback;
+
+impl SpiDevice for Loopback {
+    fn transaction(&mut self, operations: &mut [Operation<'_>]) -> Result<(), BusError> {
+        for op in operations {
+            match op {
+                Operation::Read(buf) => buf.fill(0),
+                Operation::Transfer(read, write) => read.copy_from_slice(write),
+                Operation::Write(_) | Operation::DelayNs(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+// Prints each transaction once the device has answered it
+struct Traced {
+    name: String,
+    device: Box<dyn SpiDevice>,
+    trace: Trace,
+}
+
+impl SpiDevice for Traced {
+    fn transaction(&mut self, operations: &mut [Operation<'_>]) -> Result<(), BusError> {
+        let result = self.device.transaction(operations);
+        let (name, trace) = (&self.name, &self.trace);
+        for op in operations.iter() {
+            match op {
+                Operation::Read(buf) => {
+                    trace.line(format_args!("spi {name}: read -> {}", hex(buf)))
+                }
+                Operation::Write(data) => {
+                    trace.line(format_args!("spi {name}: write {}", hex(data)))
+                }
+                Operation::Transfer(read, write) => trace.line(format_args!(
+                    "spi {name}: transfer {} -> {}",
+                    hex(write),
+                    hex(read)
+                )),
+                Operation::DelayNs(ns) => trace.line(format_args!("spi {name}: delay {ns} ns")),
+            }
+        }
+        if result.is_err() {
+            trace.line(format_args!("spi {name}: failed"));
+        }
+        result
+    }
+
+    fn release(&mut self) {
+        self.device.release();
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    hex.join(" ")
+}
+
+/// The simulated devices, handed out to guests like the firmware's `Board`.
+pub struct Board {
+    spi: Vec<(String, Box<dyn SpiDevice>)>,
+    gpio: Vec<(String, SimPin, Option<Level>)>,
+    // What stays reachable for the event schedule once guests have their devices
+    pins: Pins,
+    sensors: Vec<(String, Readings)>,
+}
+
+/// What `Board::grant` handed out.
+pub struct Grant {
+    pub spi: Option<Box<dyn SpiDevice>>,
+    pub pins: Vec<(String, Box<dyn OutputPin>, Option<Level>)>,
+}
+
+impl Board {
+    pub fn new(
+        spi: &[SpiSpec],
+        gpio: &[PinSpec],
+        trace: Option<Trace>,
+        quota: Option<&FrameQuota>,
+    ) -> anyhow::Result<Self> {
+        let pins = Pins::default();
+        let mut board = Board {
+            spi: Vec::new(),
+            gpio: Vec::new(),
+            pins: pins.clone(),
+            sensors: Vec::new(),
+        };
+        for spec in gpio {
+            if pins.set(&spec.label, spec.level.into()).is_some() {
+                anyhow::bail!("pin {} is listed twice", spec.label);
+            }
+            let pin = SimPin {
+                label: spec.label.clone(),
+                pins: pins.clone(),
+                trace: trace.clone(),
+            };
+            board
+                .gpio
+                .push((spec.label.clone(), pin, spec.safe.map(Into::into)));
+        }
+        for spec in spi {
+            let name = spec.name().to_string();
+            if board.spi.iter().any(|(device, _)| *device == name) {
+                anyhow::bail!("SPI device {name} is listed twice");
+            }
+            let mut device: Box<dyn SpiDevice> = match spec {
+                SpiSpec::Loopback { .. } => Box::new(Loopback),
+                SpiSpec::Ssd1306(display) => {
+                    for label in [Some(&display.dc), display.res.as_ref()]
+                        .into_iter()
+                        .flatten()
+                    {
+                        if pins.level(label).is_none() {
+                            anyhow::bail!("{name}: pin {label} is not listed");
+                        }
+                    }
+                    let quota = quota.cloned();
+                    Box::new(Ssd1306::new(&name, display, pins.clone(), quota)?)
+                }
+                SpiSpec::Bme280(sensor) => {
+                    let sensor = Bme280::new(&name, sensor)?;
+                    board.sensors.push((name.clone(), sensor.readings()));
+                    Box::new(sensor)
+                }
+            };
+            if let Some(trace) = &trace {
+                device = Box::new(Traced {
+                    name: name.clone(),
+                    device,
+                    trace: trace.clone(),
+                });
+            }
+            board.spi.push((name, device));
+        }
+        let displays = spi
+            .iter()
+            .filter(|spec| matches!(spec, SpiSpec::Ssd1306(_)));
+        if quota.is_some() && displays.count() == 0 {
+            anyhow::bail!("--frames needs a display to count them on");
+        }
+        Ok(board)
+    }
+
+    pub fn pins(&self) -> &Pins {
+        &self.pins
+    }
+
+    pub fn sensor(&self, name: &str) -> Option<&Readings> {
+        let mut sensors = self.sensors.iter();
+        sensors
+            .find(|(sensor, _)| sensor == name)
+            .map(|(_, readings)| readings)
+    }
+
+    // All of the devices or none, so a refused guest takes nothing from the others
+    pub fn grant(&mut self, spi: &[String], gpio: &[String]) -> anyhow::Result<Grant> {
+        if spi.len() > 1 {
+            anyhow::bail!("a guest can only be given one SPI device");
+        }
+        for name in spi {
+            if !self.spi.iter().any(|(device, _)| device == name) {
+                anyhow::bail!("SPI device {name} is unknown or taken");
+            }
+        }
+        for (i, label) in gpio.iter().enumerate() {
+            if !self.gpio.iter().any(|(pin, ..)| pin == label) || gpio[..i].contains(label) {
+                anyhow::bail!("pin {label} is unknown or taken");
+            }
+        }
+
+        let spi = spi.first().map(|name| {
+            let index = self.spi.iter().position(|(device, _)| device == name);
+            self.spi.swap_remove(index.unwrap()).1
+        });
+        let pins = gpio
+            .iter()
+            .map(|label| {
+                let index = self.gpio.iter().position(|(pin, ..)| pin == label);
+                let (label, pin, safe) = self.gpio.swap_remove(index.unwrap());
+                (label, Box::new(pin) as Box<dyn OutputPin>, safe)
+            })
+            .collect();
+        Ok(Grant { spi, pins })
+    }
+}
@@ -0,0 +1,96 @@
+//! Per-guest host state, as in pico2-quick but with simulated devices.
+
+use std::collections::BTreeMap;
+
+use delay::{DelayCtx, DelayView, VirtualClock};
+use diagnostics::{DiagnosticsCtx, DiagnosticsView};
+use gpio::{GpioCtx, GpioView};
+use logging::{Level as LogLevel, LoggingCtx, LoggingView, StdoutSink};
+use spi::{SpiCtx, SpiView};
+use supervisor::{GuestLimiter, GuestLimits};
+
+use crate::HEAP;
+use crate::devices::Grant;
+
+pub const LOG_RETENTION: usize = 32;
+
+pub struct HostState {
+    pub spi_ctx: SpiCtx,
+    pub gpio_ctx: GpioCtx,
+    pub delay_ctx: DelayCtx,
+    pub logging_ctx: LoggingCtx,
+    pub limiter: GuestLimiter,
+    pub diagnostics_ctx: DiagnosticsCtx,
+}
+
+impl HostState {
+    pub fn new(
+        name: &str,
+        grant: Grant,
+        limits: GuestLimits,
+        clock: Option<&VirtualClock>,
+    ) -> Self {
+        let mut gpio_ctx = GpioCtx {
+            pins: BTreeMap::new(),
+            safe_levels: BTreeMap::new(),
+        };
+        for (label, pin, safe) in grant.pins {
+            if let Some(level) = safe {
+                gpio_ctx.safe_levels.insert(label.clone(), level);
+            }
+            gpio_ctx.pins.insert(label, pin);
+        }
+        Self {
+            spi_ctx: SpiCtx::new(grant.spi),
+            gpio_ctx,
+            delay_ctx: clock
+                .map_or_else(DelayCtx::new, |clock| DelayCtx::with_clock(clock.clone())),
+            // Unlike defmt over RTT, stdout keeps up, so there is no rate limit
+            logging_ctx: LoggingCtx::new(name, StdoutSink)
+                .with_min_level(LogLevel::Debug)
+                .with_dedup()
+                .with_retention(LOG_RETENTION),
+            limiter: GuestLimiter::new(limits),
+            diagnostics_ctx: DiagnosticsCtx::new(&HEAP),
+        }
+    }
+
+    // Puts the devices back in a known state before the guest is instantiated again
+    pub fn reset(&mut self) {
+        self.spi_ctx.reset();
+        self.gpio_ctx.reset();
+        self.logging_ctx.flush();
+    }
+}
+
+supervisor::impl_resource_limiter!(HostState);
+
+impl SpiView for HostState {
+    fn spi_ctx(&mut self) -> &mut SpiCtx {
+        &mut self.spi_ctx
+    }
+}
+
+impl GpioView for HostState {
+    fn gpio_ctx(&mut self) -> &mut GpioCtx {
+        &mut self.gpio_ctx
+    }
+}
+
+impl DelayView for HostState {
+    fn delay_ctx(&mut self) -> &mut DelayCtx {
+        &mut self.delay_ctx
+    }
+}
+
+impl LoggingView for HostState {
+    fn logging_ctx(&mut self) -> &mut LoggingCtx {
+        &mut self.logging_ctx
+    }
+}
+
+impl DiagnosticsView for HostState {
+    fn diagnostics_ctx(&mut self) -> &mut DiagnosticsCtx {
+        &mut self.diagnostics_ctx
+    }
+}
@@ -0,0 +1,59 @@
+//! Reads a guest from disk, in whichever form the toolchain left it.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, bail};
+use engine_config::EngineSettings;
+use wasmtime::Engine;
+use wasmtime::component::Component;
+
+/// Accepts:
+/// - a component, binary or text;
+/// - a core module built with wit-bindgen, componentized like `compiler compile` does
+///   (adapters and plugs need the compiler, run its output instead);
+/// - a guest image or bare `precompile_component` output for `settings`, i.e.
+///   `compiler compile --target pulley64`, compressed or not. Signatures
+///   are not checked, the simulator runs whatever it is pointed at.
+pub fn load(engine: &Engine, settings: &EngineSettings, path: &Path) -> anyhow::Result<Component> {
+    let bytes = fs::read(path).with_context(|| path.display().to_string())?;
+    let component = if bytes.starts_with(&guest_image::MAGIC) {
+        let (header, payload) = guest_image::read_image(&bytes)
+            .map_err(|err| anyhow::anyhow!("{}: {err}", path.display()))?;
+        if header.check_engine(settings.fingerprint()).is_err() {
+            bail!(
+                "{}: built for another engine, the simulator runs images from \
+                 `compiler compile --target {}`",
+                path.display(),
+                settings.target
+            );
+        }
+        deserialize(engine, payload)
+    } else if Engine::detect_precompiled(&bytes).is_some() {
+        deserialize(engine, &bytes)
+    } else {
+        let wasm = wat::parse_bytes(&bytes).with_context(|| path.display().to_string())?;
+        let wasm = if wasmparser::Parser::is_core_wasm(&wasm) {
+            wit_component::ComponentEncoder::default()
+                .module(&wasm)
+                .and_then(|encoder| encoder.validate(true).encode())
+                .context("componentizing the core module")?
+        } else {
+            wasm.into_owned()
+        };
+        Component::new(engine, wasm)
+    };
+    component.with_context(|| path.display().to_string())
+}
+
+fn deserialize(engine: &Engine, bytes: &[u8]) -> wasmtime::Result<Component> {
+    let inflated;
+    let bytes = if guest_compress::is_compressed(bytes) {
+        inflated = guest_compress::decompress(bytes, usize::MAX)?;
+        &inflated[..]
+    } else {
+        bytes
+    };
+    // Safety: only as trustworthy as the file, which is the developer's own build
+    unsafe { Component::deserialize(engine, bytes) }
+}
@@ -0,0 +1,308 @@
+use std::alloc::System;
+use std::path::PathBuf;
+use std::pin::pin;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::Parser;
+use delay::VirtualClock;
+use diagnostics::TrackingHeap;
+use engine_config::{EngineSettings, PICO2};
+use futures::FutureExt;
+use futures::future::{self, Either};
+use logging::StdoutSink;
+use supervisor::{
+    FuelBudget, GUEST_BUDGET, GUEST_LIMITS, GUEST_MAX_QUIET_SLICES, GUEST_RESTART_POLICY,
+    GuestLimits, HostCalls, Outcome, RestartDecision, RestartTracker, TrapReport, Verdict,
+    Watchdog, find_entry_point_in, supervise,
+};
+use wasmtime::component::{Component, ComponentExportIndex, Linker};
+use wasmtime::{Engine, Store};
+
+use config::GuestSpec;
+use devices::{Board, FrameQuota, Trace};
+use host::{HostState, LOG_RETENTION};
+use schedule::Schedule;
+
+mod bme280;
+mod config;
+mod devices;
+mod host;
+mod load;
+mod schedule;
+mod ssd1306;
+
+// Backs the guests' `my:diagnostics/heap`, for the whole process rather than a fixed heap
+#[global_allocator]
+static HEAP: TrackingHeap<System> = TrackingHeap::new(System);
+
+/// Runs Pico 2 guests on this machine, with simulated devices in place of the board's.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// TOML file listing the guests and the simulated devices they are given.
+    config: PathBuf,
+    /// Stop after this many milliseconds [default: once every guest has
+    /// finished or been given up on].
+    #[arg(long, value_name = "MS")]
+    run_for: Option<u64>,
+    /// Stop once every simulated display has rendered this many frames.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    frames: Option<u32>,
+    /// Skip through delays instead of waiting them out: time only passes
+    /// while every guest sleeps, and `--run-for` and events count it. A run
+    /// then goes the same way every time, however fast this machine is.
+    #[arg(long)]
+    virtual_time: bool,
+    /// Print every SPI transaction and pin change, with the time they happened
+    /// at under `--virtual-time`.
+    #[arg(long)]
+    trace: bool,
+}
+
+// The engine the board runs, on the Pulley interpreter for this machine's pointer width
+const SIMULATOR: EngineSettings = EngineSettings {
+    target: "pulley64",
+    ..PICO2
+};
+
+const HOST_FIBER_STACK: usize = 1024 * 1024;
+
+// Set by any guest's trap, even if a restart then got it going
+static TRAPPED: AtomicBool = AtomicBool::new(false);
+
+// Exit codes besides 1 for a bad config or guest and clap's 2 for bad usage,
+// so scripts can tell a run that went wrong from one that never started
+const EXIT_TRAPPED: u8 = 3;
+const EXIT_PROTOCOL_ERRORS: u8 = 4;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if let Err(err) = run(&cli) {
+        eprintln!("error: {err:#}");
+        return ExitCode::FAILURE;
+    }
+    let errors = devices::protocol_errors();
+    if errors > 0 {
+        println!("{errors} device protocol errors");
+    }
+    if TRAPPED.load(Ordering::Relaxed) {
+        ExitCode::from(EXIT_TRAPPED)
+    } else if errors > 0 {
+        ExitCode::from(EXIT_PROTOCOL_ERRORS)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+// A guest ready to run
+struct Guest {
+    spec: GuestSpec,
+    component: Component,
+    entry: ComponentExportIndex,
+    budget: FuelBudget,
+    state: HostState,
+}
+
+fn run(cli: &Cli) -> anyhow::Result<()> {
+    let config = config::load(&cli.config)?;
+    let mut engine_config = SIMULATOR.config()?;
+    // Host calls run on the guest's fiber, and std's formatting in an unoptimized
+    // build needs more than the board's 32 KiB. Guests still get `max_wasm_stack`.
+    engine_config.async_stack_size(HOST_FIBER_STACK);
+    let engine = Engine::new(&engine_config)?;
+
+    let clock = cli.virtual_time.then(VirtualClock::new);
+    let quota = cli.frames.map(FrameQuota::new);
+    let trace = cli.trace.then(|| Trace::new(clock.as_ref()));
+    let mut board = Board::new(&config.spi, &config.pins, trace, quota.as_ref())?;
+    let schedule = Schedule::new(&config.events, &board)?;
+    let mut guests = Vec::with_capacity(config.guests.len());
+    for spec in config.guests {
+        let component = load::load(&engine, &SIMULATOR, &spec.component)?;
+        let entry = find_entry_point_in(&component, &spec.export)
+            .map_err(|err| anyhow::anyhow!("{}: {err}", spec.name))?;
+        let grant = board
+            .grant(&spec.spi, &spec.gpio)
+            .map_err(|err| err.context(spec.name.clone()))?;
+        // A guest's own limits can only tighten the host's, as with a bundle manifest
+        let limits = GuestLimits {
+            memory_bytes: spec.memory.map_or(GUEST_LIMITS.memory_bytes, |bytes| {
+                bytes.min(GUEST_LIMITS.memory_bytes)
+            }),
+            ..GUEST_LIMITS
+        };
+        let budget = FuelBudget {
+            slice: spec
+                .fuel
+                .map_or(GUEST_BUDGET.slice, |fuel| fuel.min(GUEST_BUDGET.slice)),
+        };
+        println!("[{}] Loaded {}", spec.name, spec.component.display());
+        let state = HostState::new(&spec.name, grant, limits, clock.as_ref());
+        guests.push(Guest {
+            spec,
+            component,
+            entry,
+            budget,
+            state,
+        });
+    }
+
+    let mut linker = Linker::new(&engine);
+    // The same interfaces as pico2-quick's linker, see its host-capabilities.txt
+    spi::add_to_linker(&mut linker)?;
+    gpio::add_to_linker(&mut linker)?;
+    delay::add_to_linker(&mut linker)?;
+    logging::add_to_linker(&mut linker)?;
+    diagnostics::add_to_linker(&mut linker)?;
+
+    // All guests on one thread, scheduled at fuel yields and delays like on the board
+    let all = future::join_all(
+        guests
+            .into_iter()
+            .map(|guest| supervise_guest(guest, &engine, &linker, clock.as_ref())),
+    );
+    let timeout = async {
+        match cli.run_for {
+            Some(ms) => {
+                sleep(clock.as_ref(), ms).await;
+                format!("Stopped after {ms} ms")
+            }
+            None => future::pending().await,
+        }
+    };
+    let frames = async {
+        match &quota {
+            Some(quota) => {
+                quota.met().await;
+                format!("Stopped after {} frames", quota.frames())
+            }
+            None => future::pending().await,
+        }
+    };
+    // Events are polled first, so one due as a guest wakes has happened by the time it runs
+    let stop = future::select_all([
+        schedule.run(clock.as_ref()).boxed_local(),
+        timeout.boxed_local(),
+        frames.boxed_local(),
+    ]);
+    // Guests still running are dropped mid-call, which Wasmtime unwinds
+    let all = pin!(all);
+    let stopped = match &clock {
+        Some(clock) => clock.block_on(future::select(stop, all)),
+        None => futures::executor::block_on(future::select(stop, all)),
+    };
+    if let Either::Left(((why, ..), _)) = stopped {
+        println!("{why}");
+    }
+    Ok(())
+}
+
+// Runs the guest under its restart policy until it finishes or is given up on
+async fn supervise_guest(
+    guest: Guest,
+    engine: &Engine,
+    linker: &Linker<HostState>,
+    clock: Option<&VirtualClock>,
+) {
+    let Guest {
+        spec,
+        component,
+        entry,
+        budget,
+        mut state,
+    } = guest;
+    let name = spec.name.as_str();
+    let mut restarts = RestartTracker::new(GUEST_RESTART_POLICY);
+    loop {
+        // A fresh store per run, so an interrupted instance is never reused
+        let mut store = Store::new(engine, state);
+        store.limiter(|state| state);
+        budget.apply(&mut store).unwrap();
+
+        let done = match run_guest(name, &mut store, &component, &entry, linker).await {
+            Outcome::Finished(()) => {
+                println!("[{name}] Guest finished.");
+                true
+            }
+            Outcome::Killed { slices } => {
+                println!("[{name}] Guest killed after {slices} slices");
+                true
+            }
+            Outcome::Restart { slices } => {
+                println!(
+                    "[{name}] Guest made no host calls for {GUEST_MAX_QUIET_SLICES} slices ({slices} total)"
+                );
+                false
+            }
+            Outcome::Trapped(trap) => {
+                TRAPPED.store(true, Ordering::Relaxed);
+                let report = TrapReport::new(&trap);
+                println!("[{name}] Guest trapped: {}", report.reason);
+                for (i, frame) in report.frames.iter().enumerate() {
+                    println!("  {i}: {frame}");
+                }
+                println!("[{name}] Last {LOG_RETENTION} guest log records:");
+                store.data_mut().logging_ctx.dump_recent(&mut StdoutSink);
+                false
+            }
+        };
+
+        state = store.into_data();
+        state.reset();
+        if done {
+            return;
+        }
+
+        match restarts.on_failure() {
+            RestartDecision::Restart { delay_ms } => {
+                println!(
+                    "[{name}] Restarting guest in {delay_ms} ms (restart {})",
+                    restarts.restarts()
+                );
+                sleep(clock, delay_ms).await;
+            }
+            RestartDecision::GiveUp => {
+                println!(
+                    "[{name}] Giving up on guest after {} restarts",
+                    restarts.restarts()
+                );
+                return;
+            }
+        }
+    }
+}
+
+// On the virtual clock if there is one, otherwise in real time
+async fn sleep(clock: Option<&VirtualClock>, ms: u64) {
+    match clock {
+        Some(clock) => clock.sleep(ms).await,
+        None => embassy_time::Timer::after_millis(ms).await,
+    }
+}
+
+// Instantiation failures (e.g. a trap in a start function) are reported like runtime traps
+async fn run_guest(
+    name: &str,
+    store: &mut Store<HostState>,
+    component: &Component,
+    entry: &ComponentExportIndex,
+    linker: &Linker<HostState>,
+) -> Outcome<()> {
+    let calls = HostCalls::install(store);
+    let watchdog = Watchdog::new(&calls, GUEST_MAX_QUIET_SLICES, Verdict::Restart);
+    let run = match linker.instantiate_async(&mut *store, component).await {
+        Ok(instance) => instance.get_typed_func::<(), ()>(&mut *store, entry),
+        Err(err) => return Outcome::Trapped(err),
+    };
+    let run = match run {
+        Ok(run) => run,
+        Err(err) => return Outcome::Trapped(err),
+    };
+    println!("[{name}] Starting guest...");
+    let call = async {
+        run.call_async(&mut *store, ()).await?;
+        run.post_return_async(&mut *store).await
+    };
+    supervise(call, &calls, watchdog).await
+}
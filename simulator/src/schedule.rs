@@ -0,0 +1,119 @@
+//! The config's `[[event]]`s: pin edges and sensor readings at set times.
+
+use delay::VirtualClock;
+use embassy_time::{Duration, Instant, Timer};
+use gpio::Level;
+
+use crate::bme280::{Raw, Readings};
+use crate::config::EventSpec;
+use crate::devices::{Board, Pins, level_name};
+
+enum Action {
+    Pin {
+        pins: Pins,
+        label: String,
+        level: Level,
+    },
+    Sensor {
+        name: String,
+        readings: Readings,
+        raw: Raw,
+    },
+}
+
+/// Events in the order they happen.
+pub struct Schedule(Vec<(u64, Action)>);
+
+impl Schedule {
+    // Readings are worked out here, so a bad event is refused before any guest runs
+    pub fn new(events: &[EventSpec], board: &Board) -> anyhow::Result<Self> {
+        let mut order: Vec<&EventSpec> = events.iter().collect();
+        order.sort_by_key(|event| event.at);
+        // What each sensor will read by then, as later events build on earlier ones
+        let mut raws: Vec<(&str, Raw)> = Vec::new();
+        let mut schedule = Vec::with_capacity(order.len());
+        for event in order {
+            let at = event.at;
+            let action = match (&event.pin, &event.sensor) {
+                (Some(label), None) => {
+                    let Some(level) = event.level else {
+                        anyhow::bail!("event at {at} ms: pin {label} needs a level");
+                    };
+                    if event.temperature.is_some()
+                        || event.humidity.is_some()
+                        || event.pressure.is_some()
+                        || event.raw.temperature.is_some()
+                        || event.raw.humidity.is_some()
+                        || event.raw.pressure.is_some()
+                    {
+                        anyhow::bail!("event at {at} ms: readings are for a sensor, not a pin");
+                    }
+                    if board.pins().level(label).is_none() {
+                        anyhow::bail!("event at {at} ms: pin {label} is not listed");
+                    }
+                    Action::Pin {
+                        pins: board.pins().clone(),
+                        label: label.clone(),
+                        level: level.into(),
+                    }
+                }
+                (None, Some(name)) => {
+                    if event.level.is_some() {
+                        anyhow::bail!("event at {at} ms: a level is for a pin, not a sensor");
+                    }
+                    let Some(readings) = board.sensor(name) else {
+                        anyhow::bail!("event at {at} ms: {name} is not a bme280");
+                    };
+                    let earlier = raws.iter().position(|(sensor, _)| sensor == name);
+                    let previous = match earlier {
+                        Some(i) => raws.swap_remove(i).1,
+                        None => readings.raw(),
+                    };
+                    let setting = event.setting();
+                    let raw = Raw::update(Some(previous), &setting, readings.calibration())
+                        .map_err(|err| err.context(format!("event at {at} ms")))?;
+                    raws.push((name, raw));
+                    Action::Sensor {
+                        name: name.clone(),
+                        readings: readings.clone(),
+                        raw,
+                    }
+                }
+                _ => anyhow::bail!("event at {at} ms: needs either a pin or a sensor"),
+            };
+            schedule.push((at, action));
+        }
+        Ok(Self(schedule))
+    }
+
+    /// Carries out each event when its time comes, on the virtual clock if
+    /// there is one. Never returns.
+    pub async fn run(self, clock: Option<&VirtualClock>) -> String {
+        let start = Instant::now();
+        for (at, action) in self.0 {
+            match clock {
+                Some(clock) => clock.sleep_until(at).await,
+                None => Timer::at(start + Duration::from_millis(at)).await,
+            }
+            match action {
+                Action::Pin { pins, label, level } => {
+                    pins.set(&label, level);
+                    println!("At {at} ms: pin {label} {}", level_name(level));
+                }
+                Action::Sensor {
+                    name,
+                    readings,
+                    raw,
+                } => {
+                    readings.set(raw);
+                    println!(
+                        "At {at} ms: spi {name} reads temperature {:#07x}, \
+                         pressure {:#07x}, humidity {:#06x}",
+                        raw.temperature, raw.pressure, raw.humidity
+                    );
+                }
+            }
+        }
+        std::future::pending().await
+    }
+}
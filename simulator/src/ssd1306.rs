@@ -0,0 +1,423 @@
+//! The SSD1306 controller of a PmodOLED: a 128x32 panel on 4-wire SPI, with
+//! a data/command (D/C) pin telling command bytes from display data.
+//!
+//! Commands are decoded as the datasheet lists them. Those that only tune the
+//! panel's analogue side (contrast, clocks, precharge, COM pins) or scroll are
+//! accepted but not modelled. A frame is rendered after each transaction that
+//! changed what the panel shows, with the PmodOLED mounted the way its usual
+//! init sequence (segment remap 0xA1, COM scan 0xC8) shows upright.
+//!
+//! Flagged as protocol errors:
+//! - a command byte the SSD1306 does not know, usually data sent with D/C low;
+//! - a command cut short by data, i.e. its arguments sent with D/C high;
+//! - display data, or turning the display on, before the init sequence
+//!   enabled the charge pump;
+//! - any transaction while RES is held low.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use gpio::Level;
+use spi::{BusError, Operation, SpiDevice};
+
+use crate::config::{DisplaySpec, FrameFormat};
+use crate::devices::{FrameQuota, Pins, protocol_error};
+
+pub const WIDTH: usize = 128;
+pub const HEIGHT: usize = 32;
+// GDDRAM is 128x64 whatever the panel, in pages of 8 rows with a byte per column
+const PAGES: usize = 8;
+const RAM_ROWS: usize = PAGES * 8;
+
+#[derive(Clone, Copy)]
+enum Addressing {
+    Horizontal,
+    Vertical,
+    Page,
+}
+
+// The state a reset puts back, with the datasheet's reset values
+struct Registers {
+    addressing: Addressing,
+    columns: (u8, u8),
+    pages: (u8, u8),
+    column: u8,
+    page: u8,
+    start_line: u8,
+    offset: u8,
+    // Rows driven, 16..=64
+    multiplex: u8,
+    segment_remap: bool,
+    com_reversed: bool,
+    inverse: bool,
+    entire_on: bool,
+    display_on: bool,
+    charge_pump: bool,
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self {
+            addressing: Addressing::Page,
+            columns: (0, WIDTH as u8 - 1),
+            pages: (0, PAGES as u8 - 1),
+            column: 0,
+            page: 0,
+            start_line: 0,
+            offset: 0,
+            multiplex: RAM_ROWS as u8,
+            segment_remap: false,
+            com_reversed: false,
+            inverse: false,
+            entire_on: false,
+            display_on: false,
+            charge_pump: false,
+        }
+    }
+}
+
+/// What the panel shows, row by row from the top left.
+#[derive(Clone, PartialEq)]
+pub struct Frame(Vec<bool>);
+
+impl Frame {
+    fn blank() -> Self {
+        Frame(vec![false; WIDTH * HEIGHT])
+    }
+
+    /// Plain PBM, lit pixels as 1 (black on white, as viewers show them).
+    pub fn to_pbm(&self) -> String {
+        let mut pbm = format!("P1\n{WIDTH} {HEIGHT}\n");
+        for row in self.0.chunks(WIDTH) {
+            let row: Vec<&str> = row.iter().map(|&lit| if lit { "1" } else { "0" }).collect();
+            pbm.push_str(&row.join(" "));
+            pbm.push('\n');
+        }
+        pbm
+    }
+
+    /// Lit pixels as `#`, in a box.
+    pub fn to_ascii(&self) -> String {
+        let border = format!("+{}+\n", "-".repeat(WIDTH));
+        let mut ascii = border.clone();
+        for row in self.0.chunks(WIDTH) {
+            ascii.push('|');
+            ascii.extend(row.iter().map(|&lit| if lit { '#' } else { ' ' }));
+            ascii.push_str("|\n");
+        }
+        ascii.push_str(&border);
+        ascii
+    }
+
+    /// A 1-bit grayscale PNG, lit pixels white like on the panel.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut packed = vec![0u8; WIDTH / 8 * HEIGHT];
+        for (i, _) in self.0.iter().enumerate().filter(|(_, lit)| **lit) {
+            packed[i / 8] |= 0x80 >> (i % 8);
+        }
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, WIDTH as u32, HEIGHT as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::One);
+        // Writing to a Vec cannot fail
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&packed).unwrap();
+        writer.finish().unwrap();
+        png
+    }
+}
+
+// Where each frame goes
+struct Output {
+    dir: Option<PathBuf>,
+    format: FrameFormat,
+    ascii: bool,
+    // Frames past the quota are not output, so a run always shows the same ones
+    quota: Option<FrameQuota>,
+}
+
+pub struct Ssd1306 {
+    name: String,
+    dc: String,
+    res: Option<String>,
+    pins: Pins,
+    // Times RES had fallen when last looked at
+    resets: u32,
+    ram: [u8; WIDTH * PAGES],
+    registers: Registers,
+    // A command still waiting for arguments, with those it has so far
+    pending: Option<(u8, Vec<u8>)>,
+    frame: Frame,
+    frames: u32,
+    output: Output,
+}
+
+impl Ssd1306 {
+    pub fn new(
+        name: &str,
+        spec: &DisplaySpec,
+        pins: Pins,
+        quota: Option<FrameQuota>,
+    ) -> anyhow::Result<Self> {
+        if let Some(dir) = &spec.frames {
+            fs::create_dir_all(dir).with_context(|| dir.display().to_string())?;
+        }
+        if let Some(quota) = &quota {
+            quota.join();
+        }
+        Ok(Self {
+            name: name.to_string(),
+            dc: spec.dc.clone(),
+            res: spec.res.clone(),
+            resets: spec.res.as_ref().map_or(0, |res| pins.falls(res)),
+            pins,
+            ram: [0; WIDTH * PAGES],
+            registers: Registers::default(),
+            pending: None,
+            frame: Frame::blank(),
+            frames: 0,
+            output: Output {
+                dir: spec.frames.clone(),
+                format: spec.format,
+                ascii: spec.ascii,
+                quota,
+            },
+        })
+    }
+
+    fn error(&self, msg: &str) {
+        protocol_error(&self.name, msg);
+    }
+
+    // A pulse on RES since the last transaction resets the controller; GDDRAM keeps its contents
+    fn check_reset(&mut self) -> bool {
+        let Some(res) = &self.res else {
+            return true;
+        };
+        let falls = self.pins.falls(res);
+        if falls != self.resets {
+            self.resets = falls;
+            self.registers = Registers::default();
+            self.pending = None;
+        }
+        self.pins.level(res) != Some(Level::Low)
+    }
+
+    fn receive(&mut self, bytes: &[u8], data: bool) {
+        if data {
+            if let Some((command, _)) = self.pending.take() {
+                self.error(&format!(
+                    "command {command:#04x} is missing arguments, sent with D/C high"
+                ));
+            }
+            if !bytes.is_empty() && !self.registers.charge_pump {
+                self.error("display data before the init sequence enabled the charge pump");
+            }
+            for &byte in bytes {
+                self.write_ram(byte);
+            }
+            return;
+        }
+        for &byte in bytes {
+            let (command, arguments) = match self.pending.take() {
+                Some((command, mut arguments)) => {
+                    arguments.push(byte);
+                    (command, arguments)
+                }
+                None => (byte, Vec::new()),
+            };
+            match argument_count(command) {
+                Some(count) if arguments.len() == count => self.execute(command, &arguments),
+                Some(_) => self.pending = Some((command, arguments)),
+                None => self.error(&format!(
+                    "unknown command {command:#04x}, was it data sent with D/C low?"
+                )),
+            }
+        }
+    }
+
+    fn execute(&mut self, command: u8, arguments: &[u8]) {
+        let registers = &mut self.registers;
+        match (command, arguments) {
+            (0x00..=0x0F, _) => registers.column = registers.column & 0x70 | command,
+            (0x10..=0x1F, _) => registers.column = (command & 0x07) << 4 | registers.column & 0x0F,
+            (0x20, &[mode]) => {
+                registers.addressing = match mode & 0x03 {
+                    0 => Addressing::Horizontal,
+                    1 => Addressing::Vertical,
+                    _ => Addressing::Page,
+                }
+            }
+            (0x21, &[start, end]) => {
+                registers.columns = (start & 0x7F, end & 0x7F);
+                registers.column = start & 0x7F;
+            }
+            (0x22, &[start, end]) => {
+                registers.pages = (start & 0x07, end & 0x07);
+                registers.page = start & 0x07;
+            }
+            (0x40..=0x7F, _) => registers.start_line = command & 0x3F,
+            (0x8D, &[pump]) => registers.charge_pump = pump & 0x04 != 0,
+            (0xA0 | 0xA1, _) => registers.segment_remap = command == 0xA1,
+            (0xA4 | 0xA5, _) => registers.entire_on = command == 0xA5,
+            (0xA6 | 0xA7, _) => registers.inverse = command == 0xA7,
+            (0xA8, &[ratio]) => registers.multiplex = (ratio & 0x3F).max(15) + 1,
+            (0xAE | 0xAF, _) => registers.display_on = command == 0xAF,
+            (0xB0..=0xB7, _) => registers.page = command & 0x07,
+            (0xC0 | 0xC8, _) => registers.com_reversed = command == 0xC8,
+            (0xD3, &[offset]) => registers.offset = offset & 0x3F,
+            // Contrast, timing, COM pins and scrolling: not modelled
+            _ => {}
+        }
+        if command == 0xAF && !self.registers.charge_pump {
+            self.error("display turned on before the init sequence enabled the charge pump");
+        }
+    }
+
+    // Stores a byte of display data and moves on as the addressing mode says
+    fn write_ram(&mut self, byte: u8) {
+        let registers = &mut self.registers;
+        self.ram[usize::from(registers.page) * WIDTH + usize::from(registers.column)] = byte;
+        let (first_column, last_column) = registers.columns;
+        let (first_page, last_page) = registers.pages;
+        match registers.addressing {
+            Addressing::Horizontal => {
+                if registers.column >= last_column {
+                    registers.column = first_column;
+                    registers.page = if registers.page >= last_page {
+                        first_page
+                    } else {
+                        registers.page + 1
+                    };
+                } else {
+                    registers.column += 1;
+                }
+            }
+            Addressing::Vertical => {
+                if registers.page >= last_page {
+                    registers.page = first_page;
+                    registers.column = if registers.column >= last_column {
+                        first_column
+                    } else {
+                        registers.column + 1
+                    };
+                } else {
+                    registers.page += 1;
+                }
+            }
+            // Wraps within the page
+            Addressing::Page => registers.column = (registers.column + 1) % WIDTH as u8,
+        }
+    }
+
+    fn render(&self) -> Frame {
+        let registers = &self.registers;
+        let mut frame = Frame::blank();
+        if !registers.display_on {
+            return frame;
+        }
+        let multiplex = usize::from(registers.multiplex);
+        for y in 0..multiplex.min(HEIGHT) {
+            let com = if registers.com_reversed {
+                y
+            } else {
+                multiplex - 1 - y
+            };
+            let row = (com + usize::from(registers.start_line) + usize::from(registers.offset))
+                % RAM_ROWS;
+            for x in 0..WIDTH {
+                let column = if registers.segment_remap {
+                    x
+                } else {
+                    WIDTH - 1 - x
+                };
+                let lit = self.ram[row / 8 * WIDTH + column] >> (row % 8) & 1 == 1;
+                frame.0[y * WIDTH + x] = (lit || registers.entire_on) != registers.inverse;
+            }
+        }
+        frame
+    }
+
+    fn emit(&mut self) {
+        self.frames += 1;
+        if let Some(quota) = &self.output.quota {
+            if self.frames > quota.frames() {
+                return;
+            }
+            if self.frames == quota.frames() {
+                quota.reached();
+            }
+        }
+        if self.output.ascii {
+            print!(
+                "spi {}: frame {}\n{}",
+                self.name,
+                self.frames,
+                self.frame.to_ascii()
+            );
+        }
+        if let Some(dir) = &self.output.dir {
+            let (extension, bytes) = match self.output.format {
+                FrameFormat::Pbm => ("pbm", self.frame.to_pbm().into_bytes()),
+                FrameFormat::Png => ("png", self.frame.to_png()),
+            };
+            let path = dir.join(format!("{}-{:04}.{extension}", self.name, self.frames));
+            if let Err(err) = fs::write(&path, bytes) {
+                eprintln!("spi {}: {}: {err}", self.name, path.display());
+            }
+        }
+    }
+}
+
+impl SpiDevice for Ssd1306 {
+    fn transaction(&mut self, operations: &mut [Operation<'_>]) -> Result<(), BusError> {
+        if !self.check_reset() {
+            self.error("written while RES is held low");
+            return Ok(());
+        }
+        let data = self.pins.level(&self.dc) == Some(Level::High);
+        for op in operations {
+            match op {
+                Operation::Write(bytes) => self.receive(bytes, data),
+                Operation::Transfer(read, write) => {
+                    self.receive(write, data);
+                    read.fill(0);
+                }
+                // Nothing drives MISO on a PmodOLED
+                Operation::Read(buf) => buf.fill(0),
+                Operation::DelayNs(_) => {}
+            }
+        }
+        let frame = self.render();
+        if frame != self.frame {
+            self.frame = frame;
+            self.emit();
+        }
+        Ok(())
+    }
+}
+
+// Argument bytes that follow each command byte, None for bytes that are not commands
+fn argument_count(command: u8) -> Option<usize> {
+    Some(match command {
+        0x00..=0x1F
+        | 0x2E
+        | 0x2F
+        | 0x40..=0x7F
+        | 0xA0
+        | 0xA1
+        | 0xA4..=0xA7
+        | 0xAE
+        | 0xAF
+        | 0xB0..=0xB7
+        | 0xC0
+        | 0xC8
+        | 0xE3 => 0,
+        0x20 | 0x81 | 0x8D | 0xA8 | 0xD3 | 0xD5 | 0xD9 | 0xDA | 0xDB => 1,
+        0x21 | 0x22 | 0xA3 => 2,
+        0x29 | 0x2A => 5,
+        0x26 | 0x27 => 6,
+        _ => return None,
+    })
+}
@@ -0,0 +1,560 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::time::{Duration, Instant};
+
+use engine_config::{EngineSettings, PICO2};
+use wasmtime::Engine;
+use wit_component::StringEncoding;
+use wit_parser::Resolve;
+
+mod common;
+
+// Sets DC high, logs, and transfers three bytes over the SPI device it is given
+const DEVICE_GUEST: &str = r#"(module
+    (import "wasi:gpio/gpio" "set-pin-state" (func $set_pin (param i32 i32 i32)))
+    (import "my:debug/logging" "log" (func $log (param i32 i32)))
+    (import "wasi:spi/spi" "open-device" (func $open (param i32 i32 i32)))
+    (import "wasi:spi/spi" "[method]spi-device.transfer" (func $transfer (param i32 i32 i32 i32)))
+    (import "wasi:spi/spi" "[resource-drop]spi-device" (func $drop (param i32)))
+    (memory (export "memory") 1)
+    (global $heap (mut i32) (i32.const 1024))
+    (data (i32.const 16) "DC")
+    (data (i32.const 32) "hello from the guest")
+    (data (i32.const 64) "spi0")
+    (data (i32.const 80) "\01\02\03")
+    (func (export "cabi_realloc") (param i32 i32 i32 i32) (result i32)
+        (local $ptr i32)
+        (local.set $ptr
+            (i32.and
+                (i32.sub (i32.add (global.get $heap) (local.get 2)) (i32.const 1))
+                (i32.sub (i32.const 0) (local.get 2))))
+        (global.set $heap (i32.add (local.get $ptr) (local.get 3)))
+        (local.get $ptr))
+    (func (export "my:app/run#run")
+        (local $device i32)
+        (call $set_pin (i32.const 16) (i32.const 2) (i32.const 1))
+        (call $log (i32.const 32) (i32.const 20))
+        (call $open (i32.const 64) (i32.const 4) (i32.const 256))
+        (if (i32.load8_u (i32.const 256)) (then unreachable))
+        (local.set $device (i32.load (i32.const 260)))
+        (call $transfer (local.get $device) (i32.const 80) (i32.const 3) (i32.const 272))
+        (if (i32.load8_u (i32.const 272)) (then unreachable))
+        (call $drop (local.get $device)))
+)"#;
+
+// Puts the BME280 in normal mode, then reads the temperature once a minute
+const SAMPLING_GUEST: &str = r#"(module
+    (import "wasi:spi/spi" "open-device" (func $open (param i32 i32 i32)))
+    (import "wasi:spi/spi" "[method]spi-device.write" (func $write (param i32 i32 i32 i32)))
+    (import "wasi:spi/spi" "[method]spi-device.transfer" (func $transfer (param i32 i32 i32 i32)))
+    (import "wasi:delay/delay" "delay-ms" (func $delay (param i32)))
+    (memory (export "memory") 1)
+    (global $heap (mut i32) (i32.const 1024))
+    (data (i32.const 64) "spi0")
+    (data (i32.const 80) "\74\27")
+    (data (i32.const 96) "\fa\00\00\00")
+    (func (export "cabi_realloc") (param i32 i32 i32 i32) (result i32)
+        (local $ptr i32)
+        (local.set $ptr
+            (i32.and
+                (i32.sub (i32.add (global.get $heap) (local.get 2)) (i32.const 1))
+                (i32.sub (i32.const 0) (local.get 2))))
+        (global.set $heap (i32.add (local.get $ptr) (local.get 3)))
+        (local.get $ptr))
+    (func (export "my:app/run#run")
+        (local $device i32)
+        (call $open (i32.const 64) (i32.const 4) (i32.const 256))
+        (if (i32.load8_u (i32.const 256)) (then unreachable))
+        (local.set $device (i32.load (i32.const 260)))
+        (call $write (local.get $device) (i32.const 80) (i32.const 2) (i32.const 272))
+        (loop $sample
+            (call $transfer (local.get $device) (i32.const 96) (i32.const 4) (i32.const 272))
+            (call $delay (i32.const 60000))
+            (br $sample)))
+)"#;
+
+const GUEST_WORLD: &str = r#"
+package test:guest;
+
+world guest {
+    import wasi:gpio/gpio;
+    import my:debug/logging;
+    import wasi:spi/spi;
+    import wasi:delay/delay;
+    export my:app/run;
+}
+"#;
+
+const TRAPPING_GUEST: &str = r#"(component
+    (core module $m (func (export "run") unreachable))
+    (core instance $i (instantiate $m))
+    (func (export "run") (canon lift (core func $i "run")))
+)"#;
+
+const IDLE_GUEST: &str = r#"(component
+    (core module $m (func (export "run")))
+    (core instance $i (instantiate $m))
+    (func (export "run") (canon lift (core func $i "run")))
+)"#;
+
+const DEVICES: &str = r#"
+[[spi]]
+name = "spi0"
+model = "loopback"
+
+[[pin]]
+label = "DC"
+safe = "low"
+"#;
+
+fn simulator(args: &[&str], dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_simulator"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+// What cargo produces for a wit-bindgen guest: the core module with its
+// world embedded in a custom section
+fn guest_module(wat: &str) -> Vec<u8> {
+    let mut resolve = Resolve::default();
+    for wit in [
+        include_str!("../../wit/gpio.wit"),
+        include_str!("../../wit/debug.wit"),
+        include_str!("../../wit/delay.wit"),
+        include_str!("../../wit/spi.wit"),
+        include_str!("../../wit/app.wit"),
+    ] {
+        resolve.push_str("deps.wit", wit).unwrap();
+    }
+    let package = resolve.push_str("guest.wit", GUEST_WORLD).unwrap();
+    let world = resolve.select_world(&[package], Some("guest")).unwrap();
+    let mut module = wat::parse_str(wat).unwrap();
+    wit_component::embed_component_metadata(&mut module, &resolve, world, StringEncoding::UTF8)
+        .unwrap();
+    module
+}
+
+fn device_guest() -> Vec<u8> {
+    guest_module(DEVICE_GUEST)
+}
+
+// A call the scripted guest makes on its SPI device
+enum Step<'a> {
+    // Written with DC low
+    Command(&'a [u8]),
+    // Written with DC high
+    Data(&'a [u8]),
+    Write(&'a [u8]),
+    Transfer(&'a [u8]),
+}
+
+// Makes each call in turn on the device it is given, trapping if one fails
+fn spi_guest(steps: &[Step]) -> Vec<u8> {
+    let mut data = String::new();
+    let mut calls = String::new();
+    let mut offset = 1024;
+    for step in steps {
+        let (dc, method, bytes) = match *step {
+            Step::Command(bytes) => (Some(0), "$write", bytes),
+            Step::Data(bytes) => (Some(1), "$write", bytes),
+            Step::Write(bytes) => (None, "$write", bytes),
+            Step::Transfer(bytes) => (None, "$transfer", bytes),
+        };
+        let escaped: String = bytes.iter().map(|byte| format!("\\{byte:02x}")).collect();
+        data.push_str(&format!("(data (i32.const {offset}) \"{escaped}\")\n"));
+        if let Some(level) = dc {
+            calls.push_str(&format!(
+                "(call $set_pin (i32.const 16) (i32.const 2) (i32.const {level}))\n"
+            ));
+        }
+        calls.push_str(&format!(
+            "(call {method} (local.get $device) (i32.const {offset}) (i32.const {}) (i32.const 272))
+             (if (i32.load8_u (i32.const 272)) (then unreachable))\n",
+            bytes.len()
+        ));
+        offset += bytes.len();
+    }
+    guest_module(&format!(
+        r#"(module
+    (import "wasi:gpio/gpio" "set-pin-state" (func $set_pin (param i32 i32 i32)))
+    (import "wasi:spi/spi" "open-device" (func $open (param i32 i32 i32)))
+    (import "wasi:spi/spi" "[method]spi-device.write" (func $write (param i32 i32 i32 i32)))
+    (import "wasi:spi/spi" "[method]spi-device.transfer" (func $transfer (param i32 i32 i32 i32)))
+    (import "wasi:spi/spi" "[resource-drop]spi-device" (func $drop (param i32)))
+    (memory (export "memory") 1)
+    (global $heap (mut i32) (i32.const 8192))
+    (data (i32.const 16) "DC")
+    (data (i32.const 64) "spi0")
+    {data}
+    (func (export "cabi_realloc") (param i32 i32 i32 i32) (result i32)
+        (local $ptr i32)
+        (local.set $ptr
+            (i32.and
+                (i32.sub (i32.add (global.get $heap) (local.get 2)) (i32.const 1))
+                (i32.sub (i32.const 0) (local.get 2))))
+        (global.set $heap (i32.add (local.get $ptr) (local.get 3)))
+        (local.get $ptr))
+    (func (export "my:app/run#run")
+        (local $device i32)
+        (call $open (i32.const 64) (i32.const 4) (i32.const 256))
+        (if (i32.load8_u (i32.const 256)) (then unreachable))
+        (local.set $device (i32.load (i32.const 260)))
+        {calls}
+        (call $drop (local.get $device)))
+)"#
+    ))
+}
+
+// Enough of pmod-oled-driver's init for the model: charge pump, upright, horizontal addressing
+const DISPLAY_INIT: &[u8] = &[0x8D, 0x14, 0xA1, 0xC8, 0x20, 0x00, 0xAF];
+
+fn display_config(guest: &str, display: &str) -> String {
+    format!(
+        "[[guest]]\nname = \"test\"\ncomponent = \"{guest}\"\nspi = [\"display\"]\n\
+         gpio = [\"DC\"]\n\n\
+         [[spi]]\nname = \"display\"\nmodel = \"ssd1306\"\ndc = \"DC\"\n{display}\n\n\
+         [[pin]]\nlabel = \"DC\"\n"
+    )
+}
+
+fn sensor_config(guest: &str, sensor: &str) -> String {
+    format!(
+        "[[guest]]\nname = \"test\"\ncomponent = \"{guest}\"\nspi = [\"sensor\"]\n\n\
+         [[spi]]\nname = \"sensor\"\nmodel = \"bme280\"\n{sensor}\n"
+    )
+}
+
+// What the sensor answered to each traced transfer starting with `register`, after the address byte
+fn replies(stdout: &str, register: u8) -> Vec<Vec<u8>> {
+    let prefix = format!("spi sensor: transfer {register:02x} ");
+    stdout
+        .lines()
+        // Under `--virtual-time`, after the time
+        .filter_map(|line| line.split_once(&prefix))
+        .map(|(_, line)| {
+            let (_, read) = line.split_once(" -> ").unwrap();
+            let bytes = read.split(' ').skip(1);
+            bytes
+                .map(|byte| u8::from_str_radix(byte, 16).unwrap())
+                .collect()
+        })
+        .collect()
+}
+
+fn config(guest: &str, extra: &str) -> String {
+    format!("[[guest]]\nname = \"test\"\ncomponent = \"{guest}\"\n{extra}\n{DEVICES}")
+}
+
+#[test]
+fn runs_a_guest_with_simulated_devices() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("guest.wasm"), device_guest()).unwrap();
+    let extra = "spi = [\"spi0\"]\ngpio = [\"DC\"]";
+    fs::write(dir.path().join("sim.toml"), config("guest.wasm", extra)).unwrap();
+
+    let out = simulator(&["sim.toml", "--trace"], dir.path());
+
+    assert!(out.status.success(), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("pin DC: high"), "{stdout}");
+    assert!(stdout.contains("[test] hello from the guest"), "{stdout}");
+    assert!(
+        stdout.contains("spi spi0: transfer 01 02 03 -> 01 02 03"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("[test] Guest finished."), "{stdout}");
+    // Put back to its safe level once the guest is done
+    assert!(stdout.contains("pin DC: low"), "{stdout}");
+}
+
+#[test]
+fn traps_are_reported_and_exit_with_3() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("guest.wat"), TRAPPING_GUEST).unwrap();
+    fs::write(dir.path().join("sim.toml"), config("guest.wat", "")).unwrap();
+
+    let out = simulator(&["sim.toml", "--run-for", "200"], dir.path());
+
+    assert_eq!(out.status.code(), Some(3), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("[test] Guest trapped: "), "{stdout}");
+    assert!(
+        stdout.contains("[test] Restarting guest in 500 ms"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("Stopped after 200 ms"), "{stdout}");
+}
+
+#[test]
+fn runs_precompiled_images() {
+    let dir = tempfile::tempdir().unwrap();
+    let settings = EngineSettings {
+        target: "pulley64",
+        ..PICO2
+    };
+    let engine = Engine::new(&settings.config().unwrap()).unwrap();
+    let wasm = wat::parse_str(IDLE_GUEST).unwrap();
+    let precompiled = engine.precompile_component(&wasm).unwrap();
+    let fingerprint = settings.fingerprint();
+    let compressed = guest_compress::compress(&precompiled);
+    let images = [
+        ("bare.pulley", precompiled.clone()),
+        (
+            "image.img",
+            guest_image::write_image(0, fingerprint, &precompiled),
+        ),
+        (
+            "compressed.img",
+            guest_image::write_image(0, fingerprint, &compressed),
+        ),
+    ];
+
+    for (name, bytes) in images {
+        fs::write(dir.path().join(name), bytes).unwrap();
+        fs::write(dir.path().join("sim.toml"), config(name, "")).unwrap();
+        let out = simulator(&["sim.toml"], dir.path());
+        assert!(out.status.success(), "{name}: {out:?}");
+    }
+}
+
+#[test]
+fn images_for_the_board_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let image = guest_image::write_image(0, PICO2.fingerprint(), b"not for this engine");
+    fs::write(dir.path().join("guest.img"), image).unwrap();
+    fs::write(dir.path().join("sim.toml"), config("guest.img", "")).unwrap();
+
+    let out = simulator(&["sim.toml"], dir.path());
+
+    assert_eq!(out.status.code(), Some(1), "{out:?}");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("--target pulley64"), "{stderr}");
+}
+
+#[test]
+fn unknown_or_shared_devices_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("guest.wat"), IDLE_GUEST).unwrap();
+    let cases = [
+        ("spi = [\"spi1\"]", "SPI device spi1 is unknown or taken"),
+        ("gpio = [\"DC\", \"DC\"]", "pin DC is unknown or taken"),
+        (
+            "[[event]]\nat = 5\npin = \"RES\"\nlevel = \"low\"",
+            "event at 5 ms: pin RES is not listed",
+        ),
+        (
+            "[[event]]\nat = 5\nsensor = \"spi0\"\ntemperature = 30.0",
+            "event at 5 ms: spi0 is not a bme280",
+        ),
+    ];
+
+    for (extra, error) in cases {
+        fs::write(dir.path().join("sim.toml"), config("guest.wat", extra)).unwrap();
+        let out = simulator(&["sim.toml"], dir.path());
+        assert_eq!(out.status.code(), Some(1), "{out:?}");
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(stderr.contains(error), "{stderr}");
+    }
+
+    // Two guests asking for the same device
+    let two = format!(
+        "{}\n[[guest]]\nname = \"other\"\ncomponent = \"guest.wat\"\nspi = [\"spi0\"]",
+        config("guest.wat", "spi = [\"spi0\"]")
+    );
+    fs::write(dir.path().join("sim.toml"), two).unwrap();
+    let out = simulator(&["sim.toml"], dir.path());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("other: SPI device spi0 is unknown or taken"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn display_frames_show_what_the_guest_drew() {
+    let dir = tempfile::tempdir().unwrap();
+    // An 8x8 block in the top left corner
+    let guest = spi_guest(&[Step::Command(DISPLAY_INIT), Step::Data(&[0xFF; 8])]);
+    fs::write(dir.path().join("guest.wasm"), guest).unwrap();
+
+    for (format, magic) in [("pbm", &b"P1\n128 32\n"[..]), ("png", &b"\x89PNG"[..])] {
+        let display = format!("frames = \"frames\"\nformat = \"{format}\"\nascii = true");
+        fs::write(
+            dir.path().join("sim.toml"),
+            display_config("guest.wasm", &display),
+        )
+        .unwrap();
+
+        let out = simulator(&["sim.toml"], dir.path());
+
+        assert!(out.status.success(), "{out:?}");
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        assert!(stdout.contains("spi display: frame 1\n"), "{stdout}");
+        assert!(!stdout.contains("frame 2"), "{stdout}");
+        let rows: Vec<&str> = stdout
+            .lines()
+            .filter(|line| line.starts_with('|'))
+            .collect();
+        assert_eq!(rows.len(), 32);
+        assert!(rows[..8].iter().all(|row| row.starts_with("|######## ")));
+        assert!(rows[8..].iter().all(|row| !row.contains('#')));
+
+        let frame = fs::read(dir.path().join(format!("frames/display-0001.{format}"))).unwrap();
+        assert!(frame.starts_with(magic));
+    }
+    let pbm = fs::read_to_string(dir.path().join("frames/display-0001.pbm")).unwrap();
+    let rows: Vec<&str> = pbm.lines().skip(2).collect();
+    assert!(rows[0].starts_with("1 1 1 1 1 1 1 1 0"), "{pbm}");
+    assert!(rows[8].split(' ').all(|pixel| pixel == "0"), "{pbm}");
+}
+
+#[test]
+fn display_protocol_errors_exit_with_4() {
+    let dir = tempfile::tempdir().unwrap();
+    let guest = spi_guest(&[
+        // Column address without its end column, then data
+        Step::Command(&[0x21, 0x00]),
+        Step::Data(&[0x01]),
+        Step::Command(&[0xFF]),
+        Step::Command(&[0xAF]),
+    ]);
+    fs::write(dir.path().join("guest.wasm"), guest).unwrap();
+    fs::write(
+        dir.path().join("sim.toml"),
+        display_config("guest.wasm", ""),
+    )
+    .unwrap();
+
+    let out = simulator(&["sim.toml"], dir.path());
+
+    assert_eq!(out.status.code(), Some(4), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    for error in [
+        "command 0x21 is missing arguments, sent with D/C high",
+        "display data before the init sequence enabled the charge pump",
+        "unknown command 0xff, was it data sent with D/C low?",
+        "display turned on before the init sequence enabled the charge pump",
+        "4 device protocol errors",
+    ] {
+        assert!(stdout.contains(error), "{stdout}");
+    }
+}
+
+// What the temperature-sensor guest logs over its first reading, two seconds before its second
+fn temperature_sensor_log(sensor: &str) -> String {
+    let dir = tempfile::tempdir().unwrap();
+    let image = common::compose("temperature_sensor", None, dir.path());
+    let config = sensor_config(&image.display().to_string(), sensor);
+    fs::write(dir.path().join("sim.toml"), config).unwrap();
+
+    let out = simulator(
+        &["sim.toml", "--virtual-time", "--run-for", "1000"],
+        dir.path(),
+    );
+
+    assert!(out.status.success(), "{out:?}");
+    String::from_utf8_lossy(&out.stdout).into_owned()
+}
+
+#[test]
+fn sensor_reads_as_configured() {
+    let stdout = temperature_sensor_log("temperature = 21.5\nhumidity = 40.0");
+
+    assert!(!stdout.contains("Unexpected chip ID"), "{stdout}");
+    assert!(stdout.contains("[test] Temperature: 21.50 C"), "{stdout}");
+    assert!(stdout.contains("[test] Humidity: 40.00 %RH"), "{stdout}");
+}
+
+#[test]
+fn sensor_raw_values_and_calibration() {
+    let sensor = "raw = { temperature = 519888, humidity = 30000 }\n\
+                  [spi.calibration]\ndig_h2 = 400\ndig_h4 = 0x123\ndig_h5 = 0x456";
+    let stdout = temperature_sensor_log(sensor);
+
+    // The datasheet's worked example, and humidity through the overridden H2, H4 and H5
+    assert!(stdout.contains("[test] Temperature: 25.08 C"), "{stdout}");
+    assert!(stdout.contains("[test] Humidity: 48.85 %RH"), "{stdout}");
+}
+
+#[test]
+fn sensor_protocol_errors_exit_with_4() {
+    let dir = tempfile::tempdir().unwrap();
+    // The chip ID is read-only, and a write needs a data byte
+    let guest = spi_guest(&[
+        Step::Transfer(&[0xFA, 0, 0, 0]),
+        Step::Write(&[0x50, 0x61]),
+        Step::Write(&[0x74]),
+    ]);
+    fs::write(dir.path().join("guest.wasm"), guest).unwrap();
+    fs::write(dir.path().join("sim.toml"), sensor_config("guest.wasm", "")).unwrap();
+
+    let out = simulator(&["sim.toml", "--trace"], dir.path());
+
+    assert_eq!(out.status.code(), Some(4), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    // Asleep with every measurement skipped until ctrl_meas is written
+    assert_eq!(replies(&stdout, 0xFA), [[0x80, 0x00, 0x00]]);
+    for error in [
+        "spi sensor: protocol error: write to read-only or reserved register 0xd0",
+        "spi sensor: protocol error: write to 0xf4 without a data byte",
+    ] {
+        assert!(stdout.contains(error), "{stdout}");
+    }
+}
+
+#[test]
+fn scripted_events_in_virtual_time() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("guest.wasm"), guest_module(SAMPLING_GUEST)).unwrap();
+    let config = "[[guest]]\nname = \"test\"\ncomponent = \"guest.wasm\"\nspi = [\"sensor\"]\n\n\
+                  [[spi]]\nname = \"sensor\"\nmodel = \"bme280\"\nraw = { temperature = 0x70000 }\n\n\
+                  [[pin]]\nlabel = \"RES\"\n\n\
+                  [[event]]\nat = 450000\npin = \"RES\"\nlevel = \"high\"\n\n\
+                  [[event]]\nat = 300000\nsensor = \"sensor\"\nraw = { temperature = 0x71234 }\n";
+    fs::write(dir.path().join("sim.toml"), config).unwrap();
+    let args = [
+        "sim.toml",
+        "--virtual-time",
+        "--run-for",
+        "600000",
+        "--trace",
+    ];
+
+    // Ten minutes of a guest sampling once a minute, skipped through
+    let started = Instant::now();
+    let out = simulator(&args, dir.path());
+
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(out.status.success(), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("At 450000 ms: pin RES high"), "{stdout}");
+    assert!(stdout.contains("Stopped after 600000 ms"), "{stdout}");
+    // The guest reads the new value at the event's time, the event coming first
+    for line in [
+        "At 240000 ms: spi sensor: transfer fa 00 00 00 -> 00 70 00 00",
+        "At 300000 ms: spi sensor reads temperature 0x71234",
+        "At 300000 ms: spi sensor: transfer fa 00 00 00 -> 00 71 23 40",
+        "At 540000 ms: spi sensor: transfer fa 00 00 00 -> 00 71 23 40",
+    ] {
+        assert!(stdout.contains(line), "{stdout}");
+    }
+    // A sample a minute, the stop at 10 minutes coming before that one
+    let samples = replies(&stdout, 0xFA);
+    assert_eq!(samples.len(), 10, "{stdout}");
+    assert!(
+        samples[..5]
+            .iter()
+            .all(|sample| sample == &[0x70, 0x00, 0x00])
+    );
+    assert!(
+        samples[5..]
+            .iter()
+            .all(|sample| sample == &[0x71, 0x23, 0x40])
+    );
+
+    let again = simulator(&args, dir.path());
+    assert_eq!(again.stdout, out.stdout);
+}
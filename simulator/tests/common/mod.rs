@@ -0,0 +1,70 @@
+//! Real guests for the tests, built for wasm32 and precompiled for the
+//! simulator the way `build.sh` does. Needs the wasm32-unknown-unknown target.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+fn workspace() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap()
+}
+
+fn target_dir() -> PathBuf {
+    env::var_os("CARGO_TARGET_DIR").map_or_else(|| workspace().join("target"), PathBuf::from)
+}
+
+fn cargo(args: &[&str]) {
+    let status = Command::new(env!("CARGO"))
+        .args(args)
+        .current_dir(workspace())
+        .status()
+        .unwrap();
+    assert!(status.success(), "cargo {}", args.join(" "));
+}
+
+// The guests and the compiler that composes them, built once for all tests
+fn build() {
+    static BUILT: OnceLock<()> = OnceLock::new();
+    BUILT.get_or_init(|| {
+        cargo(&[
+            "build",
+            "-p",
+            "pacman",
+            "-p",
+            "ball-screensaver",
+            "-p",
+            "pmod-oled-driver",
+            "-p",
+            "temperature-sensor",
+            "--target",
+            "wasm32-unknown-unknown",
+            "--release",
+        ]);
+        cargo(&["build", "-p", "compiler"]);
+    });
+}
+
+/// `app`, with `plug` plugged into its imports if given, precompiled into
+/// `dir` for the simulator. Returns the image's path.
+pub fn compose(app: &str, plug: Option<&str>, dir: &Path) -> PathBuf {
+    build();
+    let release = target_dir().join("wasm32-unknown-unknown/release");
+    let image = dir.join(format!("{app}.img"));
+    let mut compiler = Command::new(target_dir().join("debug/compiler"));
+    compiler
+        .arg("compile")
+        .arg(release.join(format!("{app}.wasm")));
+    if let Some(plug) = plug {
+        compiler
+            .arg("--plug")
+            .arg(release.join(format!("{plug}.wasm")));
+    }
+    let out = compiler
+        .args(["--target", "pulley64", "-o"])
+        .arg(&image)
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+    image
+}
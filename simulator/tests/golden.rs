@@ -0,0 +1,149 @@
+//! Golden-frame tests: the OLED guests, with pmod-oled-driver plugged in as
+//! `build.sh` does, run for a number of frames under `--virtual-time`, and
+//! what the display showed is compared with the PNGs in `tests/golden`.
+//!
+//! Needs the wasm32-unknown-unknown target. After an intended change to what
+//! a guest draws, rerun with `UPDATE_GOLDEN=1` and check in the new frames.
+//! A changed frame fails the test and leaves a diff image in
+//! `$CARGO_TARGET_TMPDIR/golden/<app>/` (`target/tmp` by default): red where
+//! a pixel went dark, green where one lit up.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use std::process::Command;
+
+mod common;
+
+const FRAMES: u32 = 32;
+const WIDTH: usize = 128;
+const HEIGHT: usize = 32;
+
+const DISPLAY: &str = r#"
+[[spi]]
+name = "display"
+model = "ssd1306"
+dc = "DC"
+res = "RES"
+frames = "frames"
+format = "png"
+
+[[pin]]
+label = "DC"
+
+[[pin]]
+label = "RES"
+
+[[pin]]
+label = "VBATC"
+
+[[pin]]
+label = "VDDC"
+"#;
+
+fn pixels(path: &Path) -> Vec<bool> {
+    let mut decoder = png::Decoder::new(File::open(path).unwrap());
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().unwrap();
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).unwrap();
+    assert_eq!((info.width, info.height), (WIDTH as u32, HEIGHT as u32));
+    buf[..info.buffer_size()].iter().map(|&v| v > 127).collect()
+}
+
+// Unchanged pixels dimmed, so the changed ones stand out
+fn write_diff(path: &Path, expected: &[bool], actual: &[bool]) {
+    let rgb: Vec<u8> = expected
+        .iter()
+        .zip(actual)
+        .flat_map(|pixel| match pixel {
+            (true, true) => [96, 96, 96],
+            (true, false) => [255, 0, 0],
+            (false, true) => [0, 255, 0],
+            (false, false) => [0, 0, 0],
+        })
+        .collect();
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let file = BufWriter::new(File::create(path).unwrap());
+    let mut encoder = png::Encoder::new(file, WIDTH as u32, HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&rgb).unwrap();
+}
+
+fn check_frames(app: &str) {
+    let dir = tempfile::tempdir().unwrap();
+    let image = common::compose(app, Some("pmod_oled_driver"), dir.path());
+    let config = format!(
+        "[[guest]]\nname = \"{app}\"\ncomponent = \"{}\"\nspi = [\"display\"]\n\
+         gpio = [\"DC\", \"RES\", \"VBATC\", \"VDDC\"]\n{DISPLAY}",
+        image.display()
+    );
+    fs::write(dir.path().join("sim.toml"), config).unwrap();
+
+    let frames = FRAMES.to_string();
+    let out = Command::new(env!("CARGO_BIN_EXE_simulator"))
+        .args(["sim.toml", "--virtual-time", "--frames", &frames])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+
+    assert!(out.status.success(), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains(&format!("Stopped after {FRAMES} frames")),
+        "{stdout}"
+    );
+
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(app);
+    let rendered = dir.path().join("frames");
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        let _ = fs::remove_dir_all(&golden);
+        fs::create_dir_all(&golden).unwrap();
+        for n in 1..=FRAMES {
+            let name = format!("display-{n:04}.png");
+            fs::copy(rendered.join(&name), golden.join(&name)).unwrap();
+        }
+        return;
+    }
+
+    let diffs = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("golden")
+        .join(app);
+    let mut changed = Vec::new();
+    for n in 1..=FRAMES {
+        let name = format!("display-{n:04}.png");
+        if !golden.join(&name).exists() {
+            changed.push(format!(
+                "{name}: no golden frame, rerun with UPDATE_GOLDEN=1"
+            ));
+            continue;
+        }
+        let expected = pixels(&golden.join(&name));
+        let actual = pixels(&rendered.join(&name));
+        if expected != actual {
+            let diff = diffs.join(&name);
+            write_diff(&diff, &expected, &actual);
+            changed.push(format!("{name}: see {}", diff.display()));
+        }
+    }
+    assert!(
+        changed.is_empty(),
+        "{app} drew different frames:\n{}",
+        changed.join("\n")
+    );
+}
+
+#[test]
+fn pacman_frames() {
+    check_frames("pacman");
+}
+
+#[test]
+fn ball_screensaver_frames() {
+    check_frames("ball_screensaver");
+}